@@ -0,0 +1,261 @@
+//! # ImportMap
+//!
+//! an ImportMap remaps module specifiers before they are passed to the ModuleCodeLoader
+//! it uses the format of the import maps spec (https://github.com/WICG/import-maps) with imports and scopes
+//!
+//! # Example
+//!
+//! ```no_run
+//! use spidermonkey_runtime::esimportmap::ImportMap;
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//!
+//! let import_map = ImportMap::from_json(r#"{
+//!     "imports": {
+//!         "lodash": "/node_modules/lodash-es/lodash.js",
+//!         "lodash/": "/node_modules/lodash-es/"
+//!     },
+//!     "scopes": {
+//!         "/legacy/": {"lodash": "/node_modules/lodash-3/lodash.js"}
+//!     }
+//! }"#).ok().expect("invalid import map");
+//! let rt = EsRuntimeBuilder::new().import_map(import_map).build();
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Deserialize, Default)]
+struct ImportMapJson {
+    #[serde(default)]
+    imports: HashMap<String, String>,
+    #[serde(default)]
+    scopes: HashMap<String, HashMap<String, String>>,
+}
+
+/// a map of specifiers (or specifier prefixes ending with a /) to the paths they resolve to
+#[derive(Default, Clone, Debug)]
+struct SpecifierMap {
+    // sorted by key, longest key first so the most specific prefix wins
+    entries: Vec<(String, String)>,
+}
+
+impl SpecifierMap {
+    fn new(map: HashMap<String, String>, base_dir: &str) -> Self {
+        let mut entries: Vec<(String, String)> = map
+            .into_iter()
+            .map(|(key, value)| {
+                (
+                    normalize_specifier(key.as_str(), base_dir),
+                    normalize_specifier(value.as_str(), base_dir),
+                )
+            })
+            .collect();
+        entries.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        SpecifierMap { entries }
+    }
+
+    fn resolve(&self, specifier: &str) -> Option<String> {
+        for (key, value) in &self.entries {
+            if key == specifier {
+                return Some(value.clone());
+            }
+            if key.ends_with('/') && value.ends_with('/') {
+                if let Some(rest) = specifier.strip_prefix(key.as_str()) {
+                    return Some(format!("{}{}", value, rest));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// an ImportMap which is used by the EsRuntime to remap module specifiers, see EsRuntimeBuilder::import_map()
+#[derive(Default, Clone, Debug)]
+pub struct ImportMap {
+    base_dir: String,
+    imports: SpecifierMap,
+    // sorted longest scope first
+    scopes: Vec<(String, SpecifierMap)>,
+}
+
+impl ImportMap {
+    /// parse an ImportMap from json, relative paths in the map are resolved against /
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        Self::from_json_with_base_dir(json, "/")
+    }
+
+    /// parse an ImportMap from json, relative paths in the map are resolved against base_dir
+    pub fn from_json_with_base_dir(json: &str, base_dir: &str) -> Result<Self, String> {
+        let parsed: ImportMapJson =
+            serde_json::from_str(json).map_err(|e| format!("invalid import map: {}", e))?;
+
+        let mut scopes: Vec<(String, SpecifierMap)> = parsed
+            .scopes
+            .into_iter()
+            .map(|(scope, map)| {
+                (
+                    normalize_specifier(scope.as_str(), base_dir),
+                    SpecifierMap::new(map, base_dir),
+                )
+            })
+            .collect();
+        scopes.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(ImportMap {
+            base_dir: base_dir.to_string(),
+            imports: SpecifierMap::new(parsed.imports, base_dir),
+            scopes,
+        })
+    }
+
+    /// load an ImportMap from a json file, relative paths in the map are resolved against the directory of the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("could not read import map {}: {}", path.display(), e))?;
+        let base_dir = path
+            .parent()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|| "/".to_string());
+        Self::from_json_with_base_dir(json.as_str(), base_dir.as_str())
+    }
+
+    /// resolve a specifier which is imported from the module at reference_path
+    /// this returns None if the specifier is not mapped, in that case it should be loaded as is
+    pub fn resolve(&self, specifier: &str, reference_path: &str) -> Option<String> {
+        // relative specifiers are matched by the path they point to
+        let normalized = if is_path_specifier(specifier) {
+            let ref_dir = Path::new(reference_path)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .filter(|p| p.starts_with('/'))
+                .unwrap_or_else(|| self.base_dir.clone());
+            normalize_specifier(specifier, ref_dir.as_str())
+        } else {
+            specifier.to_string()
+        };
+
+        for (scope, map) in &self.scopes {
+            let in_scope = if scope.ends_with('/') {
+                reference_path.starts_with(scope.as_str())
+            } else {
+                reference_path == scope
+            };
+            if in_scope {
+                if let Some(resolved) = map.resolve(normalized.as_str()) {
+                    return Some(resolved);
+                }
+            }
+        }
+
+        self.imports.resolve(normalized.as_str())
+    }
+}
+
+fn is_path_specifier(specifier: &str) -> bool {
+    specifier.starts_with('/') || specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+/// resolve ./ and ../ against base_dir and remove . and .. components, bare specifiers are returned as is
+fn normalize_specifier(specifier: &str, base_dir: &str) -> String {
+    if !is_path_specifier(specifier) {
+        return specifier.to_string();
+    }
+    let joined = Path::new(base_dir).join(specifier);
+    let mut ret = PathBuf::from("/");
+    for component in joined.components() {
+        match component {
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => {
+                ret.pop();
+            }
+            Component::Normal(part) => ret.push(part),
+        }
+    }
+    let mut normalized = ret.to_string_lossy().to_string();
+    // keep the trailing slash of prefixes
+    if specifier.ends_with('/') && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    normalized
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esimportmap::ImportMap;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use hirofa_utils::js_utils::Script;
+
+    #[test]
+    fn test_import_map_resolve() {
+        log::info!("test: test_import_map_resolve");
+        let import_map = ImportMap::from_json_with_base_dir(
+            r#"{
+                "imports": {
+                    "lodash": "./node_modules/lodash-es/lodash.js",
+                    "lodash/": "./node_modules/lodash-es/",
+                    "./lib/old.mjs": "./lib/new.mjs"
+                },
+                "scopes": {
+                    "/app/legacy/": {"lodash": "/app/node_modules/lodash-3/lodash.js"}
+                }
+            }"#,
+            "/app",
+        )
+        .expect("could not parse import map");
+
+        assert_eq!(
+            import_map.resolve("lodash", "/app/main.mjs"),
+            Some("/app/node_modules/lodash-es/lodash.js".to_string())
+        );
+        assert_eq!(
+            import_map.resolve("lodash/fp.js", "/app/main.mjs"),
+            Some("/app/node_modules/lodash-es/fp.js".to_string())
+        );
+        assert_eq!(
+            import_map.resolve("lodash", "/app/legacy/main.mjs"),
+            Some("/app/node_modules/lodash-3/lodash.js".to_string())
+        );
+        // not in the scope, so the top level imports are used
+        assert_eq!(
+            import_map.resolve("lodash/fp.js", "/app/legacy/main.mjs"),
+            Some("/app/node_modules/lodash-es/fp.js".to_string())
+        );
+        assert_eq!(
+            import_map.resolve("../lib/old.mjs", "/app/sub/main.mjs"),
+            Some("/app/lib/new.mjs".to_string())
+        );
+        assert_eq!(import_map.resolve("underscore", "/app/main.mjs"), None);
+        assert_eq!(import_map.resolve("./lib/other.mjs", "/app/main.mjs"), None);
+
+        assert!(ImportMap::from_json("{\"imports\": []}").is_err());
+    }
+
+    #[test]
+    fn test_import_map() {
+        log::info!("test: test_import_map");
+        let import_map = ImportMap::from_json(r#"{"imports": {"my_lib": "/libs/my_lib.mjs"}}"#)
+            .expect("could not parse import map");
+        let rt = EsRuntimeBuilder::new()
+            .import_map(import_map)
+            .module_code_loader(Box::new(|path: &str, _ref_path: &str| {
+                if path.eq("/libs/my_lib.mjs") {
+                    Some(Script::new(path, "export const answer = 42;"))
+                } else {
+                    None
+                }
+            }))
+            .build();
+        rt.load_module_sync(
+            "import {answer} from 'my_lib';\n\nglobalThis.answer = answer;",
+            "/test_import_map.mjs",
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("answer;", "test_import_map.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 42);
+    }
+}
@@ -0,0 +1,197 @@
+//! # EsModule
+//!
+//! an EsModule is a handle to a module which was loaded with EsRuntime::load_module_sync(), it can be used to
+//! access the exports of the module from rust
+//!
+//! # Example
+//!
+//! ```no_run
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+//!
+//! let rt = EsRuntimeBuilder::new().build();
+//! let module = rt.load_module_sync("export const name = 'my_plugin';\nexport function init(config) {return config.level * 2;}", "my_plugin.mes")
+//!     .ok()
+//!     .expect("module failed");
+//! for export_name in module.get_export_names() {
+//!     println!("my_plugin exports {}", export_name);
+//! }
+//! let name = module.get_export("name").ok().expect("no such export");
+//! assert_eq!(name.get_string(), "my_plugin");
+//! ```
+
+use crate::esruntimeinner::EsRuntimeInner;
+use crate::esvaluefacade::EsValueFacade;
+use crate::jsapi_utils;
+use crate::jsapi_utils::EsErrorInfo;
+use crate::spidermonkeyruntimewrapper;
+use crate::spidermonkeyruntimewrapper::SmRuntime;
+use mozjs::jsapi::JSContext;
+use mozjs::jsapi::JSObject;
+use mozjs::jsval::UndefinedValue;
+use std::sync::Arc;
+
+/// a handle to the namespace of a loaded module
+pub struct EsModule {
+    name: String,
+    cached_obj_id: usize,
+    rti_ref: Arc<EsRuntimeInner>,
+}
+
+impl EsModule {
+    /// create a new EsModule for the namespace object of a module, this should be called from the worker thread
+    pub(crate) fn new(cx: *mut JSContext, name: &str, namespace: *mut JSObject) -> Self {
+        let rti_ref = SmRuntime::clone_current_esrt_inner_arc();
+        let cached_obj_id = spidermonkeyruntimewrapper::register_cached_object(cx, namespace);
+        EsModule {
+            name: name.to_string(),
+            cached_obj_id,
+            rti_ref,
+        }
+    }
+
+    /// get the file name of the module
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// get the names of all exports of the module, this includes "default" if the module has a default export
+    pub fn get_export_names(&self) -> Vec<String> {
+        let cached_obj_id = self.cached_obj_id;
+        self.rti_ref.do_in_es_event_queue_sync(move |sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                let ns = spidermonkeyruntimewrapper::do_with_cached_object(cached_obj_id, |epr| {
+                    epr.get()
+                });
+                rooted!(in (cx) let ns_root = ns);
+                jsapi_utils::objects::get_js_obj_prop_names(cx, ns_root.handle())
+            })
+        })
+    }
+
+    /// get the value of an export as an EsValueFacade
+    pub fn get_export(&self, name: &str) -> Result<EsValueFacade, EsErrorInfo> {
+        let cached_obj_id = self.cached_obj_id;
+        let name = name.to_string();
+        self.rti_ref.do_in_es_event_queue_sync(move |sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                let ns = spidermonkeyruntimewrapper::do_with_cached_object(cached_obj_id, |epr| {
+                    epr.get()
+                });
+                rooted!(in (cx) let ns_root = ns);
+                rooted!(in (cx) let mut export_root = UndefinedValue());
+                jsapi_utils::objects::get_es_obj_prop_val(
+                    cx,
+                    ns_root.handle(),
+                    name.as_str(),
+                    export_root.handle_mut(),
+                )?;
+                EsValueFacade::try_new_v(cx, export_root.handle())
+            })
+        })
+    }
+
+    /// call an exported function and return its result as an EsValueFacade
+    pub fn call_export(
+        &self,
+        name: &str,
+        args: Vec<EsValueFacade>,
+    ) -> Result<EsValueFacade, EsErrorInfo> {
+        let cached_obj_id = self.cached_obj_id;
+        let name = name.to_string();
+        self.rti_ref.do_in_es_event_queue_sync(move |sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                let ns = spidermonkeyruntimewrapper::do_with_cached_object(cached_obj_id, |epr| {
+                    epr.get()
+                });
+                rooted!(in (cx) let ns_root = ns);
+                rooted!(in (cx) let mut func_root = UndefinedValue());
+                jsapi_utils::objects::get_es_obj_prop_val(
+                    cx,
+                    ns_root.handle(),
+                    name.as_str(),
+                    func_root.handle_mut(),
+                )?;
+                if !func_root.is_object()
+                    || !jsapi_utils::functions::object_is_function(func_root.to_object())
+                {
+                    return Err(EsErrorInfo::new(
+                        format!("export {} is not a function", name),
+                        "".to_string(),
+                        0,
+                        0,
+                    ));
+                }
+
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                spidermonkeyruntimewrapper::do_with_rooted_esvf_vec(cx, args, |hva| {
+                    jsapi_utils::functions::call_function_value2(
+                        cx,
+                        ns_root.handle(),
+                        func_root.handle(),
+                        hva,
+                        rval.handle_mut(),
+                    )
+                })?;
+                EsValueFacade::try_new_v(cx, rval.handle())
+            })
+        })
+    }
+}
+
+impl Drop for EsModule {
+    fn drop(&mut self) {
+        let cached_obj_id = self.cached_obj_id;
+        self.rti_ref.do_in_es_event_queue(move |_sm_rt| {
+            spidermonkeyruntimewrapper::remove_cached_object(cached_obj_id);
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntime::tests::init_test_runtime;
+    use crate::esvaluefacade::EsValueFacade;
+
+    #[test]
+    fn test_module_exports() {
+        log::info!("test: test_module_exports");
+        let rt = init_test_runtime();
+        let module = rt
+            .load_module_sync(
+                "export const answer = 42;\nexport function add(a, b) {return a + b;}\nexport default 'def';",
+                "test_module_exports.mes",
+            )
+            .expect("module failed");
+
+        assert_eq!(module.get_name(), "test_module_exports.mes");
+        let mut names = module.get_export_names();
+        names.sort();
+        assert_eq!(names, vec!["add", "answer", "default"]);
+
+        assert_eq!(
+            module
+                .get_export("answer")
+                .expect("could not get export")
+                .get_i32(),
+            42
+        );
+        assert_eq!(
+            module
+                .get_export("default")
+                .expect("could not get export")
+                .get_string(),
+            "def"
+        );
+
+        let res = module
+            .call_export(
+                "add",
+                vec![EsValueFacade::new_i32(1), EsValueFacade::new_i32(2)],
+            )
+            .expect("could not call export");
+        assert_eq!(res.get_i32(), 3);
+
+        assert!(module.call_export("answer", vec![]).is_err());
+    }
+}
@@ -0,0 +1,542 @@
+//! # Module loaders
+//!
+//! modules are loaded by a ModuleCodeLoader closure or by an AsyncModuleCodeLoader, an AsyncModuleCodeLoader
+//! returns a Future so it can be used for modules which live in a remote store, and it can tell why a module
+//! could not be loaded with a LoaderError
+//!
+//! # FileSystemModuleLoader
+//!
+//! the FileSystemModuleLoader loads modules from a root directory on the filesystem
+//!
+//! relative paths (./ and ../) are resolved against the path of the importing module, all other paths are
+//! resolved against the root directory, modules can never be loaded from outside the root directory
+//!
+//! when the runtime is built with EsRuntimeBuilder::watch_modules() modules are reloaded when their file changes
+//!
+//! # Example
+//!
+//! ```no_run
+//! use spidermonkey_runtime::esmoduleloader::FileSystemModuleLoader;
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use std::time::Duration;
+//!
+//! let loader = FileSystemModuleLoader::new("/opt/my_app/modules");
+//! let rt = EsRuntimeBuilder::new()
+//!     .async_module_code_loader(Box::new(loader))
+//!     .watch_modules(Duration::from_secs(1))
+//!     .build();
+//! rt.load_module_sync("import {util} from './util.mjs';\n\nutil();", "/opt/my_app/modules/main.mjs")
+//!     .ok()
+//!     .expect("module failed");
+//! ```
+
+use crate::esruntime::ModuleCodeLoader;
+use hirofa_utils::js_utils::Script;
+use log::trace;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+
+/// the reason a module could not be loaded
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoaderError {
+    /// the module does not exist
+    NotFound(String),
+    /// the module exists but may not be loaded
+    PermissionDenied(String),
+    /// the module could not be read
+    Io(String),
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderError::NotFound(msg) => write!(f, "not found: {}", msg),
+            LoaderError::PermissionDenied(msg) => write!(f, "permission denied: {}", msg),
+            LoaderError::Io(msg) => write!(f, "i/o error: {}", msg),
+        }
+    }
+}
+
+impl From<io::Error> for LoaderError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => LoaderError::NotFound(err.to_string()),
+            io::ErrorKind::PermissionDenied => LoaderError::PermissionDenied(err.to_string()),
+            _ => LoaderError::Io(err.to_string()),
+        }
+    }
+}
+
+/// the Future which is returned by an AsyncModuleCodeLoader
+pub type LoaderFuture = Pin<Box<dyn Future<Output = Result<Script, LoaderError>> + Send + 'static>>;
+
+/// an AsyncModuleCodeLoader is used to load the code of modules, see EsRuntimeBuilder::async_module_code_loader()
+///
+/// load_module() is called and the returned Future is awaited in a helper thread, never in the worker thread of the
+/// runtime, the Future should not depend on a specific executor (like a tokio runtime), use e.g. a
+/// futures::channel::oneshot to pass the result of a task running in such an executor
+///
+/// # Example
+///
+/// ```no_run
+/// use spidermonkey_runtime::esmoduleloader::{AsyncModuleCodeLoader, LoaderError, LoaderFuture};
+/// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+/// use hirofa_utils::js_utils::Script;
+///
+/// struct BlobStoreLoader {}
+///
+/// impl AsyncModuleCodeLoader for BlobStoreLoader {
+///     fn load_module(&self, path: &str, _reference_path: &str) -> LoaderFuture {
+///         let path = path.to_string();
+///         Box::pin(async move {
+///             if path.eq("/blobs/util.mjs") {
+///                 Ok(Script::new(path.as_str(), "export const util = 1;"))
+///             } else {
+///                 Err(LoaderError::NotFound(path))
+///             }
+///         })
+///     }
+/// }
+///
+/// let rt = EsRuntimeBuilder::new()
+///     .async_module_code_loader(Box::new(BlobStoreLoader {}))
+///     .build();
+/// ```
+pub trait AsyncModuleCodeLoader: Send + Sync + 'static {
+    /// load a module
+    /// path is the (relative) path of the module as used in the import statement
+    /// reference_path is the absolute path of the importing module
+    /// the Script which is returned should contain the absolute path of the module
+    fn load_module(&self, path: &str, reference_path: &str) -> LoaderFuture;
+
+    /// resolve the path of a module to the absolute path it would be loaded from, without loading it
+    /// this is used by import.meta.resolve() and as the key of the module in the module cache
+    ///
+    /// by default relative paths (./ and ../) are resolved against the directory of reference_path and all
+    /// other paths are returned as is
+    fn resolve_module(&self, path: &str, reference_path: &str) -> Result<String, LoaderError> {
+        Ok(resolve_relative_path(path, reference_path))
+    }
+}
+
+/// an AsyncModuleCodeLoader for a ModuleCodeLoader closure, a None result is reported as LoaderError::NotFound
+pub(crate) struct SyncModuleCodeLoader {
+    loader: Box<ModuleCodeLoader>,
+}
+
+impl SyncModuleCodeLoader {
+    pub(crate) fn new(loader: Box<ModuleCodeLoader>) -> Self {
+        SyncModuleCodeLoader { loader }
+    }
+}
+
+impl AsyncModuleCodeLoader for SyncModuleCodeLoader {
+    fn load_module(&self, path: &str, reference_path: &str) -> LoaderFuture {
+        let res = (self.loader)(path, reference_path)
+            .ok_or_else(|| LoaderError::NotFound(path.to_string()));
+        Box::pin(async move { res })
+    }
+}
+
+/// loads module code from files in a root directory
+pub struct FileSystemModuleLoader {
+    root: PathBuf,
+    extensions: Vec<String>,
+    index_file_name: String,
+}
+
+impl FileSystemModuleLoader {
+    /// create a new FileSystemModuleLoader for a root directory
+    /// by default the .mjs, .mes and .js extensions are probed and directories are resolved to their index file
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref();
+        // fall back to the path as is if it does not exist (yet), nothing will be loaded in that case
+        let root = fs::canonicalize(root).unwrap_or_else(|_| normalize(root));
+        FileSystemModuleLoader {
+            root,
+            extensions: vec![".mjs".to_string(), ".mes".to_string(), ".js".to_string()],
+            index_file_name: "index".to_string(),
+        }
+    }
+
+    /// set the extensions which are probed (in order) when a path does not point to an existing file
+    pub fn extensions(&mut self, extensions: &[&str]) -> &mut Self {
+        self.extensions = extensions.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    /// set the name (without extension) of the file which is loaded when a path points to a directory
+    pub fn index_file_name(&mut self, name: &str) -> &mut Self {
+        self.index_file_name = name.to_string();
+        self
+    }
+
+    /// get the root directory of this loader
+    pub fn get_root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// resolve the path of a module to the absolute path of an existing file within the root directory
+    /// path is the path as used in the import statement
+    /// reference_path is the absolute path of the importing module
+    /// a path which points outside of the root directory results in a LoaderError::PermissionDenied
+    pub fn resolve(&self, path: &str, reference_path: &str) -> Result<PathBuf, LoaderError> {
+        let not_found = || LoaderError::NotFound(path.to_string());
+        let candidate = if path.starts_with("./") || path.starts_with("../") {
+            let ref_path = Path::new(reference_path);
+            let ref_dir = if ref_path.is_absolute() {
+                ref_path.parent().unwrap_or(&self.root).to_path_buf()
+            } else {
+                // e.g. a script which was evaluated with a file name which is not a path
+                self.root
+                    .join(ref_path)
+                    .parent()
+                    .ok_or_else(not_found)?
+                    .to_path_buf()
+            };
+            ref_dir.join(path)
+        } else if Path::new(path).starts_with(&self.root) {
+            PathBuf::from(path)
+        } else {
+            self.root.join(path.trim_start_matches('/'))
+        };
+
+        let candidate = normalize(candidate.as_path());
+        if !candidate.starts_with(&self.root) {
+            trace!("FileSystemModuleLoader: {} escapes the root dir", path);
+            return Err(LoaderError::PermissionDenied(format!(
+                "{} is outside of the module root",
+                path
+            )));
+        }
+
+        let file = self.probe(candidate.as_path()).ok_or_else(not_found)?;
+
+        // symlinks may still point outside of the root dir
+        let file = fs::canonicalize(file).map_err(|_| not_found())?;
+        if file.starts_with(&self.root) {
+            Ok(file)
+        } else {
+            trace!(
+                "FileSystemModuleLoader: {} links outside the root dir",
+                path
+            );
+            Err(LoaderError::PermissionDenied(format!(
+                "{} links outside of the module root",
+                path
+            )))
+        }
+    }
+
+    /// load a module, this resolves the path and reads the file
+    pub fn load(&self, path: &str, reference_path: &str) -> Option<Script> {
+        match self.try_load(path, reference_path) {
+            Ok(script) => Some(script),
+            Err(LoaderError::NotFound(_)) => None,
+            Err(e) => {
+                log::error!("FileSystemModuleLoader: could not load {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// load a module like load() but return a LoaderError which tells why it could not be loaded
+    pub fn try_load(&self, path: &str, reference_path: &str) -> Result<Script, LoaderError> {
+        let file = self.resolve(path, reference_path)?;
+        trace!("FileSystemModuleLoader: loading {}", file.display());
+        let code = fs::read_to_string(&file).map_err(|e| {
+            LoaderError::from(io::Error::new(
+                e.kind(),
+                format!("{}: {}", file.display(), e),
+            ))
+        })?;
+        Ok(Script::new(file.to_string_lossy().as_ref(), code.as_str()))
+    }
+
+    /// convert this loader to a ModuleCodeLoader which can be passed to EsRuntimeBuilder::module_code_loader()
+    /// import.meta.resolve() can not check if a file exists in that case, pass the loader itself to
+    /// EsRuntimeBuilder::async_module_code_loader() instead if that is needed
+    pub fn into_module_code_loader(self) -> Box<ModuleCodeLoader> {
+        Box::new(move |path: &str, reference_path: &str| self.load(path, reference_path))
+    }
+
+    fn probe(&self, candidate: &Path) -> Option<PathBuf> {
+        if candidate.is_file() {
+            return Some(candidate.to_path_buf());
+        }
+        let file_name = candidate.file_name()?.to_string_lossy().to_string();
+        for ext in &self.extensions {
+            let with_ext = candidate.with_file_name(format!("{}{}", file_name, ext));
+            if with_ext.is_file() {
+                return Some(with_ext);
+            }
+        }
+        if candidate.is_dir() {
+            for ext in &self.extensions {
+                let index = candidate.join(format!("{}{}", self.index_file_name, ext));
+                if index.is_file() {
+                    return Some(index);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl AsyncModuleCodeLoader for FileSystemModuleLoader {
+    fn load_module(&self, path: &str, reference_path: &str) -> LoaderFuture {
+        let res = self.try_load(path, reference_path);
+        Box::pin(async move { res })
+    }
+
+    fn resolve_module(&self, path: &str, reference_path: &str) -> Result<String, LoaderError> {
+        self.resolve(path, reference_path)
+            .map(|file| file.to_string_lossy().to_string())
+    }
+}
+
+/// resolve a relative path (./ or ../) against the directory of reference_path, other paths are returned as is
+pub(crate) fn resolve_relative_path(path: &str, reference_path: &str) -> String {
+    if path.starts_with("./") || path.starts_with("../") {
+        let ref_dir = Path::new(reference_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        normalize(ref_dir.join(path).as_path())
+            .to_string_lossy()
+            .to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// remove . and .. components from a path without touching the filesystem
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                ret.pop();
+            }
+            other => ret.push(other.as_os_str()),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esmoduleloader::{
+        AsyncModuleCodeLoader, FileSystemModuleLoader, LoaderError, LoaderFuture,
+    };
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use hirofa_utils::js_utils::Script;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn init_test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("app/lib/utils")).unwrap();
+        fs::write(
+            dir.join("app/lib/math.mjs"),
+            "export const two = 2;\nexport {three} from './utils';",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("app/lib/utils/index.js"),
+            "export const three = 3;",
+        )
+        .unwrap();
+        fs::write(dir.join("app/main.mes"), "export const main = true;").unwrap();
+        fs::write(dir.join("secret.mjs"), "export const secret = 1;").unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        log::info!("test: test_resolve");
+        let dir = init_test_dir("es_module_loader_test_resolve");
+        let app = dir.join("app");
+        let loader = FileSystemModuleLoader::new(&app);
+        let main = app.join("main.mes");
+        let main_str = main.to_str().unwrap();
+
+        assert_eq!(
+            loader.resolve("./lib/math.mjs", main_str),
+            Ok(app.join("lib/math.mjs"))
+        );
+        assert_eq!(
+            loader.resolve("./lib/math", main_str),
+            Ok(app.join("lib/math.mjs"))
+        );
+        assert_eq!(
+            loader.resolve("./utils", app.join("lib/math.mjs").to_str().unwrap()),
+            Ok(app.join("lib/utils/index.js"))
+        );
+        assert_eq!(
+            loader.resolve("../main", app.join("lib/math.mjs").to_str().unwrap()),
+            Ok(main.clone())
+        );
+        assert_eq!(loader.resolve("main", "my_script.es"), Ok(main.clone()));
+        assert_eq!(
+            loader.resolve("/main.mes", "my_script.es"),
+            Ok(main.clone())
+        );
+        assert_eq!(loader.resolve(main_str, "my_script.es"), Ok(main.clone()));
+
+        // escaping the root dir
+        assert_eq!(
+            loader.resolve("../secret.mjs", main_str).err(),
+            Some(LoaderError::PermissionDenied(
+                "../secret.mjs is outside of the module root".to_string()
+            ))
+        );
+        assert_eq!(
+            loader.resolve("./lib/../../secret", main_str).err(),
+            Some(LoaderError::PermissionDenied(
+                "./lib/../../secret is outside of the module root".to_string()
+            ))
+        );
+        assert!(loader
+            .resolve(dir.join("secret.mjs").to_str().unwrap(), main_str)
+            .is_err());
+        assert_eq!(
+            loader.resolve("./nope.mjs", main_str).err(),
+            Some(LoaderError::NotFound("./nope.mjs".to_string()))
+        );
+
+        // resolving through the AsyncModuleCodeLoader trait does not load the module
+        assert_eq!(
+            loader.resolve_module("./lib/math", main_str),
+            Ok(app.join("lib/math.mjs").to_string_lossy().to_string())
+        );
+        assert_eq!(
+            loader.resolve_module("./nope.mjs", main_str),
+            Err(LoaderError::NotFound("./nope.mjs".to_string()))
+        );
+        // the default implementation only resolves relative paths
+        let blob_loader = TestBlobStoreLoader {};
+        assert_eq!(
+            blob_loader.resolve_module("../util.mes", "/blobs/lib/main.mes"),
+            Ok("/blobs/util.mes".to_string())
+        );
+        assert_eq!(
+            blob_loader.resolve_module("util.mes", "/blobs/lib/main.mes"),
+            Ok("util.mes".to_string())
+        );
+    }
+
+    #[test]
+    fn test_file_system_module_loader() {
+        log::info!("test: test_file_system_module_loader");
+        let dir = init_test_dir("es_module_loader_test_load");
+        let app = dir.join("app");
+        let loader = FileSystemModuleLoader::new(&app);
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(loader.into_module_code_loader())
+            .build();
+        rt.load_module_sync(
+            "import {two, three} from './lib/math.mjs';\n\nglobalThis.five = two + three;",
+            app.join("test.mjs").to_str().unwrap(),
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("five;", "test_file_system_module_loader.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 5);
+    }
+
+    struct TestBlobStoreLoader {}
+
+    impl AsyncModuleCodeLoader for TestBlobStoreLoader {
+        fn load_module(&self, path: &str, _reference_path: &str) -> LoaderFuture {
+            // emulate a remote store which answers from another thread
+            let (tx, rx) = futures::channel::oneshot::channel();
+            let path = path.to_string();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(10));
+                let res = match path.as_str() {
+                    "./util.mes" => Ok(Script::new("/blobs/util.mes", "export const util = 3;")),
+                    "./secret.mes" => Err(LoaderError::PermissionDenied(path.clone())),
+                    "./broken.mes" => Err(LoaderError::Io(format!("{}: connection reset", path))),
+                    _ => Err(LoaderError::NotFound(path.clone())),
+                };
+                let _ = tx.send(res);
+            });
+            Box::pin(async move {
+                rx.await
+                    .unwrap_or_else(|_| Err(LoaderError::Io("store went away".to_string())))
+            })
+        }
+    }
+
+    #[test]
+    fn test_async_module_code_loader() {
+        log::info!("test: test_async_module_code_loader");
+        let rt = EsRuntimeBuilder::new()
+            .async_module_code_loader(Box::new(TestBlobStoreLoader {}))
+            .build();
+        let module = rt
+            .load_module_sync(
+                "import {util} from './util.mes';\nexport const res = util * 2;",
+                "/blobs/main.mes",
+            )
+            .expect("module failed");
+        assert_eq!(
+            module
+                .get_export("res")
+                .expect("could not get export")
+                .get_i32(),
+            6
+        );
+
+        let err = rt
+            .load_module_sync(
+                "import {a} from './secret.mes';\nimport {b} from './broken.mes';\nimport {c} from './nope.mes';",
+                "/blobs/failing.mes",
+            )
+            .err()
+            .expect("module should fail");
+        let msg = err.err_msg();
+        assert!(msg.contains("permission denied: ./secret.mes"));
+        assert!(msg.contains("i/o error: ./broken.mes: connection reset"));
+        assert!(msg.contains("not found: ./nope.mes"));
+
+        // dynamic imports report the reason too
+        let prom_esvf = rt
+            .eval_sync(
+                "import('./secret.mes').then(() => 'imported', (err) => '' + err);",
+                "/blobs/test_async_module_code_loader.es",
+            )
+            .expect("script failed");
+        let prom_res = prom_esvf
+            .get_promise_result_blocking(Duration::from_secs(10))
+            .expect("import timed out");
+        match prom_res {
+            Ok(esvf) => assert!(esvf.get_string().contains("permission denied")),
+            Err(err) => panic!("import promise was rejected: {}", err.get_string()),
+        }
+    }
+
+    #[test]
+    fn test_try_load() {
+        log::info!("test: test_try_load");
+        let dir = init_test_dir("es_module_loader_test_try_load");
+        let loader = FileSystemModuleLoader::new(dir.join("app"));
+        assert!(loader.try_load("./main.mes", "/").is_ok());
+        assert_eq!(
+            loader
+                .try_load("../secret.mjs", dir.join("app/main.mes").to_str().unwrap())
+                .err(),
+            Some(LoaderError::PermissionDenied(
+                "../secret.mjs is outside of the module root".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! # EsNativeModule
+//!
+//! the EsNativeModule struct provides a way to define a module in rust which can be imported from script
+//!
+//! the exports of a native module can be values, functions and EsProxy classes, the module is
+//! resolved by its name before the ModuleCodeLoader is consulted, both for static and dynamic imports
+//!
+//! # Example
+//!
+//! ```no_run
+//! use spidermonkey_runtime::esnativemodule::EsNativeModule;
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+//!
+//! let rt = EsRuntimeBuilder::new().build();
+//!
+//! EsNativeModule::builder("esses.com.http")
+//!     .value("version", EsValueFacade::new_str("1.0".to_string()))
+//!     .sync_function("get", |args| {
+//!         Ok(EsValueFacade::new_str(format!("got {}", args[0].get_string())))
+//!     })
+//!     .async_function("fetch", |_args| {
+//!         Ok(EsValueFacade::new_i32(200))
+//!     })
+//!     .build(&rt);
+//!
+//! rt.load_module_sync("import {get, version} from 'esses.com.http';\n\nconsole.log(get('/index.html'));", "native_module_example.mes")
+//!     .ok()
+//!     .expect("module failed");
+//! rt.eval_sync("import('esses.com.http').then((http) => {return http.fetch('/index.html');});", "native_module_example.es")
+//!     .ok()
+//!     .expect("script failed");
+//! ```
+
+use crate::esreflection::EsProxy;
+use crate::esruntime::EsRuntime;
+use crate::esvaluefacade::EsValueFacade;
+use crate::jsapi_utils;
+use crate::jsapi_utils::handles::from_raw_handle_mut;
+use crate::jsapi_utils::objects::NULL_JSOBJECT;
+use crate::jsapi_utils::report_exception2;
+use crate::jsapi_utils::rooting::EsPersistentRooted;
+use hirofa_utils::js_utils::Script;
+use log::trace;
+use mozjs::jsapi::CallArgs;
+use mozjs::jsapi::GetFunctionNativeReserved;
+use mozjs::jsapi::JSContext;
+use mozjs::jsapi::JS_GetFunctionObject;
+use mozjs::jsapi::NewFunctionWithReserved;
+use mozjs::jsapi::SetFunctionNativeReserved;
+use mozjs::jsval::{Int32Value, ObjectValue, UndefinedValue};
+use mozjs::panic::wrap_panic;
+use mozjs::rust::{HandleObject, MutableHandleObject};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// a function which is exported by a native module
+pub type EsNativeFunction =
+    dyn Fn(Vec<EsValueFacade>) -> Result<EsValueFacade, String> + Send + Sync + 'static;
+
+/// the name of the import.meta property which is used to pass the exports object to the module
+const EXPORTS_META_PROP: &str = "nativeExports";
+
+enum NativeExport {
+    Value(EsValueFacade),
+    SyncFunction(Arc<EsNativeFunction>),
+    AsyncFunction(Arc<EsNativeFunction>),
+    Proxy(String),
+}
+
+struct RegisteredModule {
+    exports_obj: EsPersistentRooted,
+    export_names: Vec<String>,
+    /// the ids of the functions in NATIVE_FUNCTIONS, these are removed when the module is registered again
+    function_ids: Vec<i32>,
+}
+
+thread_local! {
+    /// the native modules of the current worker thread by name
+    static NATIVE_MODULES: RefCell<HashMap<String, RegisteredModule>> = RefCell::new(HashMap::new());
+    /// the functions exported by native modules, the id is stored in the function object
+    static NATIVE_FUNCTIONS: RefCell<HashMap<i32, (Arc<EsNativeFunction>, bool)>> = RefCell::new(HashMap::new());
+    /// ids are never reused so a function object of a replaced module can't call another function
+    static NEXT_NATIVE_FUNCTION_ID: Cell<i32> = Cell::new(0);
+}
+
+/// a module which is implemented in rust
+pub struct EsNativeModule {}
+
+impl EsNativeModule {
+    /// create a builder for a native module, name is the specifier used to import the module
+    pub fn builder(name: &str) -> EsNativeModuleBuilder {
+        EsNativeModuleBuilder::new(name)
+    }
+}
+
+/// the EsNativeModuleBuilder is used to define the exports of a native module
+pub struct EsNativeModuleBuilder {
+    name: String,
+    exports: Vec<(String, NativeExport)>,
+}
+
+impl EsNativeModuleBuilder {
+    pub fn new(name: &str) -> Self {
+        EsNativeModuleBuilder {
+            name: name.to_string(),
+            exports: vec![],
+        }
+    }
+
+    fn add_export(&mut self, name: &str, export: NativeExport) -> &mut Self {
+        assert!(
+            is_valid_export_name(name),
+            "{} is not a valid export name",
+            name
+        );
+        self.exports.push((name.to_string(), export));
+        self
+    }
+
+    /// export a value, use "default" as name for the default export
+    pub fn value(&mut self, name: &str, value: EsValueFacade) -> &mut Self {
+        self.add_export(name, NativeExport::Value(value))
+    }
+
+    /// export a function which runs in the worker thread of the runtime and returns its result directly
+    pub fn sync_function<F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: Fn(Vec<EsValueFacade>) -> Result<EsValueFacade, String> + Send + Sync + 'static,
+    {
+        self.add_export(name, NativeExport::SyncFunction(Arc::new(func)))
+    }
+
+    /// export a function which runs in a helper thread, in script it returns a Promise
+    pub fn async_function<F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: Fn(Vec<EsValueFacade>) -> Result<EsValueFacade, String> + Send + Sync + 'static,
+    {
+        self.add_export(name, NativeExport::AsyncFunction(Arc::new(func)))
+    }
+
+    /// export the class of an EsProxy which was built before, please note that the class is also
+    /// available through its namespace
+    pub fn proxy(&mut self, name: &str, proxy: &EsProxy) -> &mut Self {
+        self.add_export(name, NativeExport::Proxy(proxy.get_canonical_name()))
+    }
+
+    /// register the native module in the runtime, after this it can be imported
+    /// when a native module with the same name was registered before it is replaced, modules which already imported
+    /// it keep using the old exports but the functions of the old module throw an error when they are called
+    pub fn build(&mut self, rt: &EsRuntime) {
+        let name = self.name.clone();
+        let exports = std::mem::take(&mut self.exports);
+        rt.do_in_es_event_queue_sync(move |sm_rt| {
+            sm_rt.do_with_jsapi(move |_rt, cx, global| {
+                register_native_module(cx, global, name, exports);
+            })
+        });
+    }
+}
+
+fn is_valid_export_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn register_native_module(
+    cx: *mut JSContext,
+    global: HandleObject,
+    name: String,
+    exports: Vec<(String, NativeExport)>,
+) {
+    trace!("registering native module {}", name);
+    // remove the previous version of the module so the next import uses the new exports
+    let old_module =
+        NATIVE_MODULES.with(|modules_rc| modules_rc.borrow_mut().remove(name.as_str()));
+    if let Some(old_module) = old_module {
+        NATIVE_FUNCTIONS.with(|functions_rc| {
+            let functions = &mut *functions_rc.borrow_mut();
+            for id in &old_module.function_ids {
+                functions.remove(id);
+            }
+        });
+        jsapi_utils::modules::invalidate_module(name.as_str());
+    }
+
+    rooted!(in (cx) let mut exports_obj_root = NULL_JSOBJECT);
+    jsapi_utils::objects::new_object(cx, exports_obj_root.handle_mut());
+
+    let mut export_names = vec![];
+    let mut function_ids = vec![];
+    for (export_name, export) in exports {
+        rooted!(in (cx) let mut export_val_root = UndefinedValue());
+        match export {
+            NativeExport::Value(esvf) => {
+                esvf.to_es_value(cx, export_val_root.handle_mut());
+            }
+            NativeExport::SyncFunction(func) => {
+                rooted!(in (cx) let mut func_obj_root = NULL_JSOBJECT);
+                function_ids.push(new_native_module_function(
+                    cx,
+                    export_name.as_str(),
+                    func,
+                    false,
+                    func_obj_root.handle_mut(),
+                ));
+                export_val_root.set(ObjectValue(func_obj_root.get()));
+            }
+            NativeExport::AsyncFunction(func) => {
+                rooted!(in (cx) let mut func_obj_root = NULL_JSOBJECT);
+                function_ids.push(new_native_module_function(
+                    cx,
+                    export_name.as_str(),
+                    func,
+                    true,
+                    func_obj_root.handle_mut(),
+                ));
+                export_val_root.set(ObjectValue(func_obj_root.get()));
+            }
+            NativeExport::Proxy(canonical_name) => {
+                // the proxy class lives in its namespace, walk there from the global
+                rooted!(in (cx) let mut scope_root = global.get());
+                for part in canonical_name.split('.') {
+                    jsapi_utils::objects::get_es_obj_prop_val(
+                        cx,
+                        scope_root.handle(),
+                        part,
+                        export_val_root.handle_mut(),
+                    )
+                    .expect("could not get proxy class");
+                    if export_val_root.is_object() {
+                        scope_root.set(export_val_root.to_object());
+                    }
+                }
+            }
+        }
+        jsapi_utils::objects::set_es_obj_prop_value(
+            cx,
+            exports_obj_root.handle(),
+            export_name.as_str(),
+            export_val_root.handle(),
+        );
+        export_names.push(export_name);
+    }
+
+    let exports_obj = EsPersistentRooted::new_from_obj(cx, exports_obj_root.get());
+    NATIVE_MODULES.with(|modules_rc| {
+        let modules = &mut *modules_rc.borrow_mut();
+        modules.insert(
+            name,
+            RegisteredModule {
+                exports_obj,
+                export_names,
+                function_ids,
+            },
+        );
+    });
+}
+
+fn new_native_module_function(
+    cx: *mut JSContext,
+    name: &str,
+    func: Arc<EsNativeFunction>,
+    is_async: bool,
+    rval: MutableHandleObject,
+) -> i32 {
+    let id = NEXT_NATIVE_FUNCTION_ID.with(|next_id| next_id.replace(next_id.get() + 1));
+    NATIVE_FUNCTIONS.with(|functions_rc| {
+        functions_rc.borrow_mut().insert(id, (func, is_async));
+    });
+
+    let n = format!("{}\0", name);
+    let mut rval = rval;
+    unsafe {
+        let function = NewFunctionWithReserved(
+            cx,
+            Some(native_module_function),
+            0,
+            0,
+            n.as_ptr() as *const libc::c_char,
+        );
+        let function_obj = JS_GetFunctionObject(function);
+        SetFunctionNativeReserved(function_obj, 0, &Int32Value(id));
+        rval.set(function_obj);
+    }
+    id
+}
+
+unsafe extern "C" fn native_module_function(
+    cx: *mut JSContext,
+    argc: u32,
+    vp: *mut mozjs::jsapi::Value,
+) -> bool {
+    let mut result = false;
+    // a panic in the function must not unwind into spidermonkey
+    wrap_panic(&mut || {
+        result = call_native_module_function(cx, argc, vp);
+    });
+    result
+}
+
+unsafe fn call_native_module_function(
+    cx: *mut JSContext,
+    argc: u32,
+    vp: *mut mozjs::jsapi::Value,
+) -> bool {
+    let args = CallArgs::from_vp(vp, argc);
+    let id = (*GetFunctionNativeReserved(args.callee(), 0)).to_int32();
+    let func_opt = NATIVE_FUNCTIONS.with(|functions_rc| functions_rc.borrow().get(&id).cloned());
+    let (func, is_async) = match func_opt {
+        Some(func) => func,
+        None => {
+            report_exception2(
+                cx,
+                "the native module of this function was replaced".to_string(),
+            );
+            return false;
+        }
+    };
+
+    let mut args_vec = vec![];
+    for x in 0..args.argc_ {
+        let var_arg: mozjs::rust::HandleValue = mozjs::rust::Handle::from_raw(args.get(x));
+        args_vec.push(EsValueFacade::new_v(cx, var_arg));
+    }
+
+    let rval = from_raw_handle_mut(args.rval());
+    if is_async {
+        let prom_esvf = EsValueFacade::new_promise(move || func(args_vec));
+        prom_esvf.to_es_value(cx, rval);
+        true
+    } else {
+        match func(args_vec) {
+            Ok(esvf) => {
+                esvf.to_es_value(cx, rval);
+                true
+            }
+            Err(js_err) => {
+                let s = format!("method failed\ncaused by: {}\0", js_err);
+                report_exception2(cx, s);
+                false
+            }
+        }
+    }
+}
+
+/// get the source of the module which re-exports a native module, None if there is no native module with that name
+pub(crate) fn get_native_module_script(name: &str) -> Option<Script> {
+    NATIVE_MODULES.with(|modules_rc| {
+        let modules = &*modules_rc.borrow();
+        modules.get(name).map(|module| {
+            let mut code = format!(
+                "const __native_exports = import.meta.{};\n",
+                EXPORTS_META_PROP
+            );
+            for export_name in &module.export_names {
+                if export_name.as_str() == "default" {
+                    code.push_str("export default __native_exports.default;\n");
+                } else {
+                    code.push_str(
+                        format!(
+                            "export const {} = __native_exports.{};\n",
+                            export_name, export_name
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+            Script::new(name, code.as_str())
+        })
+    })
+}
+
+/// add the exports object of a native module to the import.meta object of that module
+pub(crate) fn init_native_module_meta(cx: *mut JSContext, name: &str, meta_object: HandleObject) {
+    let exports_obj_opt = NATIVE_MODULES.with(|modules_rc| {
+        let modules = &*modules_rc.borrow();
+        modules.get(name).map(|module| module.exports_obj.get())
+    });
+    if let Some(exports_obj) = exports_obj_opt {
+        rooted!(in (cx) let exports_val_root = ObjectValue(exports_obj));
+        jsapi_utils::objects::set_es_obj_prop_value(
+            cx,
+            meta_object,
+            EXPORTS_META_PROP,
+            exports_val_root.handle(),
+        );
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esnativemodule::EsNativeModule;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::esvaluefacade::EsValueFacade;
+    use std::time::Duration;
+
+    #[test]
+    fn test_native_module() {
+        log::info!("test: test_native_module");
+        let rt = EsRuntimeBuilder::new().build();
+        EsNativeModule::builder("esses.com.test")
+            .value("answer", EsValueFacade::new_i32(42))
+            .sync_function("add", |args| {
+                Ok(EsValueFacade::new_i32(
+                    args[0].get_i32() + args[1].get_i32(),
+                ))
+            })
+            .async_function("later", |_args| Ok(EsValueFacade::new_i32(7)))
+            .value("default", EsValueFacade::new_str("def".to_string()))
+            .build(&rt);
+
+        rt.load_module_sync(
+            "import def, {answer, add} from 'esses.com.test';\n\nglobalThis.native_res = def + add(answer, 1);",
+            "test_native_module.mes",
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("native_res;", "test_native_module.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_string(), "def43");
+
+        let esvf = rt
+            .eval_sync(
+                "import('esses.com.test').then((mod) => {return mod.later();});",
+                "test_native_module2.es",
+            )
+            .expect("script failed");
+        let prom_res = esvf
+            .get_promise_result_blocking(Duration::from_secs(5))
+            .expect("promise timed out");
+        assert_eq!(prom_res.ok().expect("import failed").get_i32(), 7);
+    }
+
+    #[test]
+    fn test_replace_native_module() {
+        log::info!("test: test_replace_native_module");
+        let rt = EsRuntimeBuilder::new().build();
+        let export_name = "version".to_string();
+        EsNativeModule::builder("esses.com.replaced")
+            .sync_function(export_name.as_str(), |_args| Ok(EsValueFacade::new_i32(1)))
+            .build(&rt);
+        rt.load_module_sync(
+            "import {version} from 'esses.com.replaced';\n\nglobalThis.old_version = version;",
+            "test_replace_native_module.mes",
+        )
+        .expect("module failed");
+
+        EsNativeModule::builder("esses.com.replaced")
+            .sync_function(export_name.as_str(), |_args| Ok(EsValueFacade::new_i32(2)))
+            .build(&rt);
+        rt.load_module_sync(
+            "import {version} from 'esses.com.replaced';\n\nglobalThis.new_version = version;",
+            "test_replace_native_module2.mes",
+        )
+        .expect("module failed");
+
+        let esvf = rt
+            .eval_sync("new_version();", "test_replace_native_module.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 2);
+        let err = rt
+            .eval_sync("old_version();", "test_replace_native_module2.es")
+            .err()
+            .expect("function of the replaced module did not fail");
+        assert!(err.message.contains("replaced"));
+    }
+}
@@ -5,24 +5,55 @@ use std::sync::{Arc, Weak};
 use crate::es_sys_scripts;
 use crate::features;
 
+use crate::esmodule::EsModule;
 use crate::esruntimeinner::EsRuntimeInner;
 use crate::esvaluefacade::EsValueFacade;
+use crate::jsapi_utils;
 use crate::jsapi_utils::EsErrorInfo;
 
 use crate::esruntimebuilder::EsRuntimeBuilder;
 use crate::spidermonkeyruntimewrapper::SmRuntime;
 
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
 use std::time::Duration;
 
 use hirofa_utils::js_utils::Script;
 use hirofa_utils::task_manager::TaskManager;
+use mozjs::jsapi::JSGCParamKey;
 
 lazy_static! {
     /// a static Multithreaded taskmanager used to run rust ops async and multithreaded ( in at least 2 threads)
     static ref HELPER_TASKS: Arc<TaskManager> = Arc::new(TaskManager::new(std::cmp::max(2, num_cpus::get())));
 }
 
+/// memory statistics of an EsRuntime as reported by the garbage collector
+#[derive(Clone, Debug)]
+pub struct EsMemoryUsage {
+    /// the number of bytes currently allocated in the gc heap
+    pub heap_size: u32,
+    /// the maximum size of the gc heap in bytes
+    pub max_heap_size: u32,
+    /// the maximum size of the nursery in bytes
+    pub max_nursery_size: u32,
+    /// the number of times the garbage collector has run
+    pub gc_number: u32,
+}
+
+/// statistics of the module cache of an EsRuntime
+#[derive(Clone, Debug)]
+pub struct EsModuleCacheStats {
+    /// the number of imports which were served from the cache
+    pub hits: usize,
+    /// the number of imports for which the module had to be loaded
+    pub misses: usize,
+    /// the number of modules currently in the cache
+    pub size: usize,
+    /// the maximum number of modules in the cache, see EsRuntimeBuilder::module_cache_size()
+    pub capacity: usize,
+}
+
 /// the EsRuntime is a facade that adds all script todo's to the EsRuntimes's event queue so they are invoked in a single worker thread
 /// you can wait for those tasks to complete by calling the _sync variants of the public methods here
 pub struct EsRuntime {
@@ -35,6 +66,22 @@ pub struct EsRuntime {
 /// the EsScriptCode struct which is returned should allways contain an absolute path even if the module is loaded with a relative path
 pub type ModuleCodeLoader = dyn Fn(&str, &str) -> Option<Script> + Send + Sync + 'static;
 
+/// A RejectionHandler function is called when a Promise is rejected without a rejection handler,
+/// or when a handler is added to such a Promise later on, see EsRuntimeBuilder::on_unhandled_rejection()
+/// The argument is the rejection reason as an EsErrorInfo, the reason itself is available through
+/// EsErrorInfo::do_with_thrown_value()
+pub type RejectionHandler = dyn Fn(EsErrorInfo) + Send + Sync + 'static;
+
+/// An UncaughtExceptionHandler function is called when a script throws an exception which can not be returned
+/// to the caller of a job, e.g. in a timer or a setImmediate function, see EsRuntimeBuilder::on_uncaught_exception()
+pub type UncaughtExceptionHandler = dyn Fn(EsErrorInfo) + Send + Sync + 'static;
+
+/// An ImportMetaHook function is called when the import.meta object of a module is created, it can be used to add
+/// custom fields to import.meta, see EsRuntimeBuilder::import_meta_hook()
+/// The argument is the absolute path of the module (import.meta.url)
+/// The fields which are returned are added to import.meta
+pub type ImportMetaHook = dyn Fn(&str) -> HashMap<String, EsValueFacade> + Send + Sync + 'static;
+
 impl EsRuntime {
     /// create a builder to instantiate an EsRuntime
     pub fn builder() -> EsRuntimeBuilder {
@@ -44,6 +91,7 @@ impl EsRuntime {
     pub(crate) fn new_inner(inner: EsRuntimeInner) -> Self {
         let arc_inner = Arc::new(inner);
         let sm_ref_inner: Weak<EsRuntimeInner> = Arc::downgrade(&arc_inner);
+        let interrupt_state = arc_inner.interrupt_state.clone();
         let rt = EsRuntime { inner: arc_inner };
 
         // pass arc around inner to sm_rt thread
@@ -54,6 +102,7 @@ impl EsRuntime {
             crate::spidermonkeyruntimewrapper::SM_RT.with(move |sm_rc: &RefCell<SmRuntime>| {
                 let sm_rt = &mut *sm_rc.borrow_mut();
                 sm_rt.opt_esrt_inner = Some(sm_ref_inner);
+                sm_rt.init_interrupt_state(interrupt_state);
             });
         });
 
@@ -82,6 +131,63 @@ impl EsRuntime {
         });
     }
 
+    /// start a new thread which periodically checks if the files of cached modules were modified
+    /// changed modules are reloaded with the module_code_loader and evaluated again, modules which are imported
+    /// after that get the new version
+    ///
+    /// a module is watched by the path the module code loader resolves it to (AsyncModuleCodeLoader::resolve_module()),
+    /// so this works for modules which are loaded from a file, e.g. by the FileSystemModuleLoader, the file of a
+    /// module is watched from the first check after it was loaded
+    pub fn start_module_watcher(&self, interval: Duration) {
+        let wrc = Arc::downgrade(&self.inner);
+        let mut watcher = jsapi_utils::modules::ModuleWatcher::default();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            let arc_opt = wrc.upgrade();
+            if let Some(arc) = arc_opt {
+                // the files are checked and loaded in this thread, only compiling and evaluating happens in the
+                // worker thread
+                let reloaded = watcher.reload_changed_modules(&arc);
+                for path in reloaded {
+                    log::debug!("module watcher reloaded {}", path);
+                }
+            } else {
+                // arc to inner dropped, stop watcher loop
+                log::trace!("stopping EsRuntime module watcher loop...");
+                break;
+            }
+        });
+    }
+
+    /// remove a module from the module cache so it is loaded again the next time it is imported
+    /// path may be the absolute path a module was resolved to when it was imported or the path of the script it was
+    /// loaded from
+    /// returns true if a module was removed
+    pub fn invalidate_module(&self, path: &str) -> bool {
+        let path = path.to_string();
+        self.do_in_es_event_queue_sync(move |_sm_rt| {
+            jsapi_utils::modules::invalidate_module(path.as_str())
+        })
+    }
+
+    /// remove all modules from the module cache
+    pub fn clear_module_cache(&self) {
+        self.do_in_es_event_queue_sync(|_sm_rt| jsapi_utils::modules::clear_module_cache())
+    }
+
+    /// get the hit and miss counts and the size of the module cache
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let stats = rt.module_cache_stats();
+    /// println!("module cache hits: {} misses: {}", stats.hits, stats.misses);
+    /// ```
+    pub fn module_cache_stats(&self) -> EsModuleCacheStats {
+        self.do_in_es_event_queue_sync(|_sm_rt| jsapi_utils::modules::get_module_cache_stats())
+    }
+
     /// this method should be called when you want to run the garbage collector
     pub fn cleanup_sync(&self) {
         self.do_with_inner(move |inner| {
@@ -89,26 +195,112 @@ impl EsRuntime {
         })
     }
 
+    /// get the memory usage of this runtime
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let usage = rt.memory_usage();
+    /// println!("heap size: {} of {}", usage.heap_size, usage.max_heap_size);
+    /// ```
+    pub fn memory_usage(&self) -> EsMemoryUsage {
+        self.do_in_es_event_queue_sync(|sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| EsMemoryUsage {
+                heap_size: jsapi_utils::get_gc_parameter(cx, JSGCParamKey::JSGC_BYTES),
+                max_heap_size: jsapi_utils::get_gc_parameter(cx, JSGCParamKey::JSGC_MAX_BYTES),
+                max_nursery_size: jsapi_utils::get_gc_parameter(
+                    cx,
+                    JSGCParamKey::JSGC_MAX_NURSERY_BYTES,
+                ),
+                gc_number: jsapi_utils::get_gc_parameter(cx, JSGCParamKey::JSGC_NUMBER),
+            })
+        })
+    }
+
     /// eval a script and wait for it to complete
     pub fn eval_sync(&self, code: &str, file_name: &str) -> Result<EsValueFacade, EsErrorInfo> {
         self.do_with_inner(move |inner| inner.eval_sync(code, file_name))
     }
 
-    /// load a script module and run it
+    /// eval a script and wait for it to complete, if the script does not complete within the
+    /// timeout it is terminated and an EsErrorInfo with message "terminated: timeout" is returned
+    ///
+    /// the runtime remains usable after a script was terminated
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use std::time::Duration;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let res = rt.eval_sync_timeout("while(true){}", "test_eval_sync_timeout.es", Duration::from_millis(100));
+    /// assert_eq!(res.err().expect("script did not fail").message, "terminated: timeout");
+    /// ```
+    pub fn eval_sync_timeout(
+        &self,
+        code: &str,
+        file_name: &str,
+        timeout: Duration,
+    ) -> Result<EsValueFacade, EsErrorInfo> {
+        self.do_with_inner(move |inner| inner.eval_sync_timeout(code, file_name, timeout))
+    }
+
+    /// terminate the script which is currently running in the worker thread of this runtime
+    /// this may be called from any thread, the terminated script will return an EsErrorInfo with
+    /// message "terminated: interrupted"
+    ///
+    /// an interrupt only affects the job which is running at the moment it is requested,
+    /// if no script is running the interrupt is ignored
+    pub fn interrupt(&self) {
+        self.do_with_inner(|inner| inner.interrupt())
+    }
+
+    /// eval a script and return a Future which resolves when the script has completed
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use futures::executor::block_on;
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let esvf = block_on(rt.eval_async("(2 * 7);", "test_eval_async.es")).ok().expect("script failed");
+    /// assert_eq!(esvf.get_i32(), 14);
+    /// ```
+    pub fn eval_async(
+        &self,
+        code: &str,
+        file_name: &str,
+    ) -> impl Future<Output = Result<EsValueFacade, EsErrorInfo>> {
+        self.do_with_inner(move |inner| inner.eval_async(code, file_name))
+    }
+
+    /// load a script module and run it, the returned EsModule can be used to access the exports of the module
+    /// when the module uses top level await this waits until the module is done, if the module fails or its
+    /// top level await is rejected an EsErrorInfo is returned
     /// # Example
     /// ```rust
     /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
     /// let rt = EsRuntimeBuilder::new().build();
     /// rt.load_module_sync("console.log('running a module, you can import and export in and from modules');", "test_module.mes");
+    /// let module = rt.load_module_sync("export const double = (a) => a * 2;", "test_module_exports.mes").ok().expect("module failed");
+    /// let res = module.call_export("double", vec![EsValueFacade::new_i32(21)]).ok().expect("call failed");
+    /// assert_eq!(res.get_i32(), 42);
     /// ```
     pub fn load_module_sync(
         &self,
         module_src: &str,
         module_file_name: &str,
-    ) -> Result<(), EsErrorInfo> {
+    ) -> Result<EsModule, EsErrorInfo> {
         self.do_with_inner(|inner| inner.load_module_sync(module_src, module_file_name))
     }
 
+    /// load a script module and return a Future which resolves with an EsModule when the module has been run
+    /// including any top level await
+    pub fn load_module_async(
+        &self,
+        module_src: &str,
+        module_file_name: &str,
+    ) -> impl Future<Output = Result<EsModule, EsErrorInfo>> {
+        self.do_with_inner(|inner| inner.load_module_async(module_src, module_file_name))
+    }
+
     /// eval a script and wait for it to complete
     pub fn eval_void_sync(&self, code: &str, file_name: &str) -> Result<(), EsErrorInfo> {
         self.do_with_inner(move |inner| inner.eval_void_sync(code, file_name))
@@ -132,6 +324,30 @@ impl EsRuntime {
         self.do_with_inner(move |inner| inner.call_sync(obj_names, function_name, args))
     }
 
+    /// call a function by name and wait for it to complete, if the function does not complete within
+    /// the timeout it is terminated and an EsErrorInfo with message "terminated: timeout" is returned
+    pub fn call_sync_timeout(
+        &self,
+        obj_names: Vec<&'static str>,
+        function_name: &str,
+        args: Vec<EsValueFacade>,
+        timeout: Duration,
+    ) -> Result<EsValueFacade, EsErrorInfo> {
+        self.do_with_inner(move |inner| {
+            inner.call_sync_timeout(obj_names, function_name, args, timeout)
+        })
+    }
+
+    /// call a function by name and return a Future which resolves when the function has completed
+    pub fn call_async(
+        &self,
+        obj_names: Vec<&'static str>,
+        function_name: &str,
+        args: Vec<EsValueFacade>,
+    ) -> impl Future<Output = Result<EsValueFacade, EsErrorInfo>> {
+        self.do_with_inner(move |inner| inner.call_async(obj_names, function_name, args))
+    }
+
     /// eval a script and don't wait for it to complete
     pub fn eval(&self, eval_code: &str, file_name: &str) {
         self.do_with_inner(move |inner| inner.eval(eval_code, file_name))
@@ -170,6 +386,19 @@ impl EsRuntime {
         self.do_with_inner(|inner| inner.do_in_es_event_queue_sync(immutable_job))
     }
 
+    /// run a closure in the worker thread of this runtime's event queue and return a Future which
+    /// resolves with the result of the closure, or with an EsErrorInfo if the closure panicked or the
+    /// runtime was dropped before the closure was run
+    pub fn do_in_es_event_queue_async<R: Send + 'static, J>(
+        &self,
+        immutable_job: J,
+    ) -> impl Future<Output = Result<R, EsErrorInfo>>
+    where
+        J: FnOnce(&SmRuntime) -> R + Send + 'static,
+    {
+        self.do_with_inner(|inner| inner.do_in_es_event_queue_async(immutable_job))
+    }
+
     /// add a task the the "helper" thread pool
     pub fn add_helper_task<T>(task: T)
     where
@@ -240,6 +469,7 @@ pub mod tests {
     use crate::jsapi_utils::EsErrorInfo;
     use hirofa_utils::js_utils::Script;
     use log::LevelFilter;
+    use std::sync::Arc;
     use std::thread;
     use std::time::Duration;
 
@@ -439,4 +669,113 @@ pub mod tests {
         assert_eq!(123, esvf_res.get_i32());
         log::info!("test: test_async_await / 6");
     }
+
+    #[test]
+    fn test_async_api() {
+        log::info!("test: test_async_api");
+        let rt = init_test_runtime();
+
+        let esvf = futures::executor::block_on(rt.eval_async(
+            "this.test_async_api_func = function(a, b){return a * b;}; 3;",
+            "test_async_api.es",
+        ))
+        .expect("eval_async failed");
+        assert_eq!(esvf.get_i32(), 3);
+
+        let esvf = futures::executor::block_on(rt.call_async(
+            vec![],
+            "test_async_api_func",
+            vec![EsValueFacade::new_i32(6), EsValueFacade::new_i32(7)],
+        ))
+        .expect("call_async failed");
+        assert_eq!(esvf.get_i32(), 42);
+
+        futures::executor::block_on(rt.load_module_async(
+            "import {other} from 'foo_test_async_api.mes';",
+            "test_async_api.mes",
+        ))
+        .expect("load_module_async failed");
+    }
+
+    #[test]
+    fn test_timeout_and_interrupt() {
+        log::info!("test: test_timeout_and_interrupt");
+        let rt = Arc::new(init_test_runtime());
+
+        let res = rt.eval_sync_timeout(
+            "while(true){}",
+            "test_timeout.es",
+            Duration::from_millis(100),
+        );
+        assert_eq!(
+            res.err().expect("script did not time out").message,
+            "terminated: timeout"
+        );
+
+        // runtime should still be usable
+        let esvf = rt
+            .eval_sync("(1 + 2);", "test_timeout2.es")
+            .expect("runtime not usable after timeout");
+        assert_eq!(esvf.get_i32(), 3);
+
+        let rt2 = rt.clone();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            rt2.interrupt();
+        });
+        let res = rt.eval_sync("while(true){}", "test_interrupt.es");
+        interrupter.join().unwrap();
+        assert_eq!(
+            res.err().expect("script was not interrupted").message,
+            "terminated: interrupted"
+        );
+
+        // a script which completes in time should not be affected
+        let esvf = rt
+            .eval_sync_timeout("(5 * 5);", "test_timeout3.es", Duration::from_secs(5))
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 25);
+
+        // an interrupt while no script is running is ignored and does not terminate the next timer
+        rt.eval_sync(
+            "this.idle_res = 'not run'; setTimeout(function(){this.idle_res = 'run';}, 50);",
+            "test_idle_interrupt.es",
+        )
+        .expect("script failed");
+        rt.interrupt();
+        thread::sleep(Duration::from_millis(200));
+        let esvf = rt
+            .eval_sync("this.idle_res;", "test_idle_interrupt2.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_string(), "run");
+    }
+
+    #[test]
+    fn test_memory_limits() {
+        log::info!("test: test_memory_limits");
+        let rt = EsRuntime::builder()
+            .max_heap_size(32 * 1024 * 1024)
+            .gc_allocation_threshold(8)
+            .build();
+
+        let usage = rt.memory_usage();
+        assert_eq!(usage.max_heap_size, 32 * 1024 * 1024);
+        assert!(usage.heap_size > 0);
+
+        let res = rt.eval_sync(
+            "let a = []; while(true){a.push('some string to fill the heap with ' + a.length);}",
+            "test_memory_limits.es",
+        );
+        let err = res.err().expect("script did not run out of memory");
+        assert!(err.message.contains("out of memory"));
+
+        // the runtime should still be usable
+        rt.eval_sync("a = null;", "test_memory_limits2.es")
+            .expect("runtime not usable after oom");
+        rt.cleanup_sync();
+        let esvf = rt
+            .eval_sync("(2 + 2);", "test_memory_limits3.es")
+            .expect("runtime not usable after oom");
+        assert_eq!(esvf.get_i32(), 4);
+    }
 }
@@ -1,5 +1,13 @@
-use crate::esruntime::{EsRuntime, ModuleCodeLoader};
+use crate::esimportmap::ImportMap;
+use crate::esmoduleloader::{AsyncModuleCodeLoader, SyncModuleCodeLoader};
+use crate::esruntime::{
+    EsRuntime, ImportMetaHook, ModuleCodeLoader, RejectionHandler, UncaughtExceptionHandler,
+};
 use crate::esruntimeinner::EsRuntimeInner;
+use crate::jsapi_utils;
+use crate::jsapi_utils::promises::RejectionHandlers;
+use mozjs::jsapi::JSGCParamKey;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// The EsRuntimeBuilder struct can be used to initialize a new EsRuntime
@@ -16,8 +24,16 @@ use std::time::Duration;
 #[derive(Default)]
 pub struct EsRuntimeBuilder {
     gc_interval: Option<Duration>,
-    pub(crate) module_code_loader: Option<Box<ModuleCodeLoader>>,
+    module_watcher_interval: Option<Duration>,
+    pub(crate) module_code_loader: Option<Box<dyn AsyncModuleCodeLoader>>,
     pub(crate) module_cache_size: usize,
+    import_map: Option<ImportMap>,
+    import_meta_hook: Option<Arc<ImportMetaHook>>,
+    max_heap_size: Option<u32>,
+    gc_allocation_threshold: Option<u32>,
+    max_nursery_size: Option<u32>,
+    rejection_handlers: RejectionHandlers,
+    uncaught_exception_handler: Option<Arc<UncaughtExceptionHandler>>,
     built: bool,
 }
 
@@ -26,8 +42,16 @@ impl EsRuntimeBuilder {
     pub fn new() -> Self {
         EsRuntimeBuilder {
             gc_interval: None,
+            module_watcher_interval: None,
             module_code_loader: None,
             module_cache_size: 50,
+            import_map: None,
+            import_meta_hook: None,
+            max_heap_size: None,
+            gc_allocation_threshold: None,
+            max_nursery_size: None,
+            rejection_handlers: RejectionHandlers::default(),
+            uncaught_exception_handler: None,
             built: false,
         }
     }
@@ -40,6 +64,16 @@ impl EsRuntimeBuilder {
 
     /// set a closure which is used to provide source code of modules
     pub fn module_code_loader(&mut self, loader: Box<ModuleCodeLoader>) -> &mut Self {
+        self.module_code_loader = Some(Box::new(SyncModuleCodeLoader::new(loader)));
+        self
+    }
+
+    /// set an AsyncModuleCodeLoader which is used to provide source code of modules, this replaces the closure
+    /// set with module_code_loader()
+    pub fn async_module_code_loader(
+        &mut self,
+        loader: Box<dyn AsyncModuleCodeLoader>,
+    ) -> &mut Self {
         self.module_code_loader = Some(loader);
         self
     }
@@ -51,6 +85,129 @@ impl EsRuntimeBuilder {
         self
     }
 
+    /// watch the files of cached modules, if set this will start a new thread which periodically checks if the files
+    /// were modified and reloads the modules which were changed, see EsRuntime::start_module_watcher()
+    pub fn watch_modules(&mut self, interval: Duration) -> &mut Self {
+        self.module_watcher_interval = Some(interval);
+        self
+    }
+
+    /// set an ImportMap which is used to remap module specifiers before they are passed to the module_code_loader
+    pub fn import_map(&mut self, import_map: ImportMap) -> &mut Self {
+        self.import_map = Some(import_map);
+        self
+    }
+
+    /// set a hook which is called when the import.meta object of a module is created, the fields it returns are
+    /// added to import.meta
+    /// import.meta.url (the absolute path of the module) and import.meta.resolve() are always set
+    /// import.meta.resolve() resolves a path with the module code loader without loading the module
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    /// use std::collections::HashMap;
+    ///
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .import_meta_hook(Box::new(|_path| {
+    ///         let mut env = HashMap::new();
+    ///         env.insert("MODE".to_string(), EsValueFacade::new_str("production".to_string()));
+    ///         let mut fields = HashMap::new();
+    ///         fields.insert("env".to_string(), EsValueFacade::new_obj(env));
+    ///         fields
+    ///     }))
+    ///     .build();
+    /// rt.load_module_sync("console.log(import.meta.env.MODE);", "/test_import_meta_hook.mes").ok().expect("module failed");
+    /// ```
+    pub fn import_meta_hook(&mut self, hook: Box<ImportMetaHook>) -> &mut Self {
+        self.import_meta_hook = Some(Arc::from(hook));
+        self
+    }
+
+    /// set the maximum size of the heap in bytes (JSGC_MAX_BYTES)
+    /// when a script tries to allocate beyond this limit it will fail with an "out of memory" EsErrorInfo
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    ///
+    /// let rt = EsRuntimeBuilder::new().max_heap_size(64 * 1024 * 1024).build();
+    /// let res = rt.eval_sync("let a = []; while(true){a.push('some string' + a.length);}", "test_max_heap_size.es");
+    /// assert!(res.is_err());
+    /// ```
+    pub fn max_heap_size(&mut self, bytes: u32) -> &mut Self {
+        self.max_heap_size = Some(bytes);
+        self
+    }
+
+    /// set the size of the heap in megabytes at which the garbage collector is triggered (JSGC_ALLOCATION_THRESHOLD)
+    pub fn gc_allocation_threshold(&mut self, mega_bytes: u32) -> &mut Self {
+        self.gc_allocation_threshold = Some(mega_bytes);
+        self
+    }
+
+    /// set the maximum size of the nursery in bytes (JSGC_MAX_NURSERY_BYTES)
+    pub fn max_nursery_size(&mut self, bytes: u32) -> &mut Self {
+        self.max_nursery_size = Some(bytes);
+        self
+    }
+
+    /// set a handler which is called when a Promise is rejected and it has no rejection handler
+    /// rejections are reported when all pending Promise jobs have run, so a rejection which is handled in the
+    /// same job or in a later Promise job is not reported
+    /// the handler is called from the worker thread of the runtime, so it should not call the _sync methods of the
+    /// EsRuntime, if no handler is set the rejection is logged as an error
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    ///
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .on_unhandled_rejection(Box::new(|err| {
+    ///         log::error!("unhandled rejection: {}", err);
+    ///     }))
+    ///     .build();
+    /// rt.eval_sync("Promise.reject(new Error('oops'));", "test_unhandled_rejection.es").ok().expect("script failed");
+    /// ```
+    pub fn on_unhandled_rejection(&mut self, handler: Box<RejectionHandler>) -> &mut Self {
+        self.rejection_handlers.unhandled = Some(Arc::from(handler));
+        self
+    }
+
+    /// set a handler which is called when a rejection handler is added to a Promise which was already reported
+    /// to the handler set with on_unhandled_rejection()
+    pub fn on_rejection_handled(&mut self, handler: Box<RejectionHandler>) -> &mut Self {
+        self.rejection_handlers.handled = Some(Arc::from(handler));
+        self
+    }
+
+    /// set a handler which is called when an exception is thrown which can not be returned to the caller of a job,
+    /// e.g. when a timer or a setImmediate function throws or when a Promise job is interrupted
+    /// an exception in a Promise reaction rejects the Promise returned by then(), that rejection is reported to the
+    /// handler set with on_unhandled_rejection()
+    /// the runtime keeps working after such an exception, if no handler is set the exception is logged as an error
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    ///
+    /// let rt = EsRuntimeBuilder::new()
+    ///     .on_uncaught_exception(Box::new(|err| {
+    ///         log::error!("uncaught exception: {}", err);
+    ///     }))
+    ///     .build();
+    /// rt.eval_sync("setTimeout(() => {throw Error('oops');}, 1);", "test_uncaught_exception.es").ok().expect("script failed");
+    /// ```
+    pub fn on_uncaught_exception(&mut self, handler: Box<UncaughtExceptionHandler>) -> &mut Self {
+        self.uncaught_exception_handler = Some(Arc::from(handler));
+        self
+    }
+
     /// build a new EsRuntime based on the settings of this builder
     /// please note that this can be used only once
     pub fn build(&mut self) -> EsRuntime {
@@ -62,17 +219,57 @@ impl EsRuntimeBuilder {
 
         // consume opts
 
-        let mcl_opt: Option<Box<ModuleCodeLoader>> = if self.module_code_loader.is_some() {
-            std::mem::replace(&mut self.module_code_loader, None)
-        } else {
-            None
-        };
+        let mcl_opt = self.module_code_loader.take();
 
-        let inner = EsRuntimeInner::build(mcl_opt, self.module_cache_size);
+        let inner = EsRuntimeInner::build(mcl_opt, self.module_cache_size, self.import_map.take());
         let es_rt = EsRuntime::new_inner(inner);
+
+        let mut gc_params = vec![];
+        if let Some(bytes) = self.max_heap_size {
+            gc_params.push((JSGCParamKey::JSGC_MAX_BYTES, bytes));
+        }
+        if let Some(mega_bytes) = self.gc_allocation_threshold {
+            gc_params.push((JSGCParamKey::JSGC_ALLOCATION_THRESHOLD, mega_bytes));
+        }
+        if let Some(bytes) = self.max_nursery_size {
+            gc_params.push((JSGCParamKey::JSGC_MAX_NURSERY_BYTES, bytes));
+        }
+        if !gc_params.is_empty() {
+            es_rt.do_in_es_event_queue_sync(move |sm_rt| {
+                sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                    for (key, value) in gc_params {
+                        jsapi_utils::set_gc_parameter(cx, key, value);
+                    }
+                })
+            });
+        }
+
+        if self.rejection_handlers.unhandled.is_some() || self.rejection_handlers.handled.is_some()
+        {
+            let handlers = std::mem::take(&mut self.rejection_handlers);
+            es_rt.do_in_es_event_queue_sync(move |_sm_rt| {
+                jsapi_utils::promises::set_rejection_handlers(handlers);
+            });
+        }
+
+        if let Some(hook) = self.import_meta_hook.take() {
+            es_rt.do_in_es_event_queue_sync(move |_sm_rt| {
+                jsapi_utils::modules::set_import_meta_hook(hook);
+            });
+        }
+
+        if let Some(handler) = self.uncaught_exception_handler.take() {
+            es_rt.do_in_es_event_queue_sync(move |sm_rt| {
+                sm_rt.init_uncaught_exception_handler(handler);
+            });
+        }
+
         if self.gc_interval.is_some() {
             es_rt.start_gc_deamon(self.gc_interval.unwrap());
         }
+        if let Some(interval) = self.module_watcher_interval {
+            es_rt.start_module_watcher(interval);
+        }
         es_rt
     }
 }
@@ -1,30 +1,41 @@
-use crate::esruntime::ModuleCodeLoader;
+use crate::esimportmap::ImportMap;
+use crate::esmodule::EsModule;
+use crate::esmoduleloader::AsyncModuleCodeLoader;
 use crate::esvaluefacade::EsValueFacade;
 use crate::jsapi_utils::handles::from_raw_handle_mut;
+use crate::jsapi_utils::modules::fetch_and_evaluate_module;
 use crate::jsapi_utils::{report_exception2, EsErrorInfo};
-use crate::spidermonkeyruntimewrapper::SmRuntime;
+use crate::spidermonkeyruntimewrapper::{InterruptState, SmRuntime};
+use futures::channel::oneshot;
 use hirofa_utils::eventloop::EventLoop;
 use log::{debug, trace};
 use mozjs::jsapi::CallArgs;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct EsRuntimeInner {
     pub(crate) event_loop: EventLoop,
     pub(crate) _pre_cleanup_tasks: Vec<Box<dyn Fn(&EsRuntimeInner) + Send + Sync>>,
-    pub(crate) module_source_loader: Option<Box<ModuleCodeLoader>>,
+    pub(crate) module_source_loader: Option<Box<dyn AsyncModuleCodeLoader>>,
     pub(crate) module_cache_size: usize,
+    pub(crate) import_map: Option<ImportMap>,
+    pub(crate) interrupt_state: Arc<InterruptState>,
 }
 
 impl EsRuntimeInner {
     pub(crate) fn build(
-        module_source_loader: Option<Box<ModuleCodeLoader>>,
+        module_source_loader: Option<Box<dyn AsyncModuleCodeLoader>>,
         module_cache_size: usize,
+        import_map: Option<ImportMap>,
     ) -> Self {
         EsRuntimeInner {
             event_loop: EventLoop::new(),
             _pre_cleanup_tasks: vec![],
             module_source_loader,
             module_cache_size,
+            import_map,
+            interrupt_state: Arc::new(InterruptState::default()),
         }
     }
 
@@ -58,6 +69,42 @@ impl EsRuntimeInner {
         }))
     }
 
+    pub fn call_async(
+        &self,
+        obj_names: Vec<&'static str>,
+        function_name: &str,
+        args: Vec<EsValueFacade>,
+    ) -> impl Future<Output = Result<EsValueFacade, EsErrorInfo>> {
+        trace!(
+            "call_async {} in thread {}",
+            function_name,
+            thread_id::get()
+        );
+        let f_n = function_name.to_string();
+        let call_future = self.do_in_es_event_queue_async(move |sm_rt: &SmRuntime| {
+            sm_rt.call(obj_names, f_n.as_str(), args)
+        });
+        async move { call_future.await? }
+    }
+
+    pub fn call_sync_timeout(
+        &self,
+        obj_names: Vec<&'static str>,
+        function_name: &str,
+        args: Vec<EsValueFacade>,
+        timeout: Duration,
+    ) -> Result<EsValueFacade, EsErrorInfo> {
+        trace!(
+            "call_sync_timeout {} in thread {}",
+            function_name,
+            thread_id::get()
+        );
+        let f_n = function_name.to_string();
+        self.do_in_es_event_queue_sync(move |sm_rt: &SmRuntime| {
+            sm_rt.with_deadline(timeout, |sm_rt| sm_rt.call(obj_names, f_n.as_str(), args))
+        })
+    }
+
     pub fn eval(&self, eval_code: &str, file_name: &str) {
         debug!("eval {} in thread {}", eval_code, thread_id::get());
 
@@ -82,6 +129,44 @@ impl EsRuntimeInner {
         }))
     }
 
+    pub fn eval_async(
+        &self,
+        code: &str,
+        file_name: &str,
+    ) -> impl Future<Output = Result<EsValueFacade, EsErrorInfo>> {
+        debug!("eval_async {} in thread {}", code, thread_id::get());
+        let eval_code = code.to_string();
+        let file_name = file_name.to_string();
+
+        let eval_future = self.do_in_es_event_queue_async(move |sm_rt: &SmRuntime| {
+            sm_rt.eval(eval_code.as_str(), file_name.as_str())
+        });
+        async move { eval_future.await? }
+    }
+
+    pub fn eval_sync_timeout(
+        &self,
+        code: &str,
+        file_name: &str,
+        timeout: Duration,
+    ) -> Result<EsValueFacade, EsErrorInfo> {
+        debug!("eval_sync_timeout {} in thread {}", code, thread_id::get());
+        let eval_code = code.to_string();
+        let file_name = file_name.to_string();
+
+        self.do_in_es_event_queue_sync(move |sm_rt: &SmRuntime| {
+            sm_rt.with_deadline(timeout, |sm_rt| {
+                sm_rt.eval(eval_code.as_str(), file_name.as_str())
+            })
+        })
+    }
+
+    /// terminate the script which is currently running in the worker thread
+    pub fn interrupt(&self) {
+        debug!("interrupt requested in thread {}", thread_id::get());
+        self.interrupt_state.request("interrupted");
+    }
+
     pub fn eval_void_sync(&self, code: &str, file_name: &str) -> Result<(), EsErrorInfo> {
         let eval_code = code.to_string();
         let file_name = file_name.to_string();
@@ -95,13 +180,48 @@ impl EsRuntimeInner {
         &self,
         module_src: &str,
         module_file_name: &str,
-    ) -> Result<(), EsErrorInfo> {
+    ) -> Result<EsModule, EsErrorInfo> {
+        if EventLoop::is_a_pool_thread() {
+            // we can't wait for the helper threads or a top level await in the worker thread, so the imports have
+            // to be cached already and the module has to be done after it was evaluated
+            let module_src_str = module_src.to_string();
+            let module_file_name_str = module_file_name.to_string();
+            return self.do_in_es_event_queue_sync(move |sm_rt: &SmRuntime| {
+                sm_rt.load_module_with_exports(
+                    module_src_str.as_str(),
+                    module_file_name_str.as_str(),
+                )
+            });
+        }
+        futures::executor::block_on(self.load_module_async(module_src, module_file_name))
+    }
+
+    pub fn load_module_async(
+        &self,
+        module_src: &str,
+        module_file_name: &str,
+    ) -> impl Future<Output = Result<EsModule, EsErrorInfo>> {
         let module_src_str = module_src.to_string();
         let module_file_name_str = module_file_name.to_string();
 
-        self.do_in_es_event_queue_sync(Box::new(move |sm_rt: &SmRuntime| {
-            sm_rt.load_module(module_src_str.as_str(), module_file_name_str.as_str())
-        }))
+        let prepare_future = self.do_in_es_event_queue_async(move |sm_rt: &SmRuntime| {
+            let res = sm_rt.prepare_module(module_src_str.as_str(), module_file_name_str.as_str());
+            (sm_rt.clone_esrt_inner(), module_file_name_str, res)
+        });
+
+        async move {
+            let (inner, module_file_name_str, res) = prepare_future.await?;
+
+            // fetch the static imports in the helper threads so a slow module code loader does not block the
+            // worker thread
+            fetch_and_evaluate_module(inner.clone(), module_file_name_str.clone(), res?).await?;
+
+            inner
+                .do_in_es_event_queue_async(move |sm_rt: &SmRuntime| {
+                    sm_rt.get_module_exports(module_file_name_str.as_str())
+                })
+                .await?
+        }
     }
 
     pub(crate) fn cleanup_sync(&self) {
@@ -148,6 +268,37 @@ impl EsRuntimeInner {
         self.event_loop.exe(job)
     }
 
+    pub fn do_in_es_event_queue_async<R: Send + 'static, J>(
+        &self,
+        job: J,
+    ) -> impl Future<Output = Result<R, EsErrorInfo>>
+    where
+        J: FnOnce(&SmRuntime) -> R + Send + 'static,
+    {
+        trace!("do_in_spidermonkey_runtime_thread_async");
+        // the result is passed back through a oneshot channel so no thread is parked while waiting
+
+        let (tx, rx) = oneshot::channel();
+
+        self.do_in_es_event_queue(move |sm_rt| {
+            if tx.send(job(sm_rt)).is_err() {
+                trace!("async job result was not awaited");
+            }
+        });
+
+        // the job is dropped when it panics or when the runtime is dropped before the job was run
+        async move {
+            rx.await.map_err(|_canceled| {
+                EsErrorInfo::new(
+                    "async job was dropped before it completed".to_string(),
+                    "do_in_es_event_queue_async".to_string(),
+                    0,
+                    0,
+                )
+            })
+        }
+    }
+
     pub fn add_global_async_function<F>(&self, name: &'static str, func: F)
     where
         F: Fn(Vec<EsValueFacade>) -> Result<EsValueFacade, String> + Send + Sync + 'static,
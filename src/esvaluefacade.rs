@@ -1,10 +1,18 @@
-use log::trace;
+use log::{debug, trace};
 
 use crate::esruntime::EsRuntime;
 use crate::esruntimeinner::EsRuntimeInner;
 use crate::jsapi_utils::arrays::{get_array_element, get_array_length, new_array, object_is_array};
+use crate::jsapi_utils::big_ints;
+use crate::jsapi_utils::dates;
+use crate::jsapi_utils::errors;
+use crate::jsapi_utils::maps_sets;
 use crate::jsapi_utils::objects::NULL_JSOBJECT;
 use crate::jsapi_utils::rooting::EsPersistentRooted;
+use crate::jsapi_utils::typed_arrays::{
+    ArrayBuffer, Float32Array, Float64Array, Int16Array, Int32Array, Int8Array, Uint16Array,
+    Uint32Array, Uint8Array, Uint8ClampedArray,
+};
 use crate::jsapi_utils::{objects, EsErrorInfo};
 use crate::spidermonkeyruntimewrapper::SmRuntime;
 use crate::{jsapi_utils, spidermonkeyruntimewrapper};
@@ -12,22 +20,104 @@ use either::Either;
 use hirofa_utils::auto_id_map::AutoIdMap;
 use hirofa_utils::debug_mutex::DebugMutex;
 use hirofa_utils::eventloop::EventLoop;
-use log::debug;
+use mozjs::jsapi::CurrentGlobalOrNull;
 use mozjs::jsapi::HandleValueArray;
 use mozjs::jsapi::JSContext;
 use mozjs::jsapi::JSObject;
-use mozjs::jsval::{BooleanValue, DoubleValue, Int32Value, JSVal, ObjectValue, UndefinedValue};
-use mozjs::rust::{HandleValue, MutableHandleValue};
+use mozjs::jsval::{
+    BooleanValue, DoubleValue, Int32Value, JSVal, NullValue, ObjectValue, UndefinedValue,
+};
+use mozjs::rust::{HandleObject, HandleValue, MutableHandleValue};
 use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
-use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
-use std::sync::{Arc, Weak};
-use std::time::Duration;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime};
+
+mod serde_bridge;
+
+pub use serde_bridge::EsSerdeError;
+
+type PromiseResult = Result<EsValueFacade, EsValueFacade>;
+
+#[derive(Default)]
+struct PromiseResultStateInner {
+    result: Option<PromiseResult>,
+    waker: Option<Waker>,
+}
+
+// the result of a promise that was passed from the script engine to rust
+// it is set by the reactions in the worker thread and taken by either a blocking waiter or a Future
+#[derive(Default)]
+struct PromiseResultState {
+    inner: Mutex<PromiseResultStateInner>,
+    condvar: Condvar,
+}
+
+impl PromiseResultState {
+    fn settle(&self, result: PromiseResult) {
+        let waker_opt = {
+            let inner = &mut *self.inner.lock().unwrap();
+            inner.result = Some(result);
+            inner.waker.take()
+        };
+        self.condvar.notify_all();
+        if let Some(waker) = waker_opt {
+            waker.wake();
+        }
+    }
+
+    fn wait_timeout(&self, timeout: Duration) -> Result<PromiseResult, RecvTimeoutError> {
+        let guard = self.inner.lock().unwrap();
+        let (mut guard, _timeout_res) = self
+            .condvar
+            .wait_timeout_while(guard, timeout, |inner| inner.result.is_none())
+            .unwrap();
+        guard.result.take().ok_or(RecvTimeoutError::Timeout)
+    }
+}
+
+/// a Future which resolves when a Promise which was returned from the script engine is settled
+/// the Output is Ok when the Promise was resolved and Err when the Promise was rejected
+///
+/// see [EsValueFacade::into_future](struct.EsValueFacade.html#method.into_future)
+pub struct EsPromiseFuture {
+    // keeps the cached promise alive while the future is pending
+    _esvf: Option<EsValueFacade>,
+    state: Option<Arc<PromiseResultState>>,
+}
+
+impl Future for EsPromiseFuture {
+    type Output = PromiseResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match &this.state {
+            None => Poll::Ready(Err(EsValueFacade::new_str(
+                "esvf was not a Promise".to_string(),
+            ))),
+            Some(state) => {
+                let inner = &mut *state.inner.lock().unwrap();
+                if let Some(result) = inner.result.take() {
+                    this._esvf = None;
+                    Poll::Ready(result)
+                } else {
+                    inner.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
 
 // placeholder for promises that were passed from the script engine to rust
 struct CachedJSPromise {
     cached_obj_id: usize,
-    opt_receiver: Option<Receiver<Result<EsValueFacade, EsValueFacade>>>,
+    result_state: Arc<PromiseResultState>,
     rti_ref: Arc<EsRuntimeInner>,
 }
 
@@ -251,6 +341,48 @@ pub trait EsValueConvertible {
     fn get_f64(&self) -> f64 {
         panic!("i am not an f64");
     }
+    fn is_big_int(&self) -> bool {
+        false
+    }
+    fn get_big_int_str(&self) -> &str {
+        panic!("i am not a BigInt");
+    }
+    fn is_typed_array(&self) -> bool {
+        false
+    }
+    fn get_typed_array(&self) -> &EsTypedArray {
+        panic!("i am not a TypedArray");
+    }
+    fn is_array_buffer(&self) -> bool {
+        false
+    }
+    fn get_array_buffer(&self) -> &Vec<u8> {
+        panic!("i am not an ArrayBuffer");
+    }
+    fn is_date(&self) -> bool {
+        false
+    }
+    fn get_date_msec(&self) -> f64 {
+        panic!("i am not a Date");
+    }
+    fn is_error(&self) -> bool {
+        false
+    }
+    fn get_error(&self) -> &EsErrorInfo {
+        panic!("i am not an Error");
+    }
+    fn is_map(&self) -> bool {
+        false
+    }
+    fn get_map(&self) -> &Vec<(EsValueFacade, EsValueFacade)> {
+        panic!("i am not a Map");
+    }
+    fn is_set(&self) -> bool {
+        false
+    }
+    fn get_set(&self) -> &Vec<EsValueFacade> {
+        panic!("i am not a Set");
+    }
     fn is_function(&self) -> bool {
         false
     }
@@ -260,6 +392,12 @@ pub trait EsValueConvertible {
     fn is_promise(&self) -> bool {
         false
     }
+    fn get_promise_future(&self) -> EsPromiseFuture {
+        EsPromiseFuture {
+            _esvf: None,
+            state: None,
+        }
+    }
     fn await_promise_blocking(
         &self,
         _timeout: Duration,
@@ -283,8 +421,338 @@ pub trait EsValueConvertible {
 struct EsUndefinedValue {}
 
 impl EsValueConvertible for EsUndefinedValue {
-    fn to_js_value(&self, _cx: *mut JSContext, _rval: MutableHandleValue) {
-        //
+    fn to_js_value(&self, _cx: *mut JSContext, rval: MutableHandleValue) {
+        let mut rval = rval;
+        rval.set(UndefinedValue());
+    }
+
+    fn is_undefined(&self) -> bool {
+        true
+    }
+}
+
+struct EsNullValue {}
+
+impl EsValueConvertible for EsNullValue {
+    fn to_js_value(&self, _cx: *mut JSContext, rval: MutableHandleValue) {
+        let mut rval = rval;
+        rval.set(NullValue());
+    }
+
+    fn is_null(&self) -> bool {
+        true
+    }
+}
+
+/// a BigInt is stored as its decimal representation so it can be of any size
+struct EsBigIntValue {
+    value: String,
+}
+
+impl EsValueConvertible for EsBigIntValue {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        // the value is always a valid decimal integer so this only fails when we are out of memory
+        if let Err(err) = big_ints::new_big_int_from_str(cx, self.value.as_str(), rval) {
+            log::error!("could not create BigInt {}: {}", self.value, err);
+        }
+    }
+
+    fn is_big_int(&self) -> bool {
+        true
+    }
+
+    fn get_big_int_str(&self) -> &str {
+        self.value.as_str()
+    }
+}
+
+/// the contents of a TypedArray, the data is copied in one go when converting from or to the script engine
+pub enum EsTypedArray {
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Uint8Clamped(Vec<u8>),
+    Int16(Vec<i16>),
+    Uint16(Vec<u16>),
+    Int32(Vec<i32>),
+    Uint32(Vec<u32>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+impl EsTypedArray {
+    /// get the number of elements in the TypedArray
+    pub fn len(&self) -> usize {
+        match self {
+            EsTypedArray::Int8(v) => v.len(),
+            EsTypedArray::Uint8(v) => v.len(),
+            EsTypedArray::Uint8Clamped(v) => v.len(),
+            EsTypedArray::Int16(v) => v.len(),
+            EsTypedArray::Uint16(v) => v.len(),
+            EsTypedArray::Int32(v) => v.len(),
+            EsTypedArray::Uint32(v) => v.len(),
+            EsTypedArray::Float32(v) => v.len(),
+            EsTypedArray::Float64(v) => v.len(),
+        }
+    }
+
+    /// check if the TypedArray has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// convert a TypedArray object, returns None if obj is not a TypedArray or if its type is not supported
+    /// (BigInt64Array and BigUint64Array), those are converted like a plain object
+    fn new_from_object(cx: *mut JSContext, obj: HandleObject) -> Option<Self> {
+        let raw_obj = obj.get();
+        let res = if Int8Array::is_instance(raw_obj) {
+            Int8Array::convert_to_vec(cx, obj).map(EsTypedArray::Int8)
+        } else if Uint8Array::is_instance(raw_obj) {
+            Uint8Array::convert_to_vec(cx, obj).map(EsTypedArray::Uint8)
+        } else if Uint8ClampedArray::is_instance(raw_obj) {
+            Uint8ClampedArray::convert_to_vec(cx, obj).map(EsTypedArray::Uint8Clamped)
+        } else if Int16Array::is_instance(raw_obj) {
+            Int16Array::convert_to_vec(cx, obj).map(EsTypedArray::Int16)
+        } else if Uint16Array::is_instance(raw_obj) {
+            Uint16Array::convert_to_vec(cx, obj).map(EsTypedArray::Uint16)
+        } else if Int32Array::is_instance(raw_obj) {
+            Int32Array::convert_to_vec(cx, obj).map(EsTypedArray::Int32)
+        } else if Uint32Array::is_instance(raw_obj) {
+            Uint32Array::convert_to_vec(cx, obj).map(EsTypedArray::Uint32)
+        } else if Float32Array::is_instance(raw_obj) {
+            Float32Array::convert_to_vec(cx, obj).map(EsTypedArray::Float32)
+        } else if Float64Array::is_instance(raw_obj) {
+            Float64Array::convert_to_vec(cx, obj).map(EsTypedArray::Float64)
+        } else {
+            return None;
+        };
+        match res {
+            Ok(typed_array) => Some(typed_array),
+            Err(err) => {
+                debug!("could not convert TypedArray: {}", err.err_msg());
+                None
+            }
+        }
+    }
+}
+
+impl EsValueConvertible for EsTypedArray {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let mut arr_root = NULL_JSOBJECT);
+        let res = match self {
+            EsTypedArray::Int8(v) => {
+                Int8Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Uint8(v) => {
+                Uint8Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Uint8Clamped(v) => {
+                Uint8ClampedArray::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Int16(v) => {
+                Int16Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Uint16(v) => {
+                Uint16Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Int32(v) => {
+                Int32Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Uint32(v) => {
+                Uint32Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Float32(v) => {
+                Float32Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+            EsTypedArray::Float64(v) => {
+                Float64Array::new_instance_from_slice(cx, arr_root.handle_mut(), v)
+            }
+        };
+        // this only fails when we are out of memory (e.g. because of EsRuntimeBuilder::max_heap_size())
+        if res.is_err() {
+            log::error!("could not create TypedArray of {} elements", self.len());
+            return;
+        }
+        let mut rval = rval;
+        rval.set(ObjectValue(*arr_root));
+    }
+
+    fn is_typed_array(&self) -> bool {
+        true
+    }
+
+    fn get_typed_array(&self) -> &EsTypedArray {
+        self
+    }
+}
+
+struct EsArrayBufferValue {
+    bytes: Vec<u8>,
+}
+
+impl EsValueConvertible for EsArrayBufferValue {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let mut buf_root = NULL_JSOBJECT);
+        // this only fails when we are out of memory (e.g. because of EsRuntimeBuilder::max_heap_size())
+        if ArrayBuffer::new_instance_from_slice(cx, buf_root.handle_mut(), self.bytes.as_slice())
+            .is_err()
+        {
+            log::error!("could not create ArrayBuffer of {} bytes", self.bytes.len());
+            return;
+        }
+        let mut rval = rval;
+        rval.set(ObjectValue(*buf_root));
+    }
+
+    fn is_array_buffer(&self) -> bool {
+        true
+    }
+
+    fn get_array_buffer(&self) -> &Vec<u8> {
+        &self.bytes
+    }
+}
+
+struct EsDateValue {
+    msec: f64,
+}
+
+impl EsValueConvertible for EsDateValue {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let global_root = unsafe { CurrentGlobalOrNull(cx) });
+        rooted!(in (cx) let mut date_root = NULL_JSOBJECT);
+        dates::new_date(cx, global_root.handle(), self.msec, date_root.handle_mut())
+            .expect("dates::new_date failed");
+        let mut rval = rval;
+        rval.set(ObjectValue(*date_root));
+    }
+
+    fn is_date(&self) -> bool {
+        true
+    }
+
+    fn get_date_msec(&self) -> f64 {
+        self.msec
+    }
+}
+
+/// an Error is represented by an EsErrorInfo, the same struct which is used for exceptions
+impl EsValueConvertible for EsErrorInfo {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let global_root = unsafe { CurrentGlobalOrNull(cx) });
+        rooted!(in (cx) let mut err_root = NULL_JSOBJECT);
+        let name = if self.name.is_empty() {
+            "Error"
+        } else {
+            self.name.as_str()
+        };
+        errors::new_error(
+            cx,
+            global_root.handle(),
+            name,
+            self.message.as_str(),
+            err_root.handle_mut(),
+        )
+        .expect("errors::new_error failed");
+
+        rooted!(in (cx) let mut prop_root = UndefinedValue());
+        if !self.stack.is_empty() {
+            let stack = jsapi_utils::format_stack(self.stack.as_slice());
+            jsapi_utils::new_es_value_from_str(cx, stack.as_str(), prop_root.handle_mut());
+            objects::set_es_obj_prop_value(cx, err_root.handle(), "stack", prop_root.handle());
+        }
+        if !self.filename.is_empty() {
+            jsapi_utils::new_es_value_from_str(cx, self.filename.as_str(), prop_root.handle_mut());
+            objects::set_es_obj_prop_value(cx, err_root.handle(), "fileName", prop_root.handle());
+        }
+        if self.lineno > 0 {
+            prop_root.set(Int32Value(self.lineno));
+            objects::set_es_obj_prop_value(cx, err_root.handle(), "lineNumber", prop_root.handle());
+            prop_root.set(Int32Value(self.column));
+            objects::set_es_obj_prop_value(
+                cx,
+                err_root.handle(),
+                "columnNumber",
+                prop_root.handle(),
+            );
+        }
+        if let Some(cause) = &self.cause {
+            cause.to_js_value(cx, prop_root.handle_mut());
+            objects::set_es_obj_prop_value(cx, err_root.handle(), "cause", prop_root.handle());
+        }
+
+        let mut rval = rval;
+        rval.set(ObjectValue(*err_root));
+    }
+
+    fn is_error(&self) -> bool {
+        true
+    }
+
+    fn get_error(&self) -> &EsErrorInfo {
+        self
+    }
+}
+
+/// the entries of a Map in insertion order, keys can be of any type
+struct EsMapValue {
+    entries: Vec<(EsValueFacade, EsValueFacade)>,
+}
+
+impl EsValueConvertible for EsMapValue {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let mut map_root = NULL_JSOBJECT);
+        maps_sets::new_map(cx, map_root.handle_mut());
+        for (key, value) in &self.entries {
+            rooted!(in (cx) let mut key_root = UndefinedValue());
+            rooted!(in (cx) let mut value_root = UndefinedValue());
+            key.to_es_value(cx, key_root.handle_mut());
+            value.to_es_value(cx, value_root.handle_mut());
+            maps_sets::map_set(
+                cx,
+                map_root.handle(),
+                key_root.handle(),
+                value_root.handle(),
+            )
+            .expect("maps_sets::map_set failed");
+        }
+        let mut rval = rval;
+        rval.set(ObjectValue(*map_root));
+    }
+
+    fn is_map(&self) -> bool {
+        true
+    }
+
+    fn get_map(&self) -> &Vec<(EsValueFacade, EsValueFacade)> {
+        &self.entries
+    }
+}
+
+/// the values of a Set in insertion order
+struct EsSetValue {
+    values: Vec<EsValueFacade>,
+}
+
+impl EsValueConvertible for EsSetValue {
+    fn to_js_value(&self, cx: *mut JSContext, rval: MutableHandleValue) {
+        rooted!(in (cx) let mut set_root = NULL_JSOBJECT);
+        maps_sets::new_set(cx, set_root.handle_mut());
+        for value in &self.values {
+            rooted!(in (cx) let mut value_root = UndefinedValue());
+            value.to_es_value(cx, value_root.handle_mut());
+            maps_sets::set_add(cx, set_root.handle(), value_root.handle())
+                .expect("maps_sets::set_add failed");
+        }
+        let mut rval = rval;
+        rval.set(ObjectValue(*set_root));
+    }
+
+    fn is_set(&self) -> bool {
+        true
+    }
+
+    fn get_set(&self) -> &Vec<EsValueFacade> {
+        &self.values
     }
 }
 
@@ -312,8 +780,14 @@ impl EsValueConvertible for CachedJSPromise {
             panic!("you really should not wait for promises in a RT's event queue thread");
         }
 
-        let rx = self.opt_receiver.as_ref().expect("not a waiting promise");
-        rx.recv_timeout(timeout)
+        self.result_state.wait_timeout(timeout)
+    }
+
+    fn get_promise_future(&self) -> EsPromiseFuture {
+        EsPromiseFuture {
+            _esvf: None,
+            state: Some(self.result_state.clone()),
+        }
     }
 }
 
@@ -448,7 +922,7 @@ impl CachedJSFunction {
             );
 
             if res2.is_ok() {
-                Ok(EsValueFacade::new_v(cx, rval.handle()))
+                EsValueFacade::try_new_v(cx, rval.handle())
             } else {
                 Err(res2.err().unwrap())
             }
@@ -616,11 +1090,210 @@ impl EsValueFacade {
         EsUndefinedValue {}.to_es_value_facade()
     }
 
+    /// create a new EsValueFacade representing a null value
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync("this.is_null = function(a){return a === null;};", "test_null.es").ok().expect("script failed");
+    /// let esvf = rt.call_sync(vec![], "is_null", vec![EsValueFacade::null()]).ok().expect("call failed");
+    /// assert!(esvf.get_boolean());
+    /// ```
+    pub fn null() -> Self {
+        EsNullValue {}.to_es_value_facade()
+    }
+
     /// create a new EsValueFacade representing a float
     pub fn new_f64(num: f64) -> Self {
         num.to_es_value_facade()
     }
 
+    /// create a new EsValueFacade representing a BigInt
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync("this.next_id = function(id){return id + 1n;};", "test_big_int.es").ok().expect("script failed");
+    /// let esvf = rt.call_sync(vec![], "next_id", vec![EsValueFacade::new_big_int_i64(9_007_199_254_740_993)])
+    ///     .ok().expect("call failed");
+    /// assert_eq!(esvf.get_big_int_i64().ok().unwrap(), 9_007_199_254_740_994);
+    /// ```
+    pub fn new_big_int_i64(num: i64) -> Self {
+        EsBigIntValue {
+            value: num.to_string(),
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a BigInt
+    pub fn new_big_int_u64(num: u64) -> Self {
+        EsBigIntValue {
+            value: num.to_string(),
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a BigInt
+    pub fn new_big_int_i128(num: i128) -> Self {
+        EsBigIntValue {
+            value: num.to_string(),
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a BigInt
+    pub fn new_big_int_u128(num: u128) -> Self {
+        EsBigIntValue {
+            value: num.to_string(),
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a BigInt from its decimal representation
+    /// this fails if the str is not a valid integer
+    pub fn new_big_int_str(s: &str) -> Result<Self, EsErrorInfo> {
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(EsErrorInfo::new(
+                format!("{} is not a valid BigInt", s),
+                "".to_string(),
+                0,
+                0,
+            ));
+        }
+        // normalize so the str is equal to what BigInt.prototype.toString() would produce
+        let trimmed = digits.trim_start_matches('0');
+        let value = if trimmed.is_empty() {
+            "0".to_string()
+        } else if digits.len() < s.len() {
+            format!("-{}", trimmed)
+        } else {
+            trimmed.to_string()
+        };
+        Ok(EsBigIntValue { value }.to_es_value_facade())
+    }
+
+    /// create a new EsValueFacade representing a TypedArray
+    pub fn new_typed_array(arr: EsTypedArray) -> Self {
+        arr.to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing an Int8Array
+    pub fn new_int8_array(vec: Vec<i8>) -> Self {
+        EsTypedArray::Int8(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Uint8Array
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync("this.reverse = function(arr){return arr.reverse();};", "test_uint8_array.es").ok().expect("script failed");
+    /// let esvf = rt.call_sync(vec![], "reverse", vec![EsValueFacade::new_uint8_array(vec![1, 2, 3])])
+    ///     .ok().expect("call failed");
+    /// assert_eq!(esvf.get_uint8_array(), &vec![3, 2, 1]);
+    /// ```
+    pub fn new_uint8_array(vec: Vec<u8>) -> Self {
+        EsTypedArray::Uint8(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Uint8ClampedArray
+    pub fn new_uint8_clamped_array(vec: Vec<u8>) -> Self {
+        EsTypedArray::Uint8Clamped(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing an Int16Array
+    pub fn new_int16_array(vec: Vec<i16>) -> Self {
+        EsTypedArray::Int16(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Uint16Array
+    pub fn new_uint16_array(vec: Vec<u16>) -> Self {
+        EsTypedArray::Uint16(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing an Int32Array
+    pub fn new_int32_array(vec: Vec<i32>) -> Self {
+        EsTypedArray::Int32(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Uint32Array
+    pub fn new_uint32_array(vec: Vec<u32>) -> Self {
+        EsTypedArray::Uint32(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Float32Array
+    pub fn new_float32_array(vec: Vec<f32>) -> Self {
+        EsTypedArray::Float32(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Float64Array
+    pub fn new_float64_array(vec: Vec<f64>) -> Self {
+        EsTypedArray::Float64(vec).to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing an ArrayBuffer
+    pub fn new_array_buffer(bytes: Vec<u8>) -> Self {
+        EsArrayBufferValue { bytes }.to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Date
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync("this.next_day = function(d){return new Date(d.getTime() + 86400000);};", "test_date.es").ok().expect("script failed");
+    /// let esvf = rt.call_sync(vec![], "next_day", vec![EsValueFacade::new_date(UNIX_EPOCH)])
+    ///     .ok().expect("call failed");
+    /// assert_eq!(esvf.get_date(), Some(UNIX_EPOCH + Duration::from_secs(86400)));
+    /// ```
+    pub fn new_date(time: SystemTime) -> Self {
+        EsDateValue {
+            msec: dates::system_time_to_msec(time),
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing an Error, the name, message, location, stack and cause of the
+    /// EsErrorInfo are set on the Error (an empty name becomes "Error")
+    pub fn new_error(err: EsErrorInfo) -> Self {
+        EsErrorInfo {
+            thrown_value: None,
+            ..err
+        }
+        .to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Map, unlike new_obj the keys may be of any type
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// rt.eval_sync("this.get_name = function(m){return m.get(1);};", "test_new_map.es").ok().expect("script failed");
+    /// let map = EsValueFacade::new_map(vec![(EsValueFacade::new_i32(1), EsValueFacade::new_str("one".to_string()))]);
+    /// let esvf = rt.call_sync(vec![], "get_name", vec![map]).ok().expect("call failed");
+    /// assert_eq!(esvf.get_string(), "one");
+    /// ```
+    pub fn new_map(entries: Vec<(EsValueFacade, EsValueFacade)>) -> Self {
+        EsMapValue { entries }.to_es_value_facade()
+    }
+
+    /// create a new EsValueFacade representing a Set
+    pub fn new_set(values: Vec<EsValueFacade>) -> Self {
+        EsSetValue { values }.to_es_value_facade()
+    }
+
     /// create a new EsValueFacade representing a basic object with properties as defined in the HashMap
     pub fn new_obj(props: HashMap<String, EsValueFacade>) -> Self {
         props.to_es_value_facade()
@@ -677,46 +1350,144 @@ impl EsValueFacade {
         RustPromise::new_esvf(resolver)
     }
 
+    /// convert a value to an EsValueFacade, a value which could not be converted is logged and becomes
+    /// undefined, use try_new_v() where the error can be passed on
     pub(crate) fn new_v(context: *mut JSContext, val_handle: HandleValue) -> Self {
+        Self::try_new_v(context, val_handle).unwrap_or_else(|err| {
+            log::error!("could not convert value to an EsValueFacade: {}", err);
+            EsUndefinedValue {}.to_es_value_facade()
+        })
+    }
+
+    /// convert a value to an EsValueFacade, this fails when a value could not be read, e.g. a Map or Set which
+    /// could not be iterated
+    pub(crate) fn try_new_v(
+        context: *mut JSContext,
+        val_handle: HandleValue,
+    ) -> Result<Self, EsErrorInfo> {
         let val: JSVal = *val_handle;
 
         trace!("EsValueFacade::new_v");
 
         if val.is_boolean() {
             trace!("EsValueFacade::new_v -> boolean");
-            val.to_boolean().to_es_value_facade()
+            Ok(val.to_boolean().to_es_value_facade())
         } else if val.is_int32() {
             trace!("EsValueFacade::new_v -> int32");
-            val.to_int32().to_es_value_facade()
+            Ok(val.to_int32().to_es_value_facade())
         } else if val.is_double() {
             trace!("EsValueFacade::new_v -> double");
-            val.to_number().to_es_value_facade()
+            Ok(val.to_number().to_es_value_facade())
         } else if val.is_string() {
             trace!("EsValueFacade::new_v -> string");
-            jsapi_utils::es_value_to_str(context, val)
+            Ok(jsapi_utils::es_value_to_str(context, val)
                 .expect("could not convert jsval to string")
-                .to_es_value_facade()
+                .to_es_value_facade())
+        } else if big_ints::is_big_int(context, val_handle) {
+            trace!("EsValueFacade::new_v -> BigInt");
+            Ok(EsBigIntValue {
+                value: big_ints::as_string(context, val_handle)?,
+            }
+            .to_es_value_facade())
         } else if val.is_object() {
             trace!("EsValueFacade::new_v -> object");
             let obj: *mut JSObject = val.to_object();
-            Self::new_v_from_object(context, obj)
+            Self::try_new_v_from_object(context, obj)
         } else if val.is_null() {
             trace!("EsValueFacade::new_v -> null");
-            // todo impl EsNull
-            EsUndefinedValue {}.to_es_value_facade()
+            Ok(EsNullValue {}.to_es_value_facade())
         } else if val.is_undefined() {
             trace!("EsValueFacade::new_v -> undefined");
-            EsUndefinedValue {}.to_es_value_facade()
+            Ok(EsUndefinedValue {}.to_es_value_facade())
         } else {
             trace!("EsValueFacade::new_v -> unknown");
-            EsUndefinedValue {}.to_es_value_facade()
+            Ok(EsUndefinedValue {}.to_es_value_facade())
         }
     }
 
-    fn new_v_from_object(context: *mut JSContext, obj: *mut JSObject) -> Self {
+    fn try_new_v_from_object(
+        context: *mut JSContext,
+        obj: *mut JSObject,
+    ) -> Result<Self, EsErrorInfo> {
         rooted!(in(context) let obj_root = obj);
 
-        if object_is_array(context, obj_root.handle()) {
+        if dates::is_date(context, obj_root.handle()) {
+            trace!("EsValueFacade::new_v -> object -> Date");
+            Ok(EsDateValue {
+                msec: dates::get_msec_since_epoch(context, obj_root.handle())
+                    .expect("could not get time of Date"),
+            }
+            .to_es_value_facade())
+        } else if errors::is_error(context, obj_root.handle()) {
+            trace!("EsValueFacade::new_v -> object -> Error");
+            // the cause chain is limited to a max depth so errors which cause each other don't recurse forever
+            rooted!(in (context) let err_val_root = ObjectValue(obj));
+            Ok(
+                jsapi_utils::error_info_from_value(context, err_val_root.handle())
+                    .to_es_value_facade(),
+            )
+        } else if maps_sets::is_map(context, obj_root.handle()) {
+            trace!("EsValueFacade::new_v -> object -> Map");
+            rooted!(in (context) let global_root = unsafe { CurrentGlobalOrNull(context) });
+            let mut entries = vec![];
+            let mut entry_err = None;
+            maps_sets::for_each_map_entry(
+                context,
+                global_root.handle(),
+                obj_root.handle(),
+                |key, value| {
+                    if entry_err.is_some() {
+                        return;
+                    }
+                    let entry_res = EsValueFacade::try_new_v(context, key).and_then(|key_esvf| {
+                        EsValueFacade::try_new_v(context, value)
+                            .map(|value_esvf| (key_esvf, value_esvf))
+                    });
+                    match entry_res {
+                        Ok(entry) => entries.push(entry),
+                        Err(err) => entry_err = Some(err),
+                    }
+                },
+            )?;
+            match entry_err {
+                Some(err) => Err(err),
+                None => Ok(EsMapValue { entries }.to_es_value_facade()),
+            }
+        } else if maps_sets::is_set(context, obj_root.handle()) {
+            trace!("EsValueFacade::new_v -> object -> Set");
+            rooted!(in (context) let global_root = unsafe { CurrentGlobalOrNull(context) });
+            let mut values = vec![];
+            let mut value_err = None;
+            maps_sets::for_each_set_value(
+                context,
+                global_root.handle(),
+                obj_root.handle(),
+                |value| {
+                    if value_err.is_some() {
+                        return;
+                    }
+                    match EsValueFacade::try_new_v(context, value) {
+                        Ok(value_esvf) => values.push(value_esvf),
+                        Err(err) => value_err = Some(err),
+                    }
+                },
+            )?;
+            match value_err {
+                Some(err) => Err(err),
+                None => Ok(EsSetValue { values }.to_es_value_facade()),
+            }
+        } else if let Some(typed_array) = EsTypedArray::new_from_object(context, obj_root.handle())
+        {
+            trace!("EsValueFacade::new_v -> object -> TypedArray");
+            Ok(typed_array.to_es_value_facade())
+        } else if ArrayBuffer::is_instance(obj) {
+            trace!("EsValueFacade::new_v -> object -> ArrayBuffer");
+            Ok(EsArrayBufferValue {
+                bytes: ArrayBuffer::convert_to_vec(context, obj_root.handle())
+                    .expect("could not convert ArrayBuffer"),
+            }
+            .to_es_value_facade())
+        } else if object_is_array(context, obj_root.handle()) {
             trace!("EsValueFacade::new_v -> object -> array");
             let mut vals = vec![];
             // add vals
@@ -732,18 +1503,22 @@ impl EsValueFacade {
                         get_res.err().unwrap().err_msg()
                     );
                 }
-                vals.push(EsValueFacade::new_v(context, arr_element_root.handle()));
+                vals.push(EsValueFacade::try_new_v(
+                    context,
+                    arr_element_root.handle(),
+                )?);
             }
 
-            vals.to_es_value_facade()
+            Ok(vals.to_es_value_facade())
         } else if jsapi_utils::promises::object_is_promise(obj_root.handle()) {
             trace!("EsValueFacade::new_v -> object -> promise");
 
             let cached_prom_id =
                 spidermonkeyruntimewrapper::register_cached_object(context, *obj_root);
 
-            let (tx, rx) = channel();
-            let tx2 = tx.clone();
+            let result_state = Arc::new(PromiseResultState::default());
+            let then_state = result_state.clone();
+            let catch_state = result_state.clone();
             assert!(jsapi_utils::promises::add_promise_reactions_callbacks(
                 context,
                 obj_root.handle(),
@@ -752,15 +1527,8 @@ impl EsValueFacade {
                         // promsie was resolved
                         let resolution = args.remove(0);
                         let res_esvf = EsValueFacade::new_v(cx, resolution);
-
-                        match tx.send(Ok(res_esvf)) {
-                            Ok(_) => Ok(()),
-                            // todo, does not include error (which is "sending on a closed channel") which is not ASCII and thus fails the error handler
-                            Err(e) => {
-                                debug!("send res error: {}", e);
-                                Err("send res error".to_string())
-                            }
-                        }
+                        then_state.settle(Ok(res_esvf));
+                        Ok(())
                     }
                 ),
                 Some(
@@ -768,33 +1536,23 @@ impl EsValueFacade {
                         // promsie was rejected
                         let rejection = args.remove(0);
                         let rej_esvf = EsValueFacade::new_v(cx, rejection);
-
-                        match tx2.send(Err(rej_esvf)) {
-                            Ok(_) => Ok(()),
-                            // todo, does not include error (which is "sending on a closed channel") which is not ASCII and thus fails the error handler
-                            Err(e) => {
-                                debug!("send rejection error: {}", e);
-                                Err("send rejection error".to_string())
-                            }
-                        }
-                        // release epr
+                        catch_state.settle(Err(rej_esvf));
+                        Ok(())
                     }
                 )
             ));
 
-            let opt_receiver = Some(rx);
-
             let rti_ref = spidermonkeyruntimewrapper::SM_RT.with(|sm_rt_rc| {
                 let sm_rt: &SmRuntime = &*sm_rt_rc.borrow();
                 sm_rt.clone_esrt_inner()
             });
             let rmev: CachedJSPromise = CachedJSPromise {
                 cached_obj_id: cached_prom_id,
-                opt_receiver,
+                result_state,
                 rti_ref,
             };
 
-            rmev.to_es_value_facade()
+            Ok(rmev.to_es_value_facade())
         } else if jsapi_utils::functions::object_is_function(obj) {
             trace!("EsValueFacade::new_v -> object -> function");
             // wrap function in persistentrooted
@@ -808,7 +1566,7 @@ impl EsValueFacade {
                 cached_obj_id,
                 rti_ref,
             };
-            cf.to_es_value_facade()
+            Ok(cf.to_es_value_facade())
         } else {
             let mut map = HashMap::new();
             trace!("EsValueFacade::new_v -> object -> object");
@@ -831,10 +1589,10 @@ impl EsValueFacade {
                     );
                 }
 
-                let prop_esvf = EsValueFacade::new_v(context, prop_val_root.handle());
+                let prop_esvf = EsValueFacade::try_new_v(context, prop_val_root.handle())?;
                 map.insert(prop_name, prop_esvf);
             }
-            map.to_es_value_facade()
+            Ok(map.to_es_value_facade())
         }
     }
 
@@ -858,6 +1616,147 @@ impl EsValueFacade {
         self.convertible.get_bool()
     }
 
+    /// get the decimal representation of the BigInt value
+    pub fn get_big_int_string(&self) -> &str {
+        self.convertible.get_big_int_str()
+    }
+
+    /// get the BigInt value as an i64, this fails if the value does not fit in an i64
+    pub fn get_big_int_i64(&self) -> Result<i64, EsErrorInfo> {
+        big_ints::parse_big_int_str(self.get_big_int_string(), "i64")
+    }
+
+    /// get the BigInt value as a u64, this fails if the value does not fit in a u64
+    pub fn get_big_int_u64(&self) -> Result<u64, EsErrorInfo> {
+        big_ints::parse_big_int_str(self.get_big_int_string(), "u64")
+    }
+
+    /// get the BigInt value as an i128, this fails if the value does not fit in an i128
+    pub fn get_big_int_i128(&self) -> Result<i128, EsErrorInfo> {
+        big_ints::parse_big_int_str(self.get_big_int_string(), "i128")
+    }
+
+    /// get the BigInt value as a u128, this fails if the value does not fit in a u128
+    pub fn get_big_int_u128(&self) -> Result<u128, EsErrorInfo> {
+        big_ints::parse_big_int_str(self.get_big_int_string(), "u128")
+    }
+
+    /// get the value of a Date, this is None when the Date was invalid
+    pub fn get_date(&self) -> Option<SystemTime> {
+        dates::msec_to_system_time(self.convertible.get_date_msec())
+    }
+
+    /// get the properties of an Error
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let esvf_prom = rt.eval_sync("Promise.reject(new TypeError('not a number'));", "test_get_error.es")
+    ///     .ok().expect("script failed");
+    /// let rejection = esvf_prom.get_promise_result_blocking(Duration::from_secs(1))
+    ///     .ok().expect("promise timed out")
+    ///     .err().expect("promise was not rejected");
+    /// assert_eq!(rejection.get_error().name, "TypeError");
+    /// assert_eq!(rejection.get_error().message, "not a number");
+    /// ```
+    pub fn get_error(&self) -> &EsErrorInfo {
+        self.convertible.get_error()
+    }
+
+    /// get the entries of a Map in insertion order
+    pub fn get_map(&self) -> &Vec<(EsValueFacade, EsValueFacade)> {
+        self.convertible.get_map()
+    }
+
+    /// get the values of a Set in insertion order
+    pub fn get_set(&self) -> &Vec<EsValueFacade> {
+        self.convertible.get_set()
+    }
+
+    /// get the contents of the TypedArray
+    pub fn get_typed_array(&self) -> &EsTypedArray {
+        self.convertible.get_typed_array()
+    }
+
+    /// get the contents of an Int8Array, this panics if the value is another kind of TypedArray
+    pub fn get_int8_array(&self) -> &Vec<i8> {
+        match self.get_typed_array() {
+            EsTypedArray::Int8(v) => v,
+            _ => panic!("i am not an Int8Array"),
+        }
+    }
+
+    /// get the contents of a Uint8Array, this panics if the value is another kind of TypedArray
+    pub fn get_uint8_array(&self) -> &Vec<u8> {
+        match self.get_typed_array() {
+            EsTypedArray::Uint8(v) => v,
+            _ => panic!("i am not a Uint8Array"),
+        }
+    }
+
+    /// get the contents of a Uint8ClampedArray, this panics if the value is another kind of TypedArray
+    pub fn get_uint8_clamped_array(&self) -> &Vec<u8> {
+        match self.get_typed_array() {
+            EsTypedArray::Uint8Clamped(v) => v,
+            _ => panic!("i am not a Uint8ClampedArray"),
+        }
+    }
+
+    /// get the contents of an Int16Array, this panics if the value is another kind of TypedArray
+    pub fn get_int16_array(&self) -> &Vec<i16> {
+        match self.get_typed_array() {
+            EsTypedArray::Int16(v) => v,
+            _ => panic!("i am not an Int16Array"),
+        }
+    }
+
+    /// get the contents of a Uint16Array, this panics if the value is another kind of TypedArray
+    pub fn get_uint16_array(&self) -> &Vec<u16> {
+        match self.get_typed_array() {
+            EsTypedArray::Uint16(v) => v,
+            _ => panic!("i am not a Uint16Array"),
+        }
+    }
+
+    /// get the contents of an Int32Array, this panics if the value is another kind of TypedArray
+    pub fn get_int32_array(&self) -> &Vec<i32> {
+        match self.get_typed_array() {
+            EsTypedArray::Int32(v) => v,
+            _ => panic!("i am not an Int32Array"),
+        }
+    }
+
+    /// get the contents of a Uint32Array, this panics if the value is another kind of TypedArray
+    pub fn get_uint32_array(&self) -> &Vec<u32> {
+        match self.get_typed_array() {
+            EsTypedArray::Uint32(v) => v,
+            _ => panic!("i am not a Uint32Array"),
+        }
+    }
+
+    /// get the contents of a Float32Array, this panics if the value is another kind of TypedArray
+    pub fn get_float32_array(&self) -> &Vec<f32> {
+        match self.get_typed_array() {
+            EsTypedArray::Float32(v) => v,
+            _ => panic!("i am not a Float32Array"),
+        }
+    }
+
+    /// get the contents of a Float64Array, this panics if the value is another kind of TypedArray
+    pub fn get_float64_array(&self) -> &Vec<f64> {
+        match self.get_typed_array() {
+            EsTypedArray::Float64(v) => v,
+            _ => panic!("i am not a Float64Array"),
+        }
+    }
+
+    /// get the contents of the ArrayBuffer
+    pub fn get_array_buffer(&self) -> &Vec<u8> {
+        self.convertible.get_array_buffer()
+    }
+
     /// check if this esvf was a promise which was returned from the script engine
     pub fn is_promise(&self) -> bool {
         self.convertible.is_promise()
@@ -890,6 +1789,29 @@ impl EsValueFacade {
         self.convertible.await_promise_blocking(timeout)
     }
 
+    /// convert a promise which was returned from the script engine into a Future
+    /// the Future resolves from the event loop of the runtime so no thread is parked while waiting
+    /// if this esvf is not a promise the Future resolves to an Err immediately
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    /// use futures::executor::block_on;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let esvf_prom = rt.eval_sync(
+    ///     "let p = new Promise((resolve, reject) => {setImmediate(() => {resolve(123);});}); p;",
+    ///     "test_into_future.es").ok().expect("script failed");
+    /// let esvf = block_on(esvf_prom.into_future()).ok().expect("promise was rejected");
+    /// assert_eq!(esvf.get_i32(), 123);
+    /// ```
+    pub fn into_future(self) -> EsPromiseFuture {
+        let mut fut = self.convertible.get_promise_future();
+        if fut.state.is_some() {
+            fut._esvf = Some(self);
+        }
+        fut
+    }
+
     /// get the value as a Map of EsValueFacades, this works when the value was an object in the script engine
     /// # Example
     /// ```no_run
@@ -941,6 +1863,16 @@ impl EsValueFacade {
         self.convertible.invoke_function(args)
     }
 
+    /// check if the value is null
+    pub fn is_null(&self) -> bool {
+        self.convertible.is_null()
+    }
+
+    /// check if the value is undefined
+    pub fn is_undefined(&self) -> bool {
+        self.convertible.is_undefined()
+    }
+
     /// check if the value is a String
     pub fn is_string(&self) -> bool {
         self.convertible.is_str()
@@ -956,6 +1888,41 @@ impl EsValueFacade {
         self.convertible.is_f64()
     }
 
+    /// check if the value is a BigInt
+    pub fn is_big_int(&self) -> bool {
+        self.convertible.is_big_int()
+    }
+
+    /// check if the value is a TypedArray
+    pub fn is_typed_array(&self) -> bool {
+        self.convertible.is_typed_array()
+    }
+
+    /// check if the value is an ArrayBuffer
+    pub fn is_array_buffer(&self) -> bool {
+        self.convertible.is_array_buffer()
+    }
+
+    /// check if the value is a Date
+    pub fn is_date(&self) -> bool {
+        self.convertible.is_date()
+    }
+
+    /// check if the value is an Error
+    pub fn is_error(&self) -> bool {
+        self.convertible.is_error()
+    }
+
+    /// check if the value is a Map
+    pub fn is_map(&self) -> bool {
+        self.convertible.is_map()
+    }
+
+    /// check if the value is a Set
+    pub fn is_set(&self) -> bool {
+        self.convertible.is_set()
+    }
+
     /// check if the value is a bool
     pub fn is_boolean(&self) -> bool {
         self.convertible.is_bool()
@@ -1017,7 +1984,7 @@ mod tests {
     use crate::esvaluefacade::EsValueFacade;
     use crate::jsapi_utils::EsErrorInfo;
     use std::collections::HashMap;
-    use std::time::Duration;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     #[allow(clippy::float_cmp)]
@@ -1129,6 +2096,39 @@ mod tests {
         assert_eq!(esvf_prom_resolved.get_i32().clone(), 123 as i32);
     }
 
+    #[test]
+    fn test_into_future() {
+        log::info!("test: test_into_future");
+
+        let rt = init_test_runtime();
+        let esvf_prom = rt
+            .eval_sync(
+                "new Promise((resolve, reject) => {setImmediate(() => {resolve(246);});});",
+                "test_into_future.es",
+            )
+            .ok()
+            .unwrap();
+        assert!(esvf_prom.is_promise());
+        // EsValueFacade does not implement Debug so the rejection can't be part of the panic message
+        let esvf_prom_resolved = futures::executor::block_on(esvf_prom.into_future())
+            .ok()
+            .expect("promise was rejected");
+
+        assert_eq!(esvf_prom_resolved.get_i32(), 246);
+
+        let esvf_prom = rt
+            .eval_sync(
+                "new Promise((resolve, reject) => {setImmediate(() => {reject('nope');});});",
+                "test_into_future2.es",
+            )
+            .ok()
+            .unwrap();
+        let rejection = futures::executor::block_on(esvf_prom.into_future())
+            .err()
+            .expect("promise was not rejected");
+        assert_eq!(rejection.get_string(), "nope");
+    }
+
     #[test]
     fn test_wait_for_prom2() {
         log::info!("test: test_wait_for_prom2");
@@ -1288,6 +2288,51 @@ mod tests {
         assert_eq!(res_esvf.get_string(), "hello");
     }
 
+    #[test]
+    fn test_null() {
+        log::info!("test: test_null");
+        let rt = init_test_runtime();
+
+        let esvf = rt
+            .eval_sync("({a: null, b: undefined, c: [null, 1]});", "test_null_1.es")
+            .ok()
+            .unwrap();
+        let map = esvf.get_object();
+        assert!(map.get("a").unwrap().is_null());
+        assert!(!map.get("a").unwrap().is_undefined());
+        assert!(map.get("b").unwrap().is_undefined());
+        assert!(!map.get("b").unwrap().is_null());
+        let arr = map.get("c").unwrap().get_array();
+        assert!(arr[0].is_null());
+        assert!(!arr[1].is_null());
+
+        rt.eval_sync(
+            "this.test_null_types = function(a, obj, arr){\
+             return [a === null, obj.n === null, obj.u === undefined, arr[0] === null].join(',');\
+             };",
+            "test_null_2.es",
+        )
+        .ok()
+        .unwrap();
+
+        let mut props = HashMap::new();
+        props.insert("n".to_string(), EsValueFacade::null());
+        props.insert("u".to_string(), EsValueFacade::undefined());
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_null_types",
+                vec![
+                    EsValueFacade::null(),
+                    EsValueFacade::new_obj(props),
+                    EsValueFacade::new_array(vec![EsValueFacade::null()]),
+                ],
+            )
+            .ok()
+            .unwrap();
+        assert_eq!(res.get_string(), "true,true,true,true");
+    }
+
     #[test]
     fn test_prepped_prom() {
         log::info!("test: test_prepped_prom");
@@ -1367,4 +2412,349 @@ mod tests {
 
         assert_eq!("123foo", res_str);
     }
+
+    #[test]
+    fn test_big_int() {
+        log::info!("test: test_big_int");
+        let rt = init_test_runtime();
+
+        let esvf = rt
+            .eval_sync("(12345678901234567890n);", "test_big_int_1.es")
+            .ok()
+            .unwrap();
+        assert!(esvf.is_big_int());
+        assert!(!esvf.is_f64());
+        assert_eq!(esvf.get_big_int_string(), "12345678901234567890");
+        assert_eq!(
+            esvf.get_big_int_u64().ok().unwrap(),
+            12_345_678_901_234_567_890
+        );
+        assert!(esvf.get_big_int_i64().is_err());
+
+        rt.eval_sync(
+            "this.test_big_int_inc = function(a){return [typeof a, a + 1n];};",
+            "test_big_int_2.es",
+        )
+        .ok()
+        .unwrap();
+
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_big_int_inc",
+                vec![EsValueFacade::new_big_int_i64(i64::MAX - 1)],
+            )
+            .expect("call failed");
+        let arr = res.get_array();
+        assert_eq!(arr[0].get_string(), "bigint");
+        assert_eq!(arr[1].get_big_int_i64().ok().unwrap(), i64::MAX);
+
+        let esvf = EsValueFacade::new_big_int_str("-00042").ok().unwrap();
+        assert_eq!(esvf.get_big_int_string(), "-42");
+        assert!(EsValueFacade::new_big_int_str("4.2").is_err());
+        assert!(EsValueFacade::new_big_int_str("-").is_err());
+    }
+
+    #[test]
+    fn test_typed_arrays() {
+        log::info!("test: test_typed_arrays");
+        let rt = init_test_runtime();
+
+        let esvf = rt
+            .eval_sync(
+                "({u8: new Uint8Array([1, 2, 255]), f64: new Float64Array([1.5, -2]), \
+                 buf: new Uint16Array([258]).buffer, sub: new Int32Array([1, 2, 3, 4]).subarray(1, 3)});",
+                "test_typed_arrays_1.es",
+            )
+            .ok()
+            .unwrap();
+        let map = esvf.get_object();
+        let u8_esvf = map.get("u8").unwrap();
+        assert!(u8_esvf.is_typed_array());
+        assert!(!u8_esvf.is_object());
+        assert_eq!(u8_esvf.get_uint8_array(), &vec![1, 2, 255]);
+        assert_eq!(
+            map.get("f64").unwrap().get_float64_array(),
+            &vec![1.5, -2.0]
+        );
+        assert!(map.get("buf").unwrap().is_array_buffer());
+        assert_eq!(map.get("buf").unwrap().get_array_buffer().len(), 2);
+        assert_eq!(map.get("sub").unwrap().get_int32_array(), &vec![2, 3]);
+
+        rt.add_global_sync_function("test_typed_arrays_double", |args: Vec<EsValueFacade>| {
+            let bytes = args
+                .get(0)
+                .expect("did not get a first arg")
+                .get_uint8_array();
+            Ok(EsValueFacade::new_uint8_array(
+                bytes.iter().map(|b| b.wrapping_mul(2)).collect(),
+            ))
+        });
+
+        rt.eval_sync(
+            "this.test_typed_arrays_fn = function(arr, buf){\
+             let res = test_typed_arrays_double(arr);\
+             return [res instanceof Uint8Array, res.join(','), buf instanceof ArrayBuffer, buf.byteLength].join(';');\
+             };",
+            "test_typed_arrays_2.es",
+        )
+        .ok()
+        .unwrap();
+
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_typed_arrays_fn",
+                vec![
+                    EsValueFacade::new_uint8_array(vec![1, 2, 3]),
+                    EsValueFacade::new_array_buffer(vec![0; 16]),
+                ],
+            )
+            .expect("call failed");
+        assert_eq!(res.get_string(), "true;2,4,6;true;16");
+    }
+
+    #[test]
+    fn test_big_int_typed_arrays() {
+        log::info!("test: test_big_int_typed_arrays");
+        let rt = init_test_runtime();
+
+        // BigInt64Array and BigUint64Array are not supported as TypedArray and are converted like an object
+        let esvf = rt
+            .eval_sync(
+                "(new BigInt64Array([1n, -2n]));",
+                "test_big_int_typed_arrays.es",
+            )
+            .expect("script failed");
+        assert!(!esvf.is_typed_array());
+        assert!(esvf.is_object());
+        assert_eq!(
+            esvf.get_object()
+                .get("1")
+                .expect("no element 1")
+                .get_big_int_i64()
+                .expect("not an i64"),
+            -2
+        );
+    }
+
+    #[test]
+    fn test_date() {
+        log::info!("test: test_date");
+        let rt = init_test_runtime();
+
+        let esvf = rt
+            .eval_sync("(new Date(1500000000123));", "test_date_1.es")
+            .ok()
+            .unwrap();
+        assert!(esvf.is_date());
+        assert!(!esvf.is_object());
+        assert_eq!(
+            esvf.get_date(),
+            Some(UNIX_EPOCH + Duration::from_millis(1_500_000_000_123))
+        );
+
+        let esvf = rt
+            .eval_sync("(new Date('not a date'));", "test_date_2.es")
+            .ok()
+            .unwrap();
+        assert!(esvf.is_date());
+        assert_eq!(esvf.get_date(), None);
+
+        rt.eval_sync(
+            "this.test_date_year = function(d){return d instanceof Date ? d.getUTCFullYear() : -1;};",
+            "test_date_3.es",
+        )
+        .ok()
+        .unwrap();
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_date_year",
+                vec![EsValueFacade::new_date(
+                    UNIX_EPOCH + Duration::from_secs(365 * 24 * 60 * 60),
+                )],
+            )
+            .expect("call failed");
+        assert_eq!(res.get_i32(), 1971);
+    }
+
+    #[test]
+    fn test_error() {
+        log::info!("test: test_error");
+        let rt = init_test_runtime();
+
+        let esvf_prom = rt
+            .eval_sync(
+                "new Promise((resolve, reject) => {setImmediate(() => {\
+                 reject(new RangeError('too big', {cause: 'because'}));\
+                 });});",
+                "test_error_1.es",
+            )
+            .expect("script failed");
+        let rejection = esvf_prom
+            .get_promise_result_blocking(Duration::from_secs(1))
+            .expect("promise timed out")
+            .err()
+            .expect("promise was not rejected");
+        assert!(rejection.is_error());
+        let err = rejection.get_error();
+        assert_eq!(err.name, "RangeError");
+        assert_eq!(err.message, "too big");
+        assert_eq!(err.filename, "test_error_1.es");
+        assert_eq!(err.lineno, 1);
+        assert!(!err.stack.is_empty());
+        assert_eq!(err.cause.as_ref().expect("no cause").message, "because");
+
+        rt.eval_sync(
+            "this.test_error_props = function(e){\
+             return [e instanceof Error, e.name, e.message, e.cause.message].join(',');\
+             };",
+            "test_error_2.es",
+        )
+        .ok()
+        .unwrap();
+        let mut err = EsErrorInfo::new("query failed".to_string(), "".to_string(), 0, 0);
+        err.name = "DbError".to_string();
+        let mut cause = EsErrorInfo::new("bad param".to_string(), "".to_string(), 0, 0);
+        cause.name = "TypeError".to_string();
+        err.cause = Some(Box::new(cause));
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_error_props",
+                vec![EsValueFacade::new_error(err)],
+            )
+            .expect("call failed");
+        assert_eq!(res.get_string(), "true,DbError,query failed,bad param");
+
+        // errors which cause each other should not recurse forever
+        let esvf = rt
+            .eval_sync(
+                "(function(){let a = new Error('a'); let b = new Error('b', {cause: a}); a.cause = b; return a;})();",
+                "test_error_3.es",
+            )
+            .expect("script failed");
+        let mut depth = 0;
+        let mut err = esvf.get_error();
+        while let Some(cause) = &err.cause {
+            err = cause;
+            depth += 1;
+        }
+        assert!(depth > 1 && depth <= 16);
+    }
+
+    #[test]
+    fn test_map_and_set() {
+        log::info!("test: test_map_and_set");
+        let rt = init_test_runtime();
+
+        let esvf = rt
+            .eval_sync(
+                "let m = new Map(); m.set(2, 'two'); m.set('a', {b: 1}); m.set(1, 'one'); \
+                 ({m, s: new Set(['x', 3, 'x'])});",
+                "test_map_and_set_1.es",
+            )
+            .ok()
+            .unwrap();
+        let map = esvf.get_object();
+        let m = map.get("m").unwrap();
+        assert!(m.is_map());
+        assert!(!m.is_object());
+        let entries = m.get_map();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].0.get_i32(), 2);
+        assert_eq!(entries[0].1.get_string(), "two");
+        assert_eq!(entries[1].0.get_string(), "a");
+        assert!(entries[1].1.is_object());
+        assert_eq!(entries[2].0.get_i32(), 1);
+
+        let s = map.get("s").unwrap();
+        assert!(s.is_set());
+        let values = s.get_set();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].get_string(), "x");
+        assert_eq!(values[1].get_i32(), 3);
+
+        rt.eval_sync(
+            "this.test_map_and_set_fn = function(m, s){\
+             return [m instanceof Map, m.get(42), m.size, s instanceof Set, s.has(true), s.size].join(',');\
+             };",
+            "test_map_and_set_2.es",
+        )
+        .ok()
+        .unwrap();
+        let res = rt
+            .call_sync(
+                vec![],
+                "test_map_and_set_fn",
+                vec![
+                    EsValueFacade::new_map(vec![
+                        (
+                            EsValueFacade::new_i32(42),
+                            EsValueFacade::new_str("answer".to_string()),
+                        ),
+                        (EsValueFacade::null(), EsValueFacade::new_bool(false)),
+                    ]),
+                    EsValueFacade::new_set(vec![EsValueFacade::new_bool(true)]),
+                ],
+            )
+            .expect("call failed");
+        assert_eq!(res.get_string(), "true,answer,2,true,true,1");
+    }
+
+    #[test]
+    fn test_map_and_set_with_altered_iterators() {
+        log::info!("test: test_map_and_set_with_altered_iterators");
+        // a dedicated runtime because the script alters globals
+        let rt = crate::esruntimebuilder::EsRuntimeBuilder::new().build();
+
+        let esvf = rt
+            .eval_sync(
+                "Array.from = function(){throw Error('no Array.from');};\
+                 Map.prototype[Symbol.iterator] = function(){throw Error('no Map iterator');};\
+                 Map.prototype.entries = Map.prototype[Symbol.iterator];\
+                 Set.prototype[Symbol.iterator] = function(){throw Error('no Set iterator');};\
+                 Set.prototype.values = Set.prototype[Symbol.iterator];\
+                 ({m: new Map([[1, 'one'], [2, 'two']]), s: new Set(['a', 'b'])});",
+                "test_map_and_set_with_altered_iterators.es",
+            )
+            .expect("script failed");
+        let map = esvf.get_object();
+        let entries = map.get("m").unwrap().get_map();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0.get_i32(), 1);
+        assert_eq!(entries[1].1.get_string(), "two");
+        let values = map.get("s").unwrap().get_set();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1].get_string(), "b");
+    }
+
+    #[test]
+    fn test_map_conversion_error() {
+        log::info!("test: test_map_conversion_error");
+        // a dedicated runtime because the script alters globals
+        let rt = crate::esruntimebuilder::EsRuntimeBuilder::new().build();
+
+        // a Map which contains an Error is still a Map
+        let esvf = rt
+            .eval_sync(
+                "new Map([['err', new Error('an error')]]);",
+                "test_map_conversion_error.es",
+            )
+            .expect("script failed");
+        assert!(esvf.is_map());
+        assert!(esvf.get_map()[0].1.is_error());
+
+        // a Map which can not be iterated is an error
+        let err = rt
+            .eval_sync(
+                "Object.defineProperty(Array.prototype, '0', {set: function(){throw Error('no index 0');}});\
+                 new Map([[1, 'one']]);",
+                "test_map_conversion_error.es",
+            )
+            .err()
+            .expect("conversion should fail");
+        assert!(err.err_msg().contains("no index 0"));
+    }
 }
@@ -0,0 +1,907 @@
+//! serde support for EsValueFacade
+//!
+//! any T: Serialize can be converted to an EsValueFacade with EsValueFacade::from_serde and any
+//! EsValueFacade can be converted to a T: DeserializeOwned with EsValueFacade::to_serde
+//!
+//! every rust type is always converted to the same script type, integers up to 32 bits become numbers and
+//! i64, u64, i128 and u128 always become a BigInt (even when the value would fit in a number), when converting
+//! back both numbers and BigInts are accepted for integer types
+//!
+//! # Example
+//! ```no_run
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use spidermonkey_runtime::esvaluefacade::EsValueFacade;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let rt = EsRuntimeBuilder::new().build();
+//! rt.eval_sync("this.older = function(user){user.age++; return user;};", "test_serde.es").ok().expect("script failed");
+//!
+//! let user = User{name: "Harry".to_string(), age: 41};
+//! let esvf = EsValueFacade::from_serde(&user).ok().expect("could not serialize");
+//! let res = rt.call_sync(vec![], "older", vec![esvf]).ok().expect("call failed");
+//! let older_user: User = res.to_serde().ok().expect("could not deserialize");
+//! assert_eq!(older_user.age, 42);
+//! ```
+
+use crate::esvaluefacade::EsValueFacade;
+use serde::de::value::StrDeserializer;
+use serde::de::{
+    DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, VariantAccess, Visitor,
+};
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize, Serializer};
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// the largest integer which can be represented by a js number without losing precision
+const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+/// error which is returned when a value could not be converted from or to an EsValueFacade
+#[derive(Debug, Clone)]
+pub struct EsSerdeError {
+    message: String,
+}
+
+impl EsSerdeError {
+    fn new(message: String) -> Self {
+        EsSerdeError { message }
+    }
+
+    /// get the description of the error
+    pub fn get_message(&self) -> &str {
+        self.message.as_str()
+    }
+}
+
+impl fmt::Display for EsSerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message.as_str())
+    }
+}
+
+impl std::error::Error for EsSerdeError {}
+
+impl serde::ser::Error for EsSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        EsSerdeError::new(msg.to_string())
+    }
+}
+
+impl serde::de::Error for EsSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        EsSerdeError::new(msg.to_string())
+    }
+}
+
+impl EsValueFacade {
+    /// create a new EsValueFacade based on a value which implements serde::Serialize
+    /// structs and maps become objects, sequences and tuples become arrays and None becomes null
+    pub fn from_serde<T: Serialize + ?Sized>(value: &T) -> Result<EsValueFacade, EsSerdeError> {
+        value.serialize(EsValueFacadeSerializer {})
+    }
+
+    /// convert this EsValueFacade to a value which implements serde::Deserialize
+    /// this fails with a descriptive EsSerdeError when the value does not match the type
+    pub fn to_serde<T: DeserializeOwned>(&self) -> Result<T, EsSerdeError> {
+        T::deserialize(EsValueFacadeDeserializer { esvf: self })
+    }
+}
+
+fn str_deserializer(s: &str) -> StrDeserializer<'_, EsSerdeError> {
+    s.into_deserializer()
+}
+
+fn new_variant(variant: &'static str, value: EsValueFacade) -> EsValueFacade {
+    let mut map = HashMap::new();
+    map.insert(variant.to_string(), value);
+    EsValueFacade::new_obj(map)
+}
+
+struct EsValueFacadeSerializer {}
+
+impl Serializer for EsValueFacadeSerializer {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVec;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_i32(v as i32))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_i32(v as i32))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_i32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_big_int_i64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_i32(v as i32))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_i32(v as i32))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        // u32 does not always fit in an i32 but it always fits in a number without losing precision
+        match i32::try_from(v) {
+            Ok(num) => Ok(EsValueFacade::new_i32(num)),
+            Err(_) => Ok(EsValueFacade::new_f64(v as f64)),
+        }
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_big_int_u64(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_big_int_i128(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_big_int_u128(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_f64(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_f64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_str(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_array(
+            v.iter()
+                .map(|b| EsValueFacade::new_i32(*b as i32))
+                .collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::null())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::null())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::null())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(EsValueFacade::new_str(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(new_variant(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SerializeVec {
+            variant: None,
+            vec: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SerializeVec {
+            variant: Some(variant),
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SerializeObject {
+            variant: None,
+            map: HashMap::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SerializeObject {
+            variant: Some(variant),
+            map: HashMap::new(),
+            next_key: None,
+        })
+    }
+}
+
+struct SerializeVec {
+    variant: Option<&'static str>,
+    vec: Vec<EsValueFacade>,
+}
+
+impl SerializeVec {
+    fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), EsSerdeError> {
+        self.vec.push(value.serialize(EsValueFacadeSerializer {})?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<EsValueFacade, EsSerdeError> {
+        let arr = EsValueFacade::new_array(self.vec);
+        Ok(match self.variant {
+            Some(variant) => new_variant(variant, arr),
+            None => arr,
+        })
+    }
+}
+
+impl SerializeSeq for SerializeVec {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for SerializeVec {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for SerializeVec {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for SerializeVec {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+struct SerializeObject {
+    variant: Option<&'static str>,
+    map: HashMap<String, EsValueFacade>,
+    next_key: Option<String>,
+}
+
+impl SerializeObject {
+    fn insert<T: ?Sized + Serialize>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), EsSerdeError> {
+        self.map
+            .insert(key, value.serialize(EsValueFacadeSerializer {})?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<EsValueFacade, EsSerdeError> {
+        let obj = EsValueFacade::new_obj(self.map);
+        Ok(match self.variant {
+            Some(variant) => new_variant(variant, obj),
+            None => obj,
+        })
+    }
+}
+
+impl SerializeMap for SerializeObject {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
+        let key_esvf = key.serialize(EsValueFacadeSerializer {})?;
+        let key_str = if key_esvf.is_string() {
+            key_esvf.get_string().to_string()
+        } else if key_esvf.is_i32() {
+            key_esvf.get_i32().to_string()
+        } else if key_esvf.is_f64() {
+            key_esvf.get_f64().to_string()
+        } else if key_esvf.is_boolean() {
+            key_esvf.get_boolean().to_string()
+        } else {
+            return Err(EsSerdeError::new(
+                "map keys must be strings, numbers or booleans".to_string(),
+            ));
+        };
+        self.next_key = Some(key_str);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self.next_key.take().ok_or_else(|| {
+            EsSerdeError::new("serialize_value called before serialize_key".to_string())
+        })?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for SerializeObject {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for SerializeObject {
+    type Ok = EsValueFacade;
+    type Error = EsSerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.finish()
+    }
+}
+
+struct EsValueFacadeDeserializer<'a> {
+    esvf: &'a EsValueFacade,
+}
+
+impl<'a> EsValueFacadeDeserializer<'a> {
+    fn unexpected(&self) -> Unexpected<'a> {
+        let esvf = self.esvf;
+        if esvf.is_null() || esvf.is_undefined() {
+            Unexpected::Unit
+        } else if esvf.is_boolean() {
+            Unexpected::Bool(esvf.get_boolean())
+        } else if esvf.is_i32() {
+            Unexpected::Signed(esvf.get_i32() as i64)
+        } else if esvf.is_f64() {
+            Unexpected::Float(esvf.get_f64())
+        } else if esvf.is_big_int() {
+            Unexpected::Other("BigInt")
+        } else if esvf.is_typed_array() {
+            Unexpected::Other("TypedArray")
+        } else if esvf.is_array_buffer() {
+            Unexpected::Other("ArrayBuffer")
+        } else if esvf.is_date() {
+            Unexpected::Other("Date")
+        } else if esvf.is_error() {
+            Unexpected::Other("Error")
+        } else if esvf.is_string() {
+            Unexpected::Str(esvf.get_string())
+        } else if esvf.is_array() || esvf.is_set() {
+            Unexpected::Seq
+        } else if esvf.is_object() || esvf.is_map() {
+            Unexpected::Map
+        } else if esvf.is_promise() {
+            Unexpected::Other("Promise")
+        } else if esvf.is_function() {
+            Unexpected::Other("function")
+        } else {
+            Unexpected::Other("unsupported value")
+        }
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for EsValueFacadeDeserializer<'a> {
+    type Error = EsSerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let esvf = self.esvf;
+        if esvf.is_null() || esvf.is_undefined() {
+            visitor.visit_unit()
+        } else if esvf.is_boolean() {
+            visitor.visit_bool(esvf.get_boolean())
+        } else if esvf.is_i32() {
+            visitor.visit_i32(esvf.get_i32())
+        } else if esvf.is_f64() {
+            let num = esvf.get_f64();
+            // integers which do not fit an i32 are represented as f64
+            if num.fract() == 0.0 && num.abs() <= MAX_SAFE_INTEGER as f64 {
+                visitor.visit_i64(num as i64)
+            } else {
+                visitor.visit_f64(num)
+            }
+        } else if esvf.is_big_int() {
+            // visit the smallest int type which fits so visitors for smaller ints still work
+            if let Ok(num) = esvf.get_big_int_i64() {
+                visitor.visit_i64(num)
+            } else if let Ok(num) = esvf.get_big_int_u64() {
+                visitor.visit_u64(num)
+            } else if let Ok(num) = esvf.get_big_int_i128() {
+                visitor.visit_i128(num)
+            } else if let Ok(num) = esvf.get_big_int_u128() {
+                visitor.visit_u128(num)
+            } else {
+                Err(EsSerdeError::new(format!(
+                    "BigInt {} does not fit in a u128",
+                    esvf.get_big_int_string()
+                )))
+            }
+        } else if esvf.is_string() {
+            visitor.visit_str(esvf.get_string())
+        } else if esvf.is_array() {
+            visitor.visit_seq(SeqAccessor {
+                iter: esvf.get_array().iter(),
+            })
+        } else if esvf.is_object() {
+            visitor.visit_map(MapAccessor {
+                iter: esvf.get_object().iter(),
+                value: None,
+            })
+        } else if esvf.is_map() {
+            visitor.visit_map(EntriesAccessor {
+                iter: esvf.get_map().iter(),
+                value: None,
+            })
+        } else if esvf.is_set() {
+            visitor.visit_seq(SeqAccessor {
+                iter: esvf.get_set().iter(),
+            })
+        } else {
+            Err(serde::de::Error::invalid_type(
+                self.unexpected(),
+                &"a value which can be deserialized",
+            ))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.esvf.is_null() || self.esvf.is_undefined() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let esvf = self.esvf;
+        if esvf.is_string() {
+            // unit variant
+            visitor.visit_enum(str_deserializer(esvf.get_string()))
+        } else if esvf.is_object() && esvf.get_object().len() == 1 {
+            let (variant, value) = esvf.get_object().iter().next().unwrap();
+            visitor.visit_enum(EnumAccessor {
+                variant: variant.as_str(),
+                value,
+            })
+        } else {
+            Err(serde::de::Error::invalid_type(
+                self.unexpected(),
+                &"a string or an object with a single property",
+            ))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccessor<'a> {
+    iter: std::slice::Iter<'a, EsValueFacade>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for SeqAccessor<'a> {
+    type Error = EsSerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.iter.next() {
+            Some(esvf) => seed
+                .deserialize(EsValueFacadeDeserializer { esvf })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapAccessor<'a> {
+    iter: hash_map::Iter<'a, String, EsValueFacade>,
+    value: Option<&'a EsValueFacade>,
+}
+
+impl<'de, 'a> MapAccess<'de> for MapAccessor<'a> {
+    type Error = EsSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(str_deserializer(key.as_str())).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(esvf) => seed.deserialize(EsValueFacadeDeserializer { esvf }),
+            None => Err(EsSerdeError::new(
+                "next_value_seed called before next_key_seed".to_string(),
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// like MapAccessor but for the entries of a Map which may have keys of any type
+struct EntriesAccessor<'a> {
+    iter: std::slice::Iter<'a, (EsValueFacade, EsValueFacade)>,
+    value: Option<&'a EsValueFacade>,
+}
+
+impl<'de, 'a> MapAccess<'de> for EntriesAccessor<'a> {
+    type Error = EsSerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(EsValueFacadeDeserializer { esvf: key })
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(esvf) => seed.deserialize(EsValueFacadeDeserializer { esvf }),
+            None => Err(EsSerdeError::new(
+                "next_value_seed called before next_key_seed".to_string(),
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccessor<'a> {
+    variant: &'a str,
+    value: &'a EsValueFacade,
+}
+
+impl<'de, 'a> EnumAccess<'de> for EnumAccessor<'a> {
+    type Error = EsSerdeError;
+    type Variant = VariantAccessor<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(str_deserializer(self.variant))?;
+        Ok((variant, VariantAccessor { esvf: self.value }))
+    }
+}
+
+struct VariantAccessor<'a> {
+    esvf: &'a EsValueFacade,
+}
+
+impl<'de, 'a> VariantAccess<'de> for VariantAccessor<'a> {
+    type Error = EsSerdeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        if self.esvf.is_null() || self.esvf.is_undefined() {
+            Ok(())
+        } else {
+            Err(serde::de::Error::invalid_type(
+                EsValueFacadeDeserializer { esvf: self.esvf }.unexpected(),
+                &"a unit variant",
+            ))
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(EsValueFacadeDeserializer { esvf: self.esvf })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        EsValueFacadeDeserializer { esvf: self.esvf }.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        EsValueFacadeDeserializer { esvf: self.esvf }.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::esruntime::tests::init_test_runtime;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::esvaluefacade::EsValueFacade;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Role {
+        Admin,
+        Guest(String),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+        age: u32,
+        score: f64,
+        id: i64,
+        big_id: u64,
+        nick: Option<String>,
+        tags: Vec<String>,
+        roles: Vec<Role>,
+        props: HashMap<String, i32>,
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        log::info!("test: test_serde_roundtrip");
+        let rt = init_test_runtime();
+        rt.eval_sync(
+            "this.test_serde = function(u){u.age++; u.tags.push('js'); return u;};",
+            "test_serde.es",
+        )
+        .expect("script failed");
+
+        let mut props = HashMap::new();
+        props.insert("a".to_string(), 1);
+        let user = User {
+            name: "Harry".to_string(),
+            age: 41,
+            score: 1.5,
+            id: 3_000_000_000,
+            big_id: u64::MAX,
+            nick: None,
+            tags: vec!["rust".to_string()],
+            roles: vec![Role::Admin, Role::Guest("x".to_string())],
+            props,
+        };
+
+        let esvf = EsValueFacade::from_serde(&user).expect("could not serialize");
+        let res = rt
+            .call_sync(vec![], "test_serde", vec![esvf])
+            .expect("call failed");
+        let res_user: User = res.to_serde().expect("could not deserialize");
+
+        assert_eq!(res_user.age, 42);
+        assert_eq!(res_user.id, 3_000_000_000);
+        assert_eq!(res_user.big_id, u64::MAX);
+        assert_eq!(res_user.nick, None);
+        assert_eq!(res_user.tags, vec!["rust".to_string(), "js".to_string()]);
+        assert_eq!(res_user.roles, user.roles);
+        assert_eq!(res_user.props.get("a"), Some(&1));
+    }
+
+    #[test]
+    fn test_serde_type_mismatch() {
+        log::info!("test: test_serde_type_mismatch");
+        let rt = init_test_runtime();
+        let esvf = rt
+            .eval_sync(
+                "({name: 'Harry', age: 'old', score: 1, id: 1, big_id: 1n, tags: [], roles: [], props: {}});",
+                "test_serde_type_mismatch.es",
+            )
+            .expect("script failed");
+        let res: Result<User, _> = esvf.to_serde();
+        let err = res.err().expect("deserialize should fail");
+        assert!(err.get_message().contains("invalid type"));
+    }
+
+    #[test]
+    fn test_serde_big_ints() {
+        log::info!("test: test_serde_big_ints");
+        let rt = EsRuntimeBuilder::new().build();
+        // BigInts should still be created when script replaces the global BigInt function
+        rt.eval_sync(
+            "this.BigInt = function(){throw Error('hijacked');}; \
+             this.test_serde_big_ints = function(a, b){return typeof a === 'bigint' && typeof b === 'bigint' && a + b === 3000000001n;};",
+            "test_serde_big_ints.es",
+        )
+        .expect("script failed");
+
+        // small and large i64 values are both converted to a BigInt
+        let a = EsValueFacade::from_serde(&1i64).expect("could not serialize");
+        let b = EsValueFacade::from_serde(&3_000_000_000u64).expect("could not serialize");
+        assert!(a.is_big_int());
+        assert!(b.is_big_int());
+        let res = rt
+            .call_sync(vec![], "test_serde_big_ints", vec![a, b])
+            .expect("call failed");
+        assert!(res.get_boolean());
+    }
+
+    #[test]
+    fn test_serde_map_and_set() {
+        log::info!("test: test_serde_map_and_set");
+        let rt = init_test_runtime();
+        let esvf = rt
+            .eval_sync(
+                "({m: new Map([[1, 'one'], [2, 'two']]), s: new Set([5, 6])});",
+                "test_serde_map_and_set.es",
+            )
+            .expect("script failed");
+
+        #[derive(Deserialize)]
+        struct Cache {
+            m: HashMap<i32, String>,
+            s: Vec<i32>,
+        }
+        let cache: Cache = esvf.to_serde().expect("could not deserialize");
+        assert_eq!(cache.m.get(&2), Some(&"two".to_string()));
+        assert_eq!(cache.s, vec![5, 6]);
+    }
+}
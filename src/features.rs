@@ -4,8 +4,10 @@ use crate::esruntime::EsRuntime;
 /// they may add a native method, a rust op or complete scripts
 mod console;
 mod immediate;
+mod timers;
 
 pub(crate) fn init(rt: &EsRuntime) {
     immediate::init(rt);
+    timers::init(rt);
     console::init(rt);
 }
@@ -1,7 +1,7 @@
 use crate::esruntime::EsRuntime;
 use crate::jsapi_utils;
 use crate::jsapi_utils::report_exception;
-use log::{error, trace};
+use log::trace;
 use mozjs::jsval::ObjectValue;
 
 pub(crate) fn init(rt: &EsRuntime) {
@@ -53,11 +53,8 @@ pub(crate) fn init(rt: &EsRuntime) {
                         vec![],
                         rval.handle_mut(),
                     );
-                    if res.is_err() {
-                        error!(
-                            "error executing setImmediate func: {}",
-                            res.err().unwrap().err_msg()
-                        );
+                    if let Err(err) = res {
+                        crate::spidermonkeyruntimewrapper::report_uncaught_exception(err);
                     } else {
                         trace!("executed setImmediate function");
                     }
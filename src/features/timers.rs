@@ -0,0 +1,336 @@
+//! this feature adds the setTimeout, setInterval, clearTimeout and clearInterval methods
+//!
+//! timers are scheduled as local tasks in the EventLoop of the runtime, they do not hold a ref to
+//! the EsRuntimeInner so a pending timer will never keep a dropped EsRuntime alive
+
+use crate::esruntime::EsRuntime;
+use crate::jsapi_utils;
+use crate::jsapi_utils::objects::NULL_JSOBJECT;
+use crate::jsapi_utils::report_exception;
+use crate::spidermonkeyruntimewrapper::{
+    do_with_cached_object, register_cached_object, remove_cached_object, SM_RT,
+};
+use hirofa_utils::auto_id_map::AutoIdMap;
+use hirofa_utils::eventloop::EventLoop;
+use log::trace;
+use mozjs::jsapi::{CallArgs, HandleValueArray, JSContext, JSObject};
+use mozjs::jsval::{Int32Value, ObjectValue, UndefinedValue};
+use std::cell::RefCell;
+use std::time::Duration;
+
+struct Timer {
+    func_id: usize,
+    args_id: usize,
+    event_loop_id: i32,
+    interval: bool,
+}
+
+thread_local! {
+    static TIMERS: RefCell<AutoIdMap<Timer>> = RefCell::new(AutoIdMap::new());
+}
+
+// the ids of the AutoIdMap start at 0 which is falsy in script, so like in browsers the ids which are returned to
+// script start at 1
+fn to_script_id(timer_id: usize) -> i32 {
+    (timer_id + 1) as i32
+}
+
+fn from_script_id(script_id: i32) -> Option<usize> {
+    if script_id > 0 {
+        Some((script_id - 1) as usize)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn init(rt: &EsRuntime) {
+    rt.do_in_es_event_queue_sync(|sm_rt| {
+        sm_rt.add_global_function("setTimeout", |cx, args| {
+            add_timer(cx, args, false, "setTimeout")
+        });
+        sm_rt.add_global_function("setInterval", |cx, args| {
+            add_timer(cx, args, true, "setInterval")
+        });
+        sm_rt.add_global_function("clearTimeout", |cx, args| {
+            clear_timer(cx, args, "clearTimeout")
+        });
+        sm_rt.add_global_function("clearInterval", |cx, args| {
+            clear_timer(cx, args, "clearInterval")
+        });
+    });
+}
+
+fn add_timer(cx: *mut JSContext, args: CallArgs, interval: bool, method_name: &str) -> bool {
+    if args.argc_ == 0 {
+        report_exception(
+            cx,
+            format!("{} requires at least one argument", method_name).as_str(),
+        );
+        return false;
+    }
+
+    let func_val_handle = args.get(0);
+    let func_val = *func_val_handle;
+    let is_func = jsapi_utils::functions::value_is_function(
+        cx,
+        jsapi_utils::handles::from_raw_handle(func_val_handle),
+    );
+    if !is_func {
+        report_exception(
+            cx,
+            format!("{} requires a function as its first argument", method_name).as_str(),
+        );
+        return false;
+    }
+
+    let delay_ms = if args.argc_ > 1 {
+        let delay_val = *args.get(1);
+        if delay_val.is_int32() {
+            delay_val.to_int32().max(0) as u64
+        } else if delay_val.is_double() && delay_val.to_double().is_finite() {
+            delay_val.to_double().max(0.0) as u64
+        } else {
+            0
+        }
+    } else {
+        0
+    };
+
+    // store the extra arguments in an array so we can cache them along with the function
+    rooted!(in (cx) let mut args_arr_root = NULL_JSOBJECT);
+    jsapi_utils::arrays::new_array(cx, args_arr_root.handle_mut());
+    for x in 2..args.argc_ {
+        rooted!(in (cx) let arg_root = *args.get(x));
+        if let Err(err) =
+            jsapi_utils::arrays::push_array_element(cx, args_arr_root.handle(), arg_root.handle())
+        {
+            report_exception(cx, err.err_msg().as_str());
+            return false;
+        }
+    }
+
+    // cache function and arguments
+    let func_id = register_cached_object(cx, func_val.to_object());
+    let args_id = register_cached_object(cx, args_arr_root.get());
+
+    let timer_id = TIMERS.with(|timers_rc| {
+        let timers = &mut *timers_rc.borrow_mut();
+        timers.insert(Timer {
+            func_id,
+            args_id,
+            event_loop_id: 0,
+            interval,
+        })
+    });
+
+    let delay = Duration::from_millis(delay_ms);
+    let event_loop_id = if interval {
+        EventLoop::add_interval(move || run_timer(timer_id), delay, delay)
+    } else {
+        EventLoop::add_timeout(
+            move || {
+                run_timer(timer_id);
+                remove_timer(timer_id);
+            },
+            delay,
+        )
+    };
+
+    TIMERS.with(|timers_rc| {
+        let timers = &mut *timers_rc.borrow_mut();
+        if let Some(timer) = timers.get_mut(&timer_id) {
+            timer.event_loop_id = event_loop_id;
+        }
+    });
+
+    trace!("added {} with id {}", method_name, timer_id);
+
+    let mut rval = jsapi_utils::handles::from_raw_handle_mut(args.rval());
+    rval.set(Int32Value(to_script_id(timer_id)));
+    true
+}
+
+fn clear_timer(_cx: *mut JSContext, args: CallArgs, method_name: &str) -> bool {
+    if args.argc_ > 0 {
+        let id_val = *args.get(0);
+        let timer_id = if id_val.is_int32() {
+            from_script_id(id_val.to_int32())
+        } else if id_val.is_double() {
+            from_script_id(id_val.to_double() as i32)
+        } else {
+            None
+        };
+
+        // like in browsers clearing an unknown or already cleared id is a no-op
+        if let Some(timer_id) = timer_id {
+            if let Some(timer) = remove_timer(timer_id) {
+                if timer.interval {
+                    EventLoop::clear_interval(timer.event_loop_id);
+                } else {
+                    EventLoop::clear_timeout(timer.event_loop_id);
+                }
+                trace!("{} cleared timer with id {}", method_name, timer_id);
+            }
+        }
+    }
+
+    let mut rval = jsapi_utils::handles::from_raw_handle_mut(args.rval());
+    rval.set(UndefinedValue());
+    true
+}
+
+/// remove a timer and release its cached function and arguments
+fn remove_timer(timer_id: usize) -> Option<Timer> {
+    let timer_opt = TIMERS.with(|timers_rc| {
+        let timers = &mut *timers_rc.borrow_mut();
+        if timers.get(&timer_id).is_some() {
+            Some(timers.remove(&timer_id))
+        } else {
+            None
+        }
+    });
+    if let Some(timer) = &timer_opt {
+        let _ = remove_cached_object(timer.func_id);
+        let _ = remove_cached_object(timer.args_id);
+    }
+    timer_opt
+}
+
+fn run_timer(timer_id: usize) {
+    let ids_opt = TIMERS.with(|timers_rc| {
+        let timers = &*timers_rc.borrow();
+        timers
+            .get(&timer_id)
+            .map(|timer| (timer.func_id, timer.args_id))
+    });
+
+    // the timer may have been cleared in the meantime
+    if let Some((func_id, args_id)) = ids_opt {
+        SM_RT.with(|sm_rt_rc| {
+            let sm_rt = &*sm_rt_rc.borrow();
+            sm_rt.do_with_jsapi(|_rt, cx, global| {
+                let func_obj: *mut JSObject = do_with_cached_object(func_id, |epr| epr.get());
+                let args_obj: *mut JSObject = do_with_cached_object(args_id, |epr| epr.get());
+
+                rooted!(in (cx) let func_val_root = ObjectValue(func_obj));
+                rooted!(in (cx) let args_obj_root = args_obj);
+
+                let args_len =
+                    jsapi_utils::arrays::get_array_length(cx, args_obj_root.handle()).unwrap_or(0);
+
+                auto_root!(in (cx) let mut args_vec = vec![]);
+                for x in 0..args_len {
+                    rooted!(in (cx) let mut arg_root = UndefinedValue());
+                    if jsapi_utils::arrays::get_array_element(
+                        cx,
+                        args_obj_root.handle(),
+                        x,
+                        arg_root.handle_mut(),
+                    )
+                    .is_ok()
+                    {
+                        args_vec.push(*arg_root);
+                    }
+                }
+
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                let arguments_value_array =
+                    unsafe { HandleValueArray::from_rooted_slice(&*args_vec) };
+                let res = jsapi_utils::functions::call_function_value2(
+                    cx,
+                    global,
+                    func_val_root.handle(),
+                    arguments_value_array,
+                    rval.handle_mut(),
+                );
+                if let Err(err) = res {
+                    crate::spidermonkeyruntimewrapper::report_uncaught_exception(err);
+                } else {
+                    trace!("executed timer function with id {}", timer_id);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_set_timeout() {
+        log::info!("test: test_set_timeout");
+        let rt = EsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            "this.timeout_res = 0; \
+             setTimeout(function(a, b){this.timeout_res = a + b;}, 10, 3, 4);",
+            "test_set_timeout.es",
+        )
+        .expect("setTimeout failed");
+
+        std::thread::sleep(Duration::from_millis(100));
+
+        let res = rt
+            .eval_sync("this.timeout_res;", "test_set_timeout2.es")
+            .expect("get res failed");
+        assert_eq!(res.get_i32(), 7);
+    }
+
+    #[test]
+    fn test_clear_timeout() {
+        log::info!("test: test_clear_timeout");
+        let rt = EsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            "this.clear_res = 'not run'; \
+             let id = setTimeout(function(){this.clear_res = 'run';}, 10); \
+             clearTimeout(id);",
+            "test_clear_timeout.es",
+        )
+        .expect("clearTimeout failed");
+
+        std::thread::sleep(Duration::from_millis(100));
+
+        let res = rt
+            .eval_sync("this.clear_res;", "test_clear_timeout2.es")
+            .expect("get res failed");
+        assert_eq!(res.get_string(), "not run");
+    }
+
+    #[test]
+    fn test_timer_ids() {
+        log::info!("test: test_timer_ids");
+        let rt = EsRuntimeBuilder::new().build();
+        let res = rt
+            .eval_sync(
+                "let id = setTimeout(function(){}, 10); \
+                 let cleared = false; \
+                 if (id) { clearTimeout(id); cleared = true; } \
+                 cleared && id > 0;",
+                "test_timer_ids.es",
+            )
+            .expect("test_timer_ids failed");
+        assert!(res.get_boolean());
+    }
+
+    #[test]
+    fn test_set_interval() {
+        log::info!("test: test_set_interval");
+        let rt = EsRuntimeBuilder::new().build();
+        rt.eval_sync(
+            "this.interval_count = 0; \
+             let id = setInterval(function(){ \
+                 if (++this.interval_count >= 3) { clearInterval(id); } \
+             }, 5);",
+            "test_set_interval.es",
+        )
+        .expect("setInterval failed");
+
+        std::thread::sleep(Duration::from_millis(200));
+
+        let res = rt
+            .eval_sync("this.interval_count;", "test_set_interval2.es")
+            .expect("get res failed");
+        assert_eq!(res.get_i32(), 3);
+    }
+}
@@ -24,7 +24,12 @@
 //!
 
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
-use crate::jsapi_utils::objects::{get_es_obj_prop_val_as_i32, get_es_obj_prop_val_as_string};
+use crate::esruntimeinner::EsRuntimeInner;
+use crate::esvaluefacade::EsValueFacade;
+use crate::jsapi_utils::objects::get_es_obj_prop_val;
+use crate::spidermonkeyruntimewrapper::{
+    do_with_cached_object, register_cached_object, remove_cached_object, SM_RT,
+};
 use log::{debug, trace};
 use mozjs::conversions::jsstr_to_string;
 use mozjs::glue::{RUST_JSID_IS_STRING, RUST_JSID_TO_STRING};
@@ -37,14 +42,20 @@ use mozjs::jsapi::JS_IsExceptionPending;
 use mozjs::jsapi::JS_NewStringCopyN;
 use mozjs::jsapi::JS_TypeOfValue;
 use mozjs::jsapi::JS_GC;
-use mozjs::jsval::{StringValue, UndefinedValue};
+use mozjs::jsapi::{JSGCParamKey, JS_GetGCParameter, JS_SetGCParameter};
+use mozjs::jsval::{ObjectValue, StringValue, UndefinedValue};
 use mozjs::rust::{HandleObject, HandleValue, MutableHandleValue, Runtime};
+use std::fmt;
 use std::str;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 pub mod arrays;
 pub mod big_ints;
+pub mod dates;
+pub mod errors;
 pub mod functions;
 pub mod handles;
+pub mod maps_sets;
 pub mod modules;
 pub mod objects;
 pub mod promises;
@@ -96,12 +107,7 @@ fn get_pending_exception_or_generic_err(cx: *mut JSContext, gen_err: &'static st
     if let Some(err) = crate::jsapi_utils::get_pending_exception(cx) {
         err
     } else {
-        EsErrorInfo {
-            message: gen_err.to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        }
+        EsErrorInfo::new(gen_err.to_string(), "".to_string(), 0, 0)
     }
 }
 
@@ -113,35 +119,16 @@ pub fn get_pending_exception(context: *mut JSContext) -> Option<EsErrorInfo> {
     if unsafe { JS_IsExceptionPending(context) } {
         rooted!(in(context) let mut error_value = UndefinedValue());
         if unsafe { JS_GetPendingException(context, error_value.handle_mut().into()) } {
-            let js_error_obj: *mut mozjs::jsapi::JSObject = error_value.to_object();
-            rooted!(in(context) let mut js_error_obj_root = js_error_obj);
-
-            let message =
-                get_es_obj_prop_val_as_string(context, js_error_obj_root.handle(), "message")
-                    .ok()
-                    .unwrap();
-            let filename =
-                get_es_obj_prop_val_as_string(context, js_error_obj_root.handle(), "fileName")
-                    .ok()
-                    .unwrap();
-            let lineno =
-                get_es_obj_prop_val_as_i32(context, js_error_obj_root.handle(), "lineNumber");
-            let column =
-                get_es_obj_prop_val_as_i32(context, js_error_obj_root.handle(), "columnNumber");
-
-            let error_info: EsErrorInfo = EsErrorInfo {
-                message,
-                filename,
-                lineno,
-                column,
-            };
+            // clear first, reading the stack or cause may run script
+            unsafe { JS_ClearPendingException(context) };
+
+            let error_info = error_info_from_thrown_value(context, error_value.handle());
 
             debug!(
                 "ex = {} in {} at {}:{}",
                 error_info.message, error_info.filename, error_info.lineno, error_info.column
             );
 
-            unsafe { JS_ClearPendingException(context) };
             Some(error_info)
         } else {
             None
@@ -151,15 +138,325 @@ pub fn get_pending_exception(context: *mut JSContext) -> Option<EsErrorInfo> {
     }
 }
 
+/// create an EsErrorInfo for a thrown (or rejected) value, the value itself is kept as thrown_value
+pub(crate) fn error_info_from_thrown_value(
+    context: *mut JSContext,
+    val: HandleValue,
+) -> EsErrorInfo {
+    let mut error_info = error_info_from_value(context, val);
+    error_info.thrown_value = Some(ThrownValue::new(context, val));
+    error_info
+}
+
+/// the max depth of a cause chain, this guards against errors which (indirectly) cause themselves
+const MAX_CAUSE_DEPTH: usize = 16;
+
+/// create an EsErrorInfo for an Error (or any other value), the cause chain is at most MAX_CAUSE_DEPTH deep
+pub(crate) fn error_info_from_value(context: *mut JSContext, val: HandleValue) -> EsErrorInfo {
+    error_info_from_value_at_depth(context, val, 0)
+}
+
+fn error_info_from_value_at_depth(
+    context: *mut JSContext,
+    val: HandleValue,
+    depth: usize,
+) -> EsErrorInfo {
+    if val.is_string() {
+        // this is also what spidermonkey sets as pending exception when it runs out of memory
+        let message =
+            es_value_to_str(context, *val).unwrap_or_else(|_| "out of memory".to_string());
+        return EsErrorInfo::new(message, "".to_string(), 0, 0);
+    }
+
+    if !val.is_object() {
+        // e.g. throw 123; or throw null;
+        return EsErrorInfo::new(value_to_string(context, val), "".to_string(), 0, 0);
+    }
+
+    rooted!(in(context) let js_error_obj_root = val.to_object());
+    let obj = js_error_obj_root.handle();
+    let is_error = errors::is_error(context, obj);
+
+    let mut message = get_prop_as_string_or_empty(context, obj, "message");
+    if message.is_empty() && !is_error {
+        // e.g. throw {code: 1}; use what String(val) gives us
+        message = value_to_string(context, val);
+    }
+
+    let mut error_info = EsErrorInfo::new(
+        message,
+        get_prop_as_string_or_empty(context, obj, "fileName"),
+        get_prop_as_i32_or_zero(context, obj, "lineNumber"),
+        get_prop_as_i32_or_zero(context, obj, "columnNumber"),
+    );
+    error_info.stack = parse_stack(get_prop_as_string_or_empty(context, obj, "stack").as_str());
+
+    if is_error {
+        error_info.name = get_prop_as_string_or_empty(context, obj, "name");
+
+        rooted!(in(context) let mut cause_root = UndefinedValue());
+        if depth < MAX_CAUSE_DEPTH
+            && get_es_obj_prop_val(context, obj, "cause", cause_root.handle_mut()).is_ok()
+            && !cause_root.is_undefined()
+        {
+            error_info.cause = Some(Box::new(error_info_from_value_at_depth(
+                context,
+                cause_root.handle(),
+                depth + 1,
+            )));
+        }
+    }
+
+    error_info
+}
+
+/// convert any value to a String like String(val) would
+fn value_to_string(context: *mut JSContext, val: HandleValue) -> String {
+    rooted!(in(context) let js_str_root = unsafe { mozjs::rust::ToString(context, val) });
+    if js_str_root.is_null() {
+        // e.g. a Symbol or an object with a throwing toString
+        unsafe { JS_ClearPendingException(context) };
+        format!("uncaught {}", get_type_name(context, val))
+    } else {
+        es_jsstring_to_string(context, *js_str_root)
+    }
+}
+
+fn get_type_name(context: *mut JSContext, val: HandleValue) -> &'static str {
+    match get_type_of(context, val) {
+        JSType::JSTYPE_UNDEFINED => "undefined",
+        JSType::JSTYPE_OBJECT => "object",
+        JSType::JSTYPE_FUNCTION => "function",
+        JSType::JSTYPE_STRING => "string",
+        JSType::JSTYPE_NUMBER => "number",
+        JSType::JSTYPE_BOOLEAN => "boolean",
+        JSType::JSTYPE_SYMBOL => "symbol",
+        JSType::JSTYPE_BIGINT => "bigint",
+        _ => "value",
+    }
+}
+
+fn get_prop_as_string_or_empty(
+    context: *mut JSContext,
+    obj: HandleObject,
+    prop_name: &str,
+) -> String {
+    rooted!(in(context) let mut val_root = UndefinedValue());
+    if get_es_obj_prop_val(context, obj, prop_name, val_root.handle_mut()).is_ok() {
+        es_value_to_str(context, *val_root).unwrap_or_default()
+    } else {
+        "".to_string()
+    }
+}
+
+fn get_prop_as_i32_or_zero(context: *mut JSContext, obj: HandleObject, prop_name: &str) -> i32 {
+    rooted!(in(context) let mut val_root = UndefinedValue());
+    if get_es_obj_prop_val(context, obj, prop_name, val_root.handle_mut()).is_ok()
+        && val_root.is_number()
+    {
+        val_root.to_number() as i32
+    } else {
+        0
+    }
+}
+
+/// parse a stack in the SpiderMonkey format (one functionName@fileName:lineNumber:columnNumber per line)
+/// frames which can not be parsed are skipped
+pub fn parse_stack(stack: &str) -> Vec<EsStackFrame> {
+    stack
+        .lines()
+        .filter_map(|line| {
+            let at_pos = line.find('@')?;
+            let mut location_parts = line[at_pos + 1..].rsplitn(3, ':');
+            let column = location_parts.next()?.parse().ok()?;
+            let lineno = location_parts.next()?.parse().ok()?;
+            let filename = location_parts.next()?;
+            Some(EsStackFrame {
+                function_name: line[..at_pos].to_string(),
+                filename: filename.to_string(),
+                lineno,
+                column,
+            })
+        })
+        .collect()
+}
+
+/// format a stack in the SpiderMonkey format, this is the reverse of parse_stack
+pub fn format_stack(stack: &[EsStackFrame]) -> String {
+    stack
+        .iter()
+        .map(|frame| {
+            format!(
+                "{}@{}:{}:{}\n",
+                frame.function_name, frame.filename, frame.lineno, frame.column
+            )
+        })
+        .collect()
+}
+
+/// a single frame of a script stack trace
+#[derive(Clone, Debug, PartialEq)]
+pub struct EsStackFrame {
+    /// the name of the function, this is empty for top level code
+    pub function_name: String,
+    pub filename: String,
+    pub lineno: i32,
+    pub column: i32,
+}
+
+/// the value which was thrown by a script, shared so EsErrorInfo can stay Clone
+/// objects stay rooted and are only converted to an EsValueFacade when do_with_thrown_value is called
+#[derive(Clone)]
+pub(crate) struct ThrownValue {
+    state: Arc<Mutex<ThrownValueState>>,
+}
+
+enum ThrownValueState {
+    Rooted {
+        cached_obj_id: usize,
+        rti_ref: Weak<EsRuntimeInner>,
+    },
+    Converted(EsValueFacade),
+}
+
+impl ThrownValue {
+    fn new(context: *mut JSContext, val: HandleValue) -> Self {
+        // the runtime is not known yet while the SmRuntime is being initialized
+        let opt_rti_ref = SM_RT.with(|sm_rt_rc| {
+            sm_rt_rc
+                .try_borrow()
+                .ok()
+                .and_then(|sm_rt| sm_rt.opt_esrt_inner.clone())
+        });
+        let state = match opt_rti_ref {
+            Some(rti_ref) if val.is_object() => ThrownValueState::Rooted {
+                cached_obj_id: register_cached_object(context, val.to_object()),
+                rti_ref,
+            },
+            // primitives are cheap to convert and don't run script
+            _ => ThrownValueState::Converted(EsValueFacade::new_v(context, val)),
+        };
+        ThrownValue {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn lock_state(&self) -> MutexGuard<ThrownValueState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// convert the rooted value, returns false if the runtime was dropped
+    fn convert(&self) -> bool {
+        let (cached_obj_id, rti) = match &*self.lock_state() {
+            ThrownValueState::Converted(_) => return true,
+            ThrownValueState::Rooted {
+                cached_obj_id,
+                rti_ref,
+            } => match rti_ref.upgrade() {
+                Some(rti) => (*cached_obj_id, rti),
+                None => return false,
+            },
+        };
+        // the lock is not held while converting so the worker thread never waits for it
+        let esvf = rti.do_in_es_event_queue_sync(move |sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                do_with_cached_object(cached_obj_id, |epr| {
+                    rooted!(in (cx) let val_root = ObjectValue(epr.get()));
+                    EsValueFacade::new_v(cx, val_root.handle())
+                })
+            })
+        });
+        let state = &mut *self.lock_state();
+        if let ThrownValueState::Rooted { .. } = state {
+            // this drops the Rooted state which removes the cached object
+            *state = ThrownValueState::Converted(esvf);
+        }
+        true
+    }
+}
+
+impl Drop for ThrownValueState {
+    fn drop(&mut self) {
+        if let ThrownValueState::Rooted {
+            cached_obj_id,
+            rti_ref,
+        } = self
+        {
+            if let Some(rti) = rti_ref.upgrade() {
+                let cached_obj_id = *cached_obj_id;
+                rti.do_in_es_event_queue(move |_sm_rt| {
+                    remove_cached_object(cached_obj_id);
+                });
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ThrownValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ThrownValue")
+    }
+}
+
 /// struct that represents a script exception
+#[derive(Clone, Debug)]
 pub struct EsErrorInfo {
     pub message: String,
     pub filename: String,
     pub lineno: i32,
     pub column: i32,
+    /// the name of the error e.g. TypeError, this is empty if the thrown value was not an Error
+    pub name: String,
+    /// the stack at the moment the error was created, innermost frame first
+    pub stack: Vec<EsStackFrame>,
+    /// the cause of the error if one was passed to the Error constructor
+    pub cause: Option<Box<EsErrorInfo>>,
+    pub(crate) thrown_value: Option<ThrownValue>,
 }
 
 impl EsErrorInfo {
+    /// create a new EsErrorInfo without name, stack or cause
+    pub fn new(message: String, filename: String, lineno: i32, column: i32) -> Self {
+        EsErrorInfo {
+            message,
+            filename,
+            lineno,
+            column,
+            name: "".to_string(),
+            stack: vec![],
+            cause: None,
+            thrown_value: None,
+        }
+    }
+
+    /// run a consumer with the value which was actually thrown by the script
+    /// the value is converted to an EsValueFacade on the first call, this runs in the event queue of the EsRuntime
+    /// this returns None if the error did not originate from a thrown value or if the EsRuntime was dropped
+    /// # Example
+    /// ```no_run
+    /// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+    ///
+    /// let rt = EsRuntimeBuilder::new().build();
+    /// let err = rt.eval_sync("throw {code: 42};", "test_thrown_value.es").err().expect("script did not fail");
+    /// let code = err.do_with_thrown_value(|esvf| esvf.get_object().get("code").unwrap().get_i32());
+    /// assert_eq!(code, Some(42));
+    /// ```
+    pub fn do_with_thrown_value<R, C: FnOnce(&EsValueFacade) -> R>(
+        &self,
+        consumer: C,
+    ) -> Option<R> {
+        let thrown_value = self.thrown_value.as_ref()?;
+        if !thrown_value.convert() {
+            return None;
+        }
+        match &*thrown_value.lock_state() {
+            ThrownValueState::Converted(esvf) => Some(consumer(esvf)),
+            ThrownValueState::Rooted { .. } => None,
+        }
+    }
+
     /// get eror as String in the form of [message] at [filename]:[lineno]:[column]
     pub fn err_msg(&self) -> String {
         format!(
@@ -169,17 +466,24 @@ impl EsErrorInfo {
     }
 }
 
-impl Clone for EsErrorInfo {
-    fn clone(&self) -> Self {
-        EsErrorInfo {
-            message: self.message.clone(),
-            filename: self.filename.clone(),
-            lineno: self.lineno,
-            column: self.column,
+impl fmt::Display for EsErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.err_msg())
+        } else {
+            write!(f, "{}: {}", self.name, self.err_msg())
         }
     }
 }
 
+impl std::error::Error for EsErrorInfo {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.cause
+            .as_ref()
+            .map(|cause| cause.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+
 /// eval a piece of source code in the engine
 pub fn eval(
     runtime: &Runtime,
@@ -199,12 +503,12 @@ pub fn eval(
         if let Some(ex) = ex_opt {
             Err(ex)
         } else {
-            Err(EsErrorInfo {
-                message: "unknown error while evalling".to_string(),
-                filename: file_name.to_string(),
-                lineno: 0,
-                column: 0,
-            })
+            Err(EsErrorInfo::new(
+                "unknown error while evalling".to_string(),
+                file_name.to_string(),
+                0,
+                0,
+            ))
         }
     }
 }
@@ -251,6 +555,19 @@ pub fn es_jsid_to_string(context: *mut JSContext, id: mozjs::jsapi::HandleId) ->
     es_jsstring_to_string(context, *id_str)
 }
 
+/// set a parameter of the garbage collector e.g. JSGC_MAX_BYTES
+pub fn set_gc_parameter(context: *mut JSContext, key: JSGCParamKey, value: u32) {
+    debug!("setting gc param {} to {}", key as u32, value);
+    unsafe {
+        JS_SetGCParameter(context, key, value);
+    }
+}
+
+/// get a parameter of the garbage collector e.g. JSGC_BYTES
+pub fn get_gc_parameter(context: *mut JSContext, key: JSGCParamKey) -> u32 {
+    unsafe { JS_GetGCParameter(context, key) }
+}
+
 /// call the garbage collector
 pub fn gc(context: *mut JSContext) {
     unsafe {
@@ -260,7 +577,9 @@ pub fn gc(context: *mut JSContext) {
 
 #[cfg(test)]
 mod tests {
-    use crate::jsapi_utils::{es_value_to_str, get_pending_exception, EsErrorInfo};
+    use crate::jsapi_utils::{
+        es_value_to_str, get_pending_exception, parse_stack, EsErrorInfo, EsStackFrame,
+    };
 
     use crate::esruntime::tests::init_test_runtime;
     use crate::esvaluefacade::EsValueFacade;
@@ -362,4 +681,151 @@ mod tests {
 
         assert_eq!(res, "quibus is not defined");
     }
+
+    #[test]
+    fn test_exception_stack_and_cause() {
+        let rt = init_test_runtime();
+        let err = rt
+            .eval_sync(
+                "function inner() {\n\
+                 throw new TypeError('outer', {cause: new RangeError('inner')});\n\
+                 }\n\
+                 function outer() {\n\
+                 inner();\n\
+                 }\n\
+                 outer();",
+                "test_exception_stack.es",
+            )
+            .err()
+            .expect("script did not fail");
+
+        assert_eq!(err.name, "TypeError");
+        assert_eq!(err.message, "outer");
+        assert_eq!(err.lineno, 2);
+        let first_frame: &EsStackFrame = err.stack.get(0).expect("no stack");
+        assert_eq!(first_frame.function_name, "inner");
+        assert_eq!(first_frame.filename, "test_exception_stack.es");
+        assert_eq!(first_frame.lineno, 2);
+        assert_eq!(err.stack.get(1).unwrap().function_name, "outer");
+        assert_eq!(err.stack.get(2).unwrap().function_name, "");
+
+        let cause = err.cause.as_ref().expect("no cause");
+        assert_eq!(cause.name, "RangeError");
+        assert_eq!(cause.message, "inner");
+        assert!(std::error::Error::source(&err).is_some());
+        assert!(format!("{}", err).starts_with("TypeError: outer at test_exception_stack.es:2:"));
+
+        let err = rt
+            .eval_sync("throw 123;", "test_exception_primitive.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "123");
+        assert_eq!(err.name, "");
+        assert!(err.cause.is_none());
+    }
+
+    #[test]
+    fn test_parse_stack() {
+        let frames = parse_stack("foo@http://host:8080/a.es:3:14\n@b.es:1:1\ngarbage\n");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].function_name, "foo");
+        assert_eq!(frames[0].filename, "http://host:8080/a.es");
+        assert_eq!(frames[0].lineno, 3);
+        assert_eq!(frames[0].column, 14);
+        assert_eq!(frames[1].function_name, "");
+        assert_eq!(frames[1].filename, "b.es");
+    }
+
+    #[test]
+    fn test_non_error_throw_values() {
+        let rt = init_test_runtime();
+
+        let err = rt
+            .eval_sync("throw 'oops';", "test_throw_string.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "oops");
+        assert_eq!(
+            err.do_with_thrown_value(|esvf| esvf.get_string().to_string()),
+            Some("oops".to_string())
+        );
+
+        let err = rt
+            .eval_sync("throw 42;", "test_throw_number.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "42");
+        assert_eq!(err.do_with_thrown_value(|esvf| esvf.get_i32()), Some(42));
+
+        let err = rt
+            .eval_sync("throw null;", "test_throw_null.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "null");
+        assert_eq!(err.do_with_thrown_value(|esvf| esvf.is_null()), Some(true));
+
+        let err = rt
+            .eval_sync("throw Symbol('s');", "test_throw_symbol.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "uncaught symbol");
+
+        let err = rt
+            .eval_sync("throw {code: 7};", "test_throw_object.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(err.message, "[object Object]");
+        let code = err
+            .clone()
+            .do_with_thrown_value(|esvf| esvf.get_object().get("code").unwrap().get_i32());
+        assert_eq!(code, Some(7));
+
+        let err = rt
+            .eval_sync("throw new Error('real error');", "test_throw_error.es")
+            .err()
+            .expect("script did not fail");
+        assert_eq!(
+            err.do_with_thrown_value(|esvf| esvf.get_error().message.clone()),
+            Some("real error".to_string())
+        );
+
+        assert!(EsErrorInfo::new("x".to_string(), "".to_string(), 0, 0)
+            .do_with_thrown_value(|_esvf| ())
+            .is_none());
+    }
+
+    #[test]
+    fn test_thrown_value_is_converted_lazily() {
+        log::info!("test: test_thrown_value_is_converted_lazily");
+        let rt = init_test_runtime();
+
+        let err = rt
+            .eval_sync(
+                "globalThis.lazy_thrown_value_gets = 0;\
+                 throw {get code(){globalThis.lazy_thrown_value_gets++; return 3;}};",
+                "test_thrown_value_is_converted_lazily.es",
+            )
+            .err()
+            .expect("script did not fail");
+        let gets = rt
+            .eval_sync(
+                "lazy_thrown_value_gets;",
+                "test_thrown_value_is_converted_lazily2.es",
+            )
+            .expect("script failed");
+        assert_eq!(gets.get_i32(), 0);
+
+        for _ in 0..2 {
+            let code =
+                err.do_with_thrown_value(|esvf| esvf.get_object().get("code").unwrap().get_i32());
+            assert_eq!(code, Some(3));
+        }
+        let gets = rt
+            .eval_sync(
+                "lazy_thrown_value_gets;",
+                "test_thrown_value_is_converted_lazily3.es",
+            )
+            .expect("script failed");
+        assert_eq!(gets.get_i32(), 1);
+    }
 }
@@ -1,8 +1,15 @@
 use crate::jsapi_utils;
+use crate::jsapi_utils::EsErrorInfo;
+use mozjs::jsapi::BigInt;
 use mozjs::jsapi::JSContext;
+use mozjs::jsapi::JSString;
 use mozjs::jsapi::JSType;
-use mozjs::jsval::UndefinedValue;
-use mozjs::rust::{HandleObject, HandleValue};
+use mozjs::jsapi::{BigIntToString, ToBigInt};
+use mozjs::jsval::{BigIntValue, UndefinedValue};
+use mozjs::rust::{HandleValue, MutableHandleValue};
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
 
 // fun fact.. bigint is NOT an object but JSVal does not have to_bigint.. just like it does not have to_function but functionvals to_object just fine
 
@@ -11,35 +18,211 @@ pub fn is_big_int(cx: *mut JSContext, val: HandleValue) -> bool {
     js_type == JSType::JSTYPE_BIGINT
 }
 
-pub fn as_string(cx: *mut JSContext, global: HandleObject, obj: HandleValue) -> String {
+/// get the decimal representation of a BigInt
+/// this uses the jsapi directly so it does not depend on BigInt.prototype.toString which may be altered by script
+/// this fails if the value is not a BigInt
+pub fn as_string(cx: *mut JSContext, obj: HandleValue) -> Result<String, EsErrorInfo> {
+    if !is_big_int(cx, obj) {
+        return Err(EsErrorInfo::new(
+            "value is not a BigInt".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ));
+    }
+    to_js_big_int(cx, obj).and_then(|big_int| {
+        rooted!(in (cx) let big_int_root = big_int);
+        let js_str: *mut JSString = unsafe { BigIntToString(cx, big_int_root.handle().into(), 10) };
+        if js_str.is_null() {
+            Err(pending_or_new_error(cx, "BigIntToString failed"))
+        } else {
+            rooted!(in (cx) let js_str_root = js_str);
+            Ok(jsapi_utils::es_jsstring_to_string(cx, *js_str_root))
+        }
+    })
+}
+
+/// ToBigInt, for a BigInt value this returns the BigInt itself
+fn to_js_big_int(cx: *mut JSContext, val: HandleValue) -> Result<*mut BigInt, EsErrorInfo> {
+    let big_int: *mut BigInt = unsafe { ToBigInt(cx, val.into()) };
+    if big_int.is_null() {
+        Err(pending_or_new_error(cx, "ToBigInt failed"))
+    } else {
+        Ok(big_int)
+    }
+}
+
+fn pending_or_new_error(cx: *mut JSContext, msg: &str) -> EsErrorInfo {
+    jsapi_utils::get_pending_exception(cx)
+        .unwrap_or_else(|| EsErrorInfo::new(msg.to_string(), "".to_string(), 0, 0))
+}
+
+/// create a new BigInt from a string, this fails if the string is not a valid integer
+/// # Example
+/// ```no_run
+/// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+/// use spidermonkey_runtime::jsapi_utils::big_ints;
+/// use mozjs::jsval::UndefinedValue;
+/// use mozjs::rooted;
+///
+/// let rt = EsRuntimeBuilder::new().build();
+/// rt.do_in_es_event_queue_sync(|sm_rt| {
+///     sm_rt.do_with_jsapi(|_rt, cx, _global| {
+///         rooted!(in (cx) let mut big_int_root = UndefinedValue());
+///         big_ints::new_big_int_from_str(cx, "12345678901234567890", big_int_root.handle_mut())
+///             .ok().expect("could not create BigInt");
+///         assert_eq!(big_ints::to_u64(cx, big_int_root.handle()).ok().unwrap(), 12345678901234567890);
+///     });
+/// });
+/// ```
+pub fn new_big_int_from_str(
+    cx: *mut JSContext,
+    s: &str,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    // the ToBigInt operation is used instead of the global BigInt function which may be altered by script
     rooted!(in (cx) let mut str_val = UndefinedValue());
+    jsapi_utils::new_es_value_from_str(cx, s, str_val.handle_mut());
+    let big_int = to_js_big_int(cx, str_val.handle())?;
+    let mut rval = rval;
+    rval.set(BigIntValue(unsafe { &*big_int }));
+    Ok(())
+}
 
-    jsapi_utils::functions::call_namespace_function_name(
-        cx,
-        global,
-        vec!["BigInt", "prototype", "toString"],
-        "apply",
-        vec![obj.get()],
-        str_val.handle_mut(),
-    )
-    .ok()
-    .expect("call_namespace_function_name failed");
+/// create a new BigInt from an i64
+pub fn new_big_int_from_i64(
+    cx: *mut JSContext,
+    num: i64,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    new_big_int_from_str(cx, num.to_string().as_str(), rval)
+}
+
+/// create a new BigInt from a u64
+pub fn new_big_int_from_u64(
+    cx: *mut JSContext,
+    num: u64,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    new_big_int_from_str(cx, num.to_string().as_str(), rval)
+}
+
+/// create a new BigInt from an i128
+pub fn new_big_int_from_i128(
+    cx: *mut JSContext,
+    num: i128,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    new_big_int_from_str(cx, num.to_string().as_str(), rval)
+}
+
+/// create a new BigInt from a u128
+pub fn new_big_int_from_u128(
+    cx: *mut JSContext,
+    num: u128,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    new_big_int_from_str(cx, num.to_string().as_str(), rval)
+}
 
-    jsapi_utils::es_value_to_str(cx, str_val.handle().get()).expect("could not convert to string")
+/// parse the decimal representation of a BigInt, this fails if the value does not fit in T
+pub(crate) fn parse_big_int_str<T: FromStr>(s: &str, type_name: &str) -> Result<T, EsErrorInfo>
+where
+    T::Err: Display,
+{
+    s.parse::<T>().map_err(|err| {
+        EsErrorInfo::new(
+            format!("BigInt {} does not fit in an {}: {}", s, type_name, err),
+            "".to_string(),
+            0,
+            0,
+        )
+    })
 }
 
-// todo
-// get as u128 / etc
-// new from string or u128
-// compare two bigints
-// compare with u128 etc
+/// get the value of a BigInt as an i64, this fails if the value does not fit in an i64
+pub fn to_i64(cx: *mut JSContext, obj: HandleValue) -> Result<i64, EsErrorInfo> {
+    parse_big_int_str(as_string(cx, obj)?.as_str(), "i64")
+}
+
+/// get the value of a BigInt as a u64, this fails if the value does not fit in a u64
+pub fn to_u64(cx: *mut JSContext, obj: HandleValue) -> Result<u64, EsErrorInfo> {
+    parse_big_int_str(as_string(cx, obj)?.as_str(), "u64")
+}
+
+/// get the value of a BigInt as an i128, this fails if the value does not fit in an i128
+pub fn to_i128(cx: *mut JSContext, obj: HandleValue) -> Result<i128, EsErrorInfo> {
+    parse_big_int_str(as_string(cx, obj)?.as_str(), "i128")
+}
+
+/// get the value of a BigInt as a u128, this fails if the value does not fit in a u128
+pub fn to_u128(cx: *mut JSContext, obj: HandleValue) -> Result<u128, EsErrorInfo> {
+    parse_big_int_str(as_string(cx, obj)?.as_str(), "u128")
+}
+
+/// compare two BigInts, this fails if a value is not a BigInt
+pub fn compare(
+    cx: *mut JSContext,
+    a: HandleValue,
+    b: HandleValue,
+) -> Result<Ordering, EsErrorInfo> {
+    Ok(compare_big_int_strs(
+        as_string(cx, a)?.as_str(),
+        as_string(cx, b)?.as_str(),
+    ))
+}
+
+/// compare a BigInt with an i128, this fails if a is not a BigInt
+pub fn compare_with_i128(
+    cx: *mut JSContext,
+    a: HandleValue,
+    b: i128,
+) -> Result<Ordering, EsErrorInfo> {
+    Ok(compare_big_int_strs(
+        as_string(cx, a)?.as_str(),
+        b.to_string().as_str(),
+    ))
+}
+
+/// compare a BigInt with a u128, this fails if a is not a BigInt
+pub fn compare_with_u128(
+    cx: *mut JSContext,
+    a: HandleValue,
+    b: u128,
+) -> Result<Ordering, EsErrorInfo> {
+    Ok(compare_big_int_strs(
+        as_string(cx, a)?.as_str(),
+        b.to_string().as_str(),
+    ))
+}
+
+/// compare the decimal representations of two BigInts as produced by BigInt.prototype.toString()
+/// this works for any size so we don't need to parse them to a fixed width int
+fn compare_big_int_strs(a: &str, b: &str) -> Ordering {
+    let a_neg = a.starts_with('-');
+    let b_neg = b.starts_with('-');
+    match (a_neg, b_neg) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => compare_magnitudes(a, b),
+        (true, true) => compare_magnitudes(&b[1..], &a[1..]),
+    }
+}
+
+fn compare_magnitudes(a: &str, b: &str) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
 
 #[cfg(test)]
 pub mod tests {
     use crate::jsapi_utils;
-    use crate::jsapi_utils::big_ints::{as_string, is_big_int};
+    use crate::jsapi_utils::big_ints::{
+        as_string, compare, compare_with_i128, compare_with_u128, is_big_int, new_big_int_from_i64,
+        new_big_int_from_str, new_big_int_from_u128, to_i64, to_u128, to_u64,
+    };
     use crate::jsapi_utils::tests::test_with_sm_rt;
     use mozjs::jsval::UndefinedValue;
+    use std::cmp::Ordering;
 
     #[test]
     fn test_bigint() {
@@ -59,10 +242,88 @@ pub mod tests {
                 assert!(is_big_int(cx, rval.handle()));
 
                 assert_eq!(
-                    as_string(cx, global, rval.handle()).as_str(),
+                    as_string(cx, rval.handle())
+                        .expect("could not convert BigInt")
+                        .as_str(),
                     "12345678901234567890"
                 );
             });
         });
     }
+
+    #[test]
+    fn test_bigint_conversions() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                rooted!(in (cx) let mut big_root = UndefinedValue());
+                new_big_int_from_str(cx, "12345678901234567890", big_root.handle_mut())
+                    .expect("could not create BigInt");
+                assert!(is_big_int(cx, big_root.handle()));
+                assert_eq!(
+                    to_u64(cx, big_root.handle()).ok().unwrap(),
+                    12_345_678_901_234_567_890
+                );
+                // does not fit in an i64
+                assert!(to_i64(cx, big_root.handle()).is_err());
+
+                rooted!(in (cx) let mut neg_root = UndefinedValue());
+                new_big_int_from_i64(cx, i64::MIN, neg_root.handle_mut())
+                    .expect("could not create BigInt");
+                assert_eq!(to_i64(cx, neg_root.handle()).ok().unwrap(), i64::MIN);
+                assert!(to_u64(cx, neg_root.handle()).is_err());
+
+                rooted!(in (cx) let mut max_root = UndefinedValue());
+                new_big_int_from_u128(cx, u128::MAX, max_root.handle_mut())
+                    .expect("could not create BigInt");
+                assert_eq!(to_u128(cx, max_root.handle()).ok().unwrap(), u128::MAX);
+
+                assert!(new_big_int_from_str(cx, "abc", big_root.handle_mut()).is_err());
+
+                // values which are not a BigInt result in an error
+                rooted!(in (cx) let num_root = mozjs::jsval::Int32Value(12));
+                assert!(as_string(cx, num_root.handle()).is_err());
+                assert!(to_i64(cx, num_root.handle()).is_err());
+                assert!(compare_with_i128(cx, num_root.handle(), 12).is_err());
+            });
+        });
+    }
+
+    #[test]
+    fn test_bigint_compare() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                rooted!(in (cx) let mut a_root = UndefinedValue());
+                rooted!(in (cx) let mut b_root = UndefinedValue());
+                new_big_int_from_str(cx, "-900", a_root.handle_mut())
+                    .expect("could not create BigInt");
+                new_big_int_from_str(cx, "-1000", b_root.handle_mut())
+                    .expect("could not create BigInt");
+
+                assert_eq!(
+                    compare(cx, a_root.handle(), b_root.handle()).expect("compare failed"),
+                    Ordering::Greater
+                );
+                assert_eq!(
+                    compare(cx, b_root.handle(), a_root.handle()).expect("compare failed"),
+                    Ordering::Less
+                );
+                assert_eq!(
+                    compare(cx, a_root.handle(), a_root.handle()).expect("compare failed"),
+                    Ordering::Equal
+                );
+                assert_eq!(
+                    compare_with_i128(cx, a_root.handle(), -900).expect("compare failed"),
+                    Ordering::Equal
+                );
+                assert_eq!(
+                    compare_with_i128(cx, a_root.handle(), 5).expect("compare failed"),
+                    Ordering::Less
+                );
+                assert_eq!(
+                    compare_with_u128(cx, a_root.handle(), 0).expect("compare failed"),
+                    Ordering::Less
+                );
+            });
+        });
+    }
 }
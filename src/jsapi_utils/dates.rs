@@ -0,0 +1,139 @@
+use crate::jsapi_utils::objects::{get_es_obj_prop_val, new_from_constructor};
+use crate::jsapi_utils::{get_pending_exception_or_generic_err, EsErrorInfo};
+use mozjs::jsapi::DateGetMsecSinceEpoch;
+use mozjs::jsapi::HandleValueArray;
+use mozjs::jsapi::JSContext;
+use mozjs::jsapi::ObjectIsDate;
+use mozjs::jsval::{DoubleValue, UndefinedValue};
+use mozjs::rust::{HandleObject, MutableHandleObject};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// check if an object is a Date
+pub fn is_date(cx: *mut JSContext, obj: HandleObject) -> bool {
+    let mut is_date = false;
+    let ok = unsafe { ObjectIsDate(cx, obj.into(), &mut is_date) };
+    ok && is_date
+}
+
+/// get the number of milliseconds since the unix epoch of a Date, this is NaN for an invalid Date
+pub fn get_msec_since_epoch(cx: *mut JSContext, obj: HandleObject) -> Result<f64, EsErrorInfo> {
+    let mut msec: f64 = 0.0;
+    if unsafe { DateGetMsecSinceEpoch(cx, obj.into(), &mut msec) } {
+        Ok(msec)
+    } else {
+        Err(get_pending_exception_or_generic_err(
+            cx,
+            "could not get time of Date",
+        ))
+    }
+}
+
+/// create a new Date from the number of milliseconds since the unix epoch
+/// # Example
+/// ```no_run
+/// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+/// use spidermonkey_runtime::jsapi_utils::dates;
+/// use spidermonkey_runtime::jsapi_utils::objects::NULL_JSOBJECT;
+/// use mozjs::rooted;
+///
+/// let rt = EsRuntimeBuilder::new().build();
+/// rt.do_in_es_event_queue_sync(|sm_rt| {
+///     sm_rt.do_with_jsapi(|_rt, cx, global| {
+///         rooted!(in (cx) let mut date_root = NULL_JSOBJECT);
+///         dates::new_date(cx, global, 1000.0, date_root.handle_mut()).ok().expect("could not create Date");
+///         assert!(dates::is_date(cx, date_root.handle()));
+///     });
+/// });
+/// ```
+pub fn new_date(
+    cx: *mut JSContext,
+    global: HandleObject,
+    msec: f64,
+    rval: MutableHandleObject,
+) -> Result<(), EsErrorInfo> {
+    rooted!(in (cx) let mut constructor_root = UndefinedValue());
+    get_es_obj_prop_val(cx, global, "Date", constructor_root.handle_mut())?;
+    // a double is not a gc thing so it needs no rooting
+    let args = vec![DoubleValue(msec)];
+    let args_value_array = unsafe { HandleValueArray::from_rooted_slice(&*args) };
+    new_from_constructor(cx, constructor_root.handle(), args_value_array, rval)
+}
+
+/// convert a SystemTime to the number of milliseconds since the unix epoch
+pub fn system_time_to_msec(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => duration_to_msec(after),
+        Err(err) => -duration_to_msec(err.duration()),
+    }
+}
+
+/// convert the number of milliseconds since the unix epoch to a SystemTime, this returns None for NaN
+pub fn msec_to_system_time(msec: f64) -> Option<SystemTime> {
+    if !msec.is_finite() {
+        None
+    } else if msec >= 0.0 {
+        UNIX_EPOCH.checked_add(msec_to_duration(msec))
+    } else {
+        UNIX_EPOCH.checked_sub(msec_to_duration(-msec))
+    }
+}
+
+// split in whole millis and nanos so whole millis survive the conversion without rounding errors
+fn duration_to_msec(duration: Duration) -> f64 {
+    duration.as_millis() as f64 + (duration.subsec_nanos() % 1_000_000) as f64 / 1_000_000.0
+}
+
+fn msec_to_duration(msec: f64) -> Duration {
+    let whole = msec.trunc();
+    Duration::from_millis(whole as u64)
+        + Duration::from_nanos(((msec - whole) * 1_000_000.0) as u64)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::jsapi_utils;
+    use crate::jsapi_utils::dates::{
+        get_msec_since_epoch, is_date, msec_to_system_time, new_date, system_time_to_msec,
+    };
+    use crate::jsapi_utils::objects::NULL_JSOBJECT;
+    use crate::jsapi_utils::tests::test_with_sm_rt;
+    use mozjs::jsval::UndefinedValue;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_dates() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|rt, cx, global| {
+                rooted!(in (cx) let mut date_root = NULL_JSOBJECT);
+                new_date(cx, global, 1_500_000_000_123.0, date_root.handle_mut())
+                    .expect("could not create Date");
+                assert!(is_date(cx, date_root.handle()));
+                assert_eq!(
+                    get_msec_since_epoch(cx, date_root.handle()).ok().unwrap(),
+                    1_500_000_000_123.0
+                );
+
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                jsapi_utils::eval(rt, global, "({});", "test_dates.es", rval.handle_mut())
+                    .expect("script failed");
+                rooted!(in (cx) let obj_root = rval.to_object());
+                assert!(!is_date(cx, obj_root.handle()));
+            });
+        });
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_system_time_conversion() {
+        let time = UNIX_EPOCH + Duration::from_millis(1234);
+        assert_eq!(system_time_to_msec(time), 1234.0);
+        assert_eq!(msec_to_system_time(1234.0), Some(time));
+
+        let before = UNIX_EPOCH - Duration::from_millis(1000);
+        assert_eq!(system_time_to_msec(before), -1000.0);
+        assert_eq!(msec_to_system_time(-1000.0), Some(before));
+
+        assert_eq!(msec_to_system_time(f64::NAN), None);
+    }
+}
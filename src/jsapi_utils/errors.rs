@@ -0,0 +1,128 @@
+use crate::jsapi_utils;
+use crate::jsapi_utils::objects::{get_es_obj_prop_val, new_from_constructor};
+use crate::jsapi_utils::EsErrorInfo;
+use mozjs::jsapi::ESClass;
+use mozjs::jsapi::GetBuiltinClass;
+use mozjs::jsapi::HandleValueArray;
+use mozjs::jsapi::JSContext;
+use mozjs::jsval::UndefinedValue;
+use mozjs::rust::{HandleObject, MutableHandleObject};
+
+/// the Error constructors which accept a message as their first argument
+const NATIVE_ERROR_NAMES: [&str; 7] = [
+    "Error",
+    "EvalError",
+    "RangeError",
+    "ReferenceError",
+    "SyntaxError",
+    "TypeError",
+    "URIError",
+];
+
+/// check if an object is an Error, this is also true for subclasses like TypeError
+pub fn is_error(cx: *mut JSContext, obj: HandleObject) -> bool {
+    let mut cls = ESClass::Other;
+    let ok = unsafe { GetBuiltinClass(cx, obj.into(), &mut cls) };
+    ok && cls == ESClass::Error
+}
+
+/// create a new Error, if name is one of the native errors (e.g. TypeError) that constructor is used,
+/// else an Error is created and its name property is set
+/// # Example
+/// ```no_run
+/// use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+/// use spidermonkey_runtime::jsapi_utils::errors;
+/// use spidermonkey_runtime::jsapi_utils::objects::NULL_JSOBJECT;
+/// use mozjs::rooted;
+///
+/// let rt = EsRuntimeBuilder::new().build();
+/// rt.do_in_es_event_queue_sync(|sm_rt| {
+///     sm_rt.do_with_jsapi(|_rt, cx, global| {
+///         rooted!(in (cx) let mut err_root = NULL_JSOBJECT);
+///         errors::new_error(cx, global, "TypeError", "that is not a number", err_root.handle_mut())
+///             .ok().expect("could not create Error");
+///         assert!(errors::is_error(cx, err_root.handle()));
+///     });
+/// });
+/// ```
+pub fn new_error(
+    cx: *mut JSContext,
+    global: HandleObject,
+    name: &str,
+    message: &str,
+    rval: MutableHandleObject,
+) -> Result<(), EsErrorInfo> {
+    let is_native = NATIVE_ERROR_NAMES.contains(&name);
+    let constructor_name = if is_native { name } else { "Error" };
+    rooted!(in (cx) let mut constructor_root = UndefinedValue());
+    get_es_obj_prop_val(cx, global, constructor_name, constructor_root.handle_mut())?;
+
+    rooted!(in (cx) let mut message_root = UndefinedValue());
+    jsapi_utils::new_es_value_from_str(cx, message, message_root.handle_mut());
+    let args = vec![message_root.get()];
+    let args_value_array = unsafe { HandleValueArray::from_rooted_slice(&*args) };
+    new_from_constructor(cx, constructor_root.handle(), args_value_array, rval)?;
+
+    if !is_native {
+        rooted!(in (cx) let mut name_root = UndefinedValue());
+        jsapi_utils::new_es_value_from_str(cx, name, name_root.handle_mut());
+        rooted!(in (cx) let err_obj_root = rval.get());
+        jsapi_utils::objects::set_es_obj_prop_value(
+            cx,
+            err_obj_root.handle(),
+            "name",
+            name_root.handle(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::jsapi_utils;
+    use crate::jsapi_utils::errors::{is_error, new_error};
+    use crate::jsapi_utils::objects::{get_es_obj_prop_val_as_string, NULL_JSOBJECT};
+    use crate::jsapi_utils::tests::test_with_sm_rt;
+    use mozjs::jsval::UndefinedValue;
+
+    #[test]
+    fn test_errors() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|rt, cx, global| {
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                jsapi_utils::eval(
+                    rt,
+                    global,
+                    "(new RangeError('out of range'));",
+                    "test_errors.es",
+                    rval.handle_mut(),
+                )
+                .expect("script failed");
+                rooted!(in (cx) let range_err_root = rval.to_object());
+                assert!(is_error(cx, range_err_root.handle()));
+
+                rooted!(in (cx) let mut err_root = NULL_JSOBJECT);
+                new_error(cx, global, "MyError", "oops", err_root.handle_mut())
+                    .expect("could not create Error");
+                assert!(is_error(cx, err_root.handle()));
+                assert_eq!(
+                    get_es_obj_prop_val_as_string(cx, err_root.handle(), "name")
+                        .ok()
+                        .unwrap(),
+                    "MyError"
+                );
+                assert_eq!(
+                    get_es_obj_prop_val_as_string(cx, err_root.handle(), "message")
+                        .ok()
+                        .unwrap(),
+                    "oops"
+                );
+
+                jsapi_utils::eval(rt, global, "({});", "test_errors2.es", rval.handle_mut())
+                    .expect("script failed");
+                rooted!(in (cx) let obj_root = rval.to_object());
+                assert!(!is_error(cx, obj_root.handle()));
+            });
+        });
+    }
+}
@@ -131,12 +131,12 @@ pub fn call_function_name2(
         Err(err)
     } else {
         trace!("call_function_name2 -> unknown err");
-        Err(EsErrorInfo {
-            message: "unknown error".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -234,12 +234,12 @@ pub fn call_function2(
     } else if let Some(err) = get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -264,12 +264,12 @@ pub fn call_function_value2(
     } else if let Some(err) = get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -340,12 +340,12 @@ pub fn call_namespace_function_name2(
         let val: JSVal = *new_subscope_root.handle();
 
         if !val.is_object() {
-            return Err(EsErrorInfo {
-                message: format!("{} was not an object.", obj_name),
-                column: 0,
-                lineno: 0,
-                filename: "".to_string(),
-            });
+            return Err(EsErrorInfo::new(
+                format!("{} was not an object.", obj_name),
+                "".to_string(),
+                0,
+                0,
+            ));
         }
 
         sub_scope = val.to_object();
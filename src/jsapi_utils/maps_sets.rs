@@ -0,0 +1,373 @@
+//! # maps_sets
+//!
+//! utilities for working with Map and Set objects
+//!
+//! # Example
+//!
+//! ```no_run
+//! use spidermonkey_runtime::esruntimebuilder::EsRuntimeBuilder;
+//! use spidermonkey_runtime::jsapi_utils::maps_sets;
+//! use spidermonkey_runtime::jsapi_utils::objects::NULL_JSOBJECT;
+//! use mozjs::jsval::{Int32Value, UndefinedValue};
+//! use mozjs::rooted;
+//!
+//! let rt = EsRuntimeBuilder::new().build();
+//! rt.do_in_es_event_queue_sync(|sm_rt| {
+//!     sm_rt.do_with_jsapi(|_rt, cx, _global| {
+//!         rooted!(in (cx) let mut map_root = NULL_JSOBJECT);
+//!         maps_sets::new_map(cx, map_root.handle_mut());
+//!         rooted!(in (cx) let key_root = Int32Value(1));
+//!         rooted!(in (cx) let val_root = Int32Value(2));
+//!         maps_sets::map_set(cx, map_root.handle(), key_root.handle(), val_root.handle())
+//!             .ok().expect("map_set failed");
+//!         rooted!(in (cx) let mut rval = UndefinedValue());
+//!         maps_sets::map_get(cx, map_root.handle(), key_root.handle(), rval.handle_mut())
+//!             .ok().expect("map_get failed");
+//!         assert_eq!(rval.to_int32(), 2);
+//!     });
+//! });
+//! ```
+
+use crate::jsapi_utils::arrays::{
+    get_array_element, get_array_length, new_array, push_array_element,
+};
+use crate::jsapi_utils::functions::new_native_function;
+use crate::jsapi_utils::objects::NULL_JSOBJECT;
+use crate::jsapi_utils::{get_pending_exception_or_generic_err, EsErrorInfo};
+use mozjs::jsapi::CallArgs;
+use mozjs::jsapi::ESClass;
+use mozjs::jsapi::GetBuiltinClass;
+use mozjs::jsapi::JSContext;
+use mozjs::jsapi::JSNative;
+use mozjs::jsapi::{MapDelete, MapForEach, MapGet, MapHas, MapSet, MapSize, NewMapObject};
+use mozjs::jsapi::{NewSetObject, SetAdd, SetDelete, SetForEach, SetHas, SetSize};
+use mozjs::jsval::{JSVal, ObjectValue, UndefinedValue};
+use mozjs::rust::{HandleObject, HandleValue, MutableHandleObject, MutableHandleValue};
+
+fn get_builtin_class(cx: *mut JSContext, obj: HandleObject) -> ESClass {
+    let mut cls = ESClass::Other;
+    if unsafe { GetBuiltinClass(cx, obj.into(), &mut cls) } {
+        cls
+    } else {
+        ESClass::Other
+    }
+}
+
+fn check_ok(cx: *mut JSContext, ok: bool, gen_err: &'static str) -> Result<(), EsErrorInfo> {
+    if ok {
+        Ok(())
+    } else {
+        Err(get_pending_exception_or_generic_err(cx, gen_err))
+    }
+}
+
+/// check if an object is a Map
+pub fn is_map(cx: *mut JSContext, obj: HandleObject) -> bool {
+    get_builtin_class(cx, obj) == ESClass::Map
+}
+
+/// check if an object is a Set
+pub fn is_set(cx: *mut JSContext, obj: HandleObject) -> bool {
+    get_builtin_class(cx, obj) == ESClass::Set
+}
+
+/// create a new Map
+pub fn new_map(cx: *mut JSContext, rval: MutableHandleObject) {
+    let mut rval = rval;
+    rval.set(unsafe { NewMapObject(cx) });
+}
+
+/// create a new Set
+pub fn new_set(cx: *mut JSContext, rval: MutableHandleObject) {
+    let mut rval = rval;
+    rval.set(unsafe { NewSetObject(cx) });
+}
+
+/// get the number of entries in a Map
+pub fn map_size(cx: *mut JSContext, map: HandleObject) -> u32 {
+    unsafe { MapSize(cx, map.into()) }
+}
+
+/// get a value from a Map, rval is set to undefined if the key is not present
+pub fn map_get(
+    cx: *mut JSContext,
+    map: HandleObject,
+    key: HandleValue,
+    rval: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    let ok = unsafe { MapGet(cx, map.into(), key.into(), rval.into()) };
+    check_ok(cx, ok, "MapGet failed")
+}
+
+/// set a value in a Map
+pub fn map_set(
+    cx: *mut JSContext,
+    map: HandleObject,
+    key: HandleValue,
+    val: HandleValue,
+) -> Result<(), EsErrorInfo> {
+    let ok = unsafe { MapSet(cx, map.into(), key.into(), val.into()) };
+    check_ok(cx, ok, "MapSet failed")
+}
+
+/// check if a Map contains a key
+pub fn map_has(
+    cx: *mut JSContext,
+    map: HandleObject,
+    key: HandleValue,
+) -> Result<bool, EsErrorInfo> {
+    let mut has = false;
+    let ok = unsafe { MapHas(cx, map.into(), key.into(), &mut has) };
+    check_ok(cx, ok, "MapHas failed").map(|_| has)
+}
+
+/// delete a key from a Map, returns true if the key was present
+pub fn map_delete(
+    cx: *mut JSContext,
+    map: HandleObject,
+    key: HandleValue,
+) -> Result<bool, EsErrorInfo> {
+    let mut deleted = false;
+    let ok = unsafe { MapDelete(cx, map.into(), key.into(), &mut deleted) };
+    check_ok(cx, ok, "MapDelete failed").map(|_| deleted)
+}
+
+/// get the number of values in a Set
+pub fn set_size(cx: *mut JSContext, set: HandleObject) -> u32 {
+    unsafe { SetSize(cx, set.into()) }
+}
+
+/// add a value to a Set
+pub fn set_add(cx: *mut JSContext, set: HandleObject, val: HandleValue) -> Result<(), EsErrorInfo> {
+    let ok = unsafe { SetAdd(cx, set.into(), val.into()) };
+    check_ok(cx, ok, "SetAdd failed")
+}
+
+/// check if a Set contains a value
+pub fn set_has(
+    cx: *mut JSContext,
+    set: HandleObject,
+    val: HandleValue,
+) -> Result<bool, EsErrorInfo> {
+    let mut has = false;
+    let ok = unsafe { SetHas(cx, set.into(), val.into(), &mut has) };
+    check_ok(cx, ok, "SetHas failed").map(|_| has)
+}
+
+/// delete a value from a Set, returns true if the value was present
+pub fn set_delete(
+    cx: *mut JSContext,
+    set: HandleObject,
+    val: HandleValue,
+) -> Result<bool, EsErrorInfo> {
+    let mut deleted = false;
+    let ok = unsafe { SetDelete(cx, set.into(), val.into(), &mut deleted) };
+    check_ok(cx, ok, "SetDelete failed").map(|_| deleted)
+}
+
+/// call a consumer for every key and value of a Map in insertion order
+/// this uses MapForEach so it does not depend on Map.prototype or iterators which may be altered by script
+pub fn for_each_map_entry<C>(
+    cx: *mut JSContext,
+    _global: HandleObject,
+    map: HandleObject,
+    mut consumer: C,
+) -> Result<(), EsErrorInfo>
+where
+    C: FnMut(HandleValue, HandleValue),
+{
+    // the keys and values are collected in an array as key, value, key, value...
+    rooted!(in (cx) let mut entries_root = NULL_JSOBJECT);
+    new_array(cx, entries_root.handle_mut());
+    collect_for_each(cx, map, entries_root.handle(), true)?;
+
+    let len = get_array_length(cx, entries_root.handle())?;
+    rooted!(in (cx) let mut key_root = UndefinedValue());
+    rooted!(in (cx) let mut val_root = UndefinedValue());
+    for x in (0..len).step_by(2) {
+        get_array_element(cx, entries_root.handle(), x, key_root.handle_mut())?;
+        get_array_element(cx, entries_root.handle(), x + 1, val_root.handle_mut())?;
+        consumer(key_root.handle(), val_root.handle());
+    }
+    Ok(())
+}
+
+/// call a consumer for every value of a Set in insertion order
+/// this uses SetForEach so it does not depend on Set.prototype or iterators which may be altered by script
+pub fn for_each_set_value<C>(
+    cx: *mut JSContext,
+    _global: HandleObject,
+    set: HandleObject,
+    mut consumer: C,
+) -> Result<(), EsErrorInfo>
+where
+    C: FnMut(HandleValue),
+{
+    rooted!(in (cx) let mut values_root = NULL_JSOBJECT);
+    new_array(cx, values_root.handle_mut());
+    collect_for_each(cx, set, values_root.handle(), false)?;
+
+    let len = get_array_length(cx, values_root.handle())?;
+    rooted!(in (cx) let mut val_root = UndefinedValue());
+    for x in 0..len {
+        get_array_element(cx, values_root.handle(), x, val_root.handle_mut())?;
+        consumer(val_root.handle());
+    }
+    Ok(())
+}
+
+/// collect the entries of a Map or the values of a Set in an array
+fn collect_for_each(
+    cx: *mut JSContext,
+    obj: HandleObject,
+    arr: HandleObject,
+    is_map: bool,
+) -> Result<(), EsErrorInfo> {
+    rooted!(in (cx) let mut func_root = NULL_JSOBJECT);
+    let native: JSNative = if is_map {
+        Some(collect_map_entry)
+    } else {
+        Some(collect_set_value)
+    };
+    new_native_function(cx, "collect", native, func_root.handle_mut());
+    if func_root.is_null() {
+        return Err(get_pending_exception_or_generic_err(
+            cx,
+            "could not create collect function",
+        ));
+    }
+    rooted!(in (cx) let func_val_root = ObjectValue(*func_root));
+    // the array is passed as this to the collect function
+    rooted!(in (cx) let this_val_root = ObjectValue(arr.get()));
+    let ok = unsafe {
+        if is_map {
+            MapForEach(
+                cx,
+                obj.into(),
+                func_val_root.handle().into(),
+                this_val_root.handle().into(),
+            )
+        } else {
+            SetForEach(
+                cx,
+                obj.into(),
+                func_val_root.handle().into(),
+                this_val_root.handle().into(),
+            )
+        }
+    };
+    check_ok(cx, ok, "MapForEach or SetForEach failed")
+}
+
+/// called by MapForEach with (value, key, map), pushes key and value to the array which is passed as this
+unsafe extern "C" fn collect_map_entry(cx: *mut JSContext, argc: u32, vp: *mut JSVal) -> bool {
+    let args = CallArgs::from_vp(vp, argc);
+    rooted!(in (cx) let arr_root = args.thisv().to_object());
+    rooted!(in (cx) let key_root = *args.get(1));
+    rooted!(in (cx) let val_root = *args.get(0));
+    let res = push_array_element(cx, arr_root.handle(), key_root.handle())
+        .and_then(|_| push_array_element(cx, arr_root.handle(), val_root.handle()));
+    args.rval().set(UndefinedValue());
+    res.is_ok()
+}
+
+/// called by SetForEach with (value, value, set), pushes the value to the array which is passed as this
+unsafe extern "C" fn collect_set_value(cx: *mut JSContext, argc: u32, vp: *mut JSVal) -> bool {
+    let args = CallArgs::from_vp(vp, argc);
+    rooted!(in (cx) let arr_root = args.thisv().to_object());
+    rooted!(in (cx) let val_root = *args.get(0));
+    let res = push_array_element(cx, arr_root.handle(), val_root.handle());
+    args.rval().set(UndefinedValue());
+    res.is_ok()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::jsapi_utils;
+    use crate::jsapi_utils::maps_sets::{
+        for_each_map_entry, for_each_set_value, is_map, is_set, map_delete, map_get, map_has,
+        map_set, map_size, new_map, new_set, set_add, set_delete, set_has, set_size,
+    };
+    use crate::jsapi_utils::objects::NULL_JSOBJECT;
+    use crate::jsapi_utils::tests::test_with_sm_rt;
+    use mozjs::jsval::{Int32Value, UndefinedValue};
+
+    #[test]
+    fn test_map() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|_rt, cx, global| {
+                rooted!(in (cx) let mut map_root = NULL_JSOBJECT);
+                new_map(cx, map_root.handle_mut());
+                assert!(is_map(cx, map_root.handle()));
+                assert!(!is_set(cx, map_root.handle()));
+
+                rooted!(in (cx) let key_root = Int32Value(1));
+                rooted!(in (cx) let val_root = Int32Value(2));
+                map_set(cx, map_root.handle(), key_root.handle(), val_root.handle())
+                    .expect("map_set failed");
+                assert_eq!(map_size(cx, map_root.handle()), 1);
+                assert!(map_has(cx, map_root.handle(), key_root.handle())
+                    .ok()
+                    .unwrap());
+
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                map_get(cx, map_root.handle(), key_root.handle(), rval.handle_mut())
+                    .expect("map_get failed");
+                assert_eq!(rval.to_int32(), 2);
+
+                let mut entries = vec![];
+                for_each_map_entry(cx, global, map_root.handle(), |k, v| {
+                    entries.push((k.to_int32(), v.to_int32()));
+                })
+                .expect("for_each_map_entry failed");
+                assert_eq!(entries, vec![(1, 2)]);
+
+                assert!(map_delete(cx, map_root.handle(), key_root.handle())
+                    .ok()
+                    .unwrap());
+                assert!(!map_has(cx, map_root.handle(), key_root.handle())
+                    .ok()
+                    .unwrap());
+            });
+        });
+    }
+
+    #[test]
+    fn test_set() {
+        test_with_sm_rt(|sm_rt| {
+            sm_rt.do_with_jsapi(|rt, cx, global| {
+                rooted!(in (cx) let mut rval = UndefinedValue());
+                jsapi_utils::eval(
+                    rt,
+                    global,
+                    "(new Set([3, 1, 2]));",
+                    "test_set.es",
+                    rval.handle_mut(),
+                )
+                .expect("script failed");
+                rooted!(in (cx) let set_root = rval.to_object());
+                assert!(is_set(cx, set_root.handle()));
+                assert_eq!(set_size(cx, set_root.handle()), 3);
+
+                let mut values = vec![];
+                for_each_set_value(cx, global, set_root.handle(), |v| {
+                    values.push(v.to_int32());
+                })
+                .expect("for_each_set_value failed");
+                assert_eq!(values, vec![3, 1, 2]);
+
+                rooted!(in (cx) let mut new_set_root = NULL_JSOBJECT);
+                new_set(cx, new_set_root.handle_mut());
+                rooted!(in (cx) let val_root = Int32Value(7));
+                set_add(cx, new_set_root.handle(), val_root.handle()).expect("set_add failed");
+                assert!(set_has(cx, new_set_root.handle(), val_root.handle())
+                    .ok()
+                    .unwrap());
+                assert!(set_delete(cx, new_set_root.handle(), val_root.handle())
+                    .ok()
+                    .unwrap());
+                assert_eq!(set_size(cx, new_set_root.handle()), 0);
+            });
+        });
+    }
+}
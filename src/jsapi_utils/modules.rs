@@ -1,29 +1,50 @@
-use crate::esruntime::EsRuntime;
+use crate::esmoduleloader::{normalize, resolve_relative_path, LoaderError};
+use crate::esruntime::{EsModuleCacheStats, EsRuntime, ImportMetaHook};
+use crate::esruntimeinner::EsRuntimeInner;
 use crate::jsapi_utils;
 use crate::jsapi_utils::objects::NULL_JSOBJECT;
 use crate::jsapi_utils::rooting::EsPersistentRooted;
-use crate::jsapi_utils::{get_pending_exception, report_exception2, EsErrorInfo};
+use crate::jsapi_utils::{
+    error_info_from_thrown_value, get_pending_exception, report_exception2, EsErrorInfo,
+};
 use crate::spidermonkeyruntimewrapper::{register_cached_object, SmRuntime, SM_RT};
+use futures::channel::{mpsc, oneshot};
+use futures::executor::LocalPool;
+use futures::task::SpawnExt;
+use futures::StreamExt;
 use hirofa_utils::js_utils::Script;
 use log::trace;
 use lru::LruCache;
-use mozjs::jsapi::DynamicImportStatus;
-use mozjs::jsapi::FinishDynamicModuleImport_NoTLA;
+use mozjs::jsapi::FinishDynamicModuleImport;
+use mozjs::jsapi::GetModuleNamespace;
 use mozjs::jsapi::Handle as RawHandle;
 use mozjs::jsapi::HandleObject as RawHandleObject;
 use mozjs::jsapi::HandleValue as RawHandleValue;
 use mozjs::jsapi::JSContext;
 use mozjs::jsapi::JSObject;
 use mozjs::jsapi::JSString;
+use mozjs::jsapi::PromiseState;
 use mozjs::jsapi::SetModuleDynamicImportHook;
 use mozjs::jsapi::SetModuleMetadataHook;
 use mozjs::jsapi::SetModulePrivate;
 use mozjs::jsapi::SetModuleResolveHook;
+use mozjs::jsapi::{GetRequestedModuleSpecifier, GetRequestedModules};
 use mozjs::jsval::UndefinedValue;
 use mozjs::jsval::{NullValue, ObjectValue, StringValue};
-use mozjs::rust::{transform_u16_to_source_text, Runtime};
-use std::cell::RefCell;
+use mozjs::rust::{
+    transform_u16_to_source_text, HandleObject, HandleValue, MutableHandleObject,
+    MutableHandleValue, Runtime,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
 use std::ptr;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 /// prepare a Runtime for working with modules
 /// this initializes the methods needed to load modules from script
@@ -39,11 +60,101 @@ pub(crate) fn init_runtime_for_modules(rt: &Runtime) {
 }
 
 /// compile a module script, this does not cache the module, use SmRuntime::load_module for that
+/// the modules it imports are not loaded, it can only import modules which are cached already
 /// it runs CompileModule, ModuleInstantiate and ModuleEvaluate and returns an EsErrorInfo if any one fails
+/// when the module uses top level await it may still be running when this returns, use compile_module_with_promise
+/// if you need to know when it is done
 pub fn compile_module(
     context: *mut JSContext,
     src: &str,
     file_name: &str,
+) -> Result<*mut JSObject, EsErrorInfo> {
+    rooted!(in (context) let mut evaluation_promise_root = UndefinedValue());
+    let compiled_module = compile_module_with_promise(
+        context,
+        src,
+        file_name,
+        evaluation_promise_root.handle_mut(),
+    )?;
+    if evaluation_promise_root.is_object() {
+        rooted!(in (context) let evaluation_promise_obj_root = evaluation_promise_root.to_object());
+        if jsapi_utils::promises::get_promise_state(evaluation_promise_obj_root.handle())
+            == PromiseState::Rejected
+        {
+            rooted!(in (context) let reason_root = jsapi_utils::promises::get_promise_result(evaluation_promise_obj_root.handle()));
+            return Err(error_info_from_thrown_value(context, reason_root.handle()));
+        }
+    }
+    Ok(compiled_module)
+}
+
+/// compile a module script like compile_module, the Promise which is returned by ModuleEvaluate is set in
+/// evaluation_promise, when the module uses top level await that Promise is settled when the module is done
+pub fn compile_module_with_promise(
+    context: *mut JSContext,
+    src: &str,
+    file_name: &str,
+    evaluation_promise: MutableHandleValue,
+) -> Result<*mut JSObject, EsErrorInfo> {
+    let compiled_module = compile_module_only(context, src, file_name)?;
+
+    rooted!(in(context) let module_script_root = compiled_module);
+    instantiate_and_evaluate_module(
+        context,
+        module_script_root.handle(),
+        file_name,
+        evaluation_promise,
+    )?;
+
+    Ok(compiled_module)
+}
+
+/// run ModuleInstantiate and ModuleEvaluate for a compiled module, for a module which was already evaluated
+/// this just returns its evaluation Promise
+fn instantiate_and_evaluate_module(
+    context: *mut JSContext,
+    module: HandleObject,
+    file_name: &str,
+    evaluation_promise: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    trace!("ModuleInstantiate: {}", file_name);
+
+    let res = unsafe { mozjs::rust::wrappers::ModuleInstantiate(context, module) };
+    if !res {
+        if let Some(err) = get_pending_exception(context) {
+            return Err(err);
+        }
+        return Err(EsErrorInfo::new(
+            "ModuleInstantiate failed unknown".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ));
+    }
+
+    trace!("ModuleEvaluate: {}", file_name);
+    let res = unsafe { mozjs::rust::wrappers::ModuleEvaluate(context, module, evaluation_promise) };
+    if !res {
+        if let Some(err) = get_pending_exception(context) {
+            return Err(err);
+        }
+        return Err(EsErrorInfo::new(
+            "ModuleEvaluate failed unknown".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ));
+    }
+
+    Ok(())
+}
+
+/// compile a module script without instantiating or evaluating it, the modules it imports are instantiated and
+/// evaluated together with the importing module
+fn compile_module_only(
+    context: *mut JSContext,
+    src: &str,
+    file_name: &str,
 ) -> Result<*mut JSObject, EsErrorInfo> {
     // use mozjs::jsapi::CompileModule; todo, how are the wrapped ones different?
     // https://doc.servo.org/mozjs/jsapi/fn.CompileModule.html
@@ -58,7 +169,7 @@ pub fn compile_module(
     let compiled_module: *mut JSObject =
         unsafe { mozjs::jsapi::CompileModule(context, options.ptr, &mut source) };
 
-    rooted!(in(context) let mut module_script_root = compiled_module);
+    rooted!(in(context) let module_script_root = compiled_module);
 
     // see ModuleInstantiate
     if module_script_root.is_null() {
@@ -66,12 +177,12 @@ pub fn compile_module(
         if let Some(err) = get_pending_exception(context) {
             return Err(err);
         }
-        return Err(EsErrorInfo {
-            message: "CompileModule failed unknown".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        });
+        return Err(EsErrorInfo::new(
+            "CompileModule failed unknown".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ));
     }
 
     trace!("SetModulePrivate: {}", file_name);
@@ -89,49 +200,610 @@ pub fn compile_module(
     );
     unsafe { SetModulePrivate(compiled_module, &ObjectValue(*private_obj_root)) };
 
-    trace!("ModuleInstantiate: {}", file_name);
+    Ok(compiled_module)
+}
 
-    let res =
-        unsafe { mozjs::rust::wrappers::ModuleInstantiate(context, module_script_root.handle()) };
-    if !res {
-        if let Some(err) = get_pending_exception(context) {
-            return Err(err);
+/// receives the result of the evaluation of a module which uses top level await
+pub(crate) type ModuleEvaluation = oneshot::Receiver<Result<(), EsErrorInfo>>;
+
+/// check the Promise returned by ModuleEvaluate, this returns a ModuleEvaluation if the Promise is still pending
+/// or an EsErrorInfo if it was rejected
+pub(crate) fn get_module_evaluation(
+    cx: *mut JSContext,
+    evaluation_promise: HandleValue,
+) -> Result<Option<ModuleEvaluation>, EsErrorInfo> {
+    if !evaluation_promise.is_object() {
+        // top level await is not enabled, the module was evaluated synchronously
+        return Ok(None);
+    }
+    rooted!(in (cx) let promise_root = evaluation_promise.to_object());
+    match jsapi_utils::promises::get_promise_state(promise_root.handle()) {
+        PromiseState::Fulfilled => Ok(None),
+        PromiseState::Rejected => {
+            rooted!(in (cx) let reason_root = jsapi_utils::promises::get_promise_result(promise_root.handle()));
+            Err(error_info_from_thrown_value(cx, reason_root.handle()))
+        }
+        PromiseState::Pending => {
+            let (tx, rx) = oneshot::channel();
+            // both reactions need the sender but only one of them will ever be called
+            let then_tx = Rc::new(RefCell::new(Some(tx)));
+            let catch_tx = then_tx.clone();
+            jsapi_utils::promises::add_promise_reactions_callbacks(
+                cx,
+                promise_root.handle(),
+                Some(
+                    move |_cx, _args: Vec<HandleValue>, _rval: MutableHandleValue| {
+                        if let Some(tx) = then_tx.borrow_mut().take() {
+                            let _ = tx.send(Ok(()));
+                        }
+                        Ok(())
+                    },
+                ),
+                Some(
+                    move |cx, args: Vec<HandleValue>, _rval: MutableHandleValue| {
+                        if let Some(tx) = catch_tx.borrow_mut().take() {
+                            let _ = tx.send(Err(error_info_from_thrown_value(cx, args[0])));
+                        }
+                        Ok(())
+                    },
+                ),
+            );
+            Ok(Some(rx))
         }
-        return Err(EsErrorInfo {
-            message: "ModuleInstantiate failed unknown".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        });
     }
+}
 
-    trace!("ModuleEvaluate: {}", file_name);
-    rooted!(in (context) let mut _module_rval = UndefinedValue());
-    let res = unsafe {
-        mozjs::rust::wrappers::ModuleEvaluate(
-            context,
-            module_script_root.handle(),
-            _module_rval.handle_mut(),
-        )
+/// wait until a module which uses top level await is done
+pub(crate) async fn wait_for_module_evaluation(
+    evaluation: Option<ModuleEvaluation>,
+) -> Result<(), EsErrorInfo> {
+    match evaluation {
+        None => Ok(()),
+        Some(receiver) => receiver.await.unwrap_or_else(|_canceled| {
+            Err(EsErrorInfo::new(
+                "module evaluation was cancelled".to_string(),
+                "".to_string(),
+                0,
+                0,
+            ))
+        }),
+    }
+}
+
+thread_local! {
+// store epr in Box because https://doc.servo.org/mozjs_sys/jsgc/struct.Heap.html#method.boxed
+    static MODULE_CACHE: RefCell<LruCache<String, EsPersistentRooted>> = RefCell::new(init_module_cache());
+    /// the path of the script of every cached module, by cache key
+    static MODULE_FILES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    /// modules which were fetched before they were imported, see fetch_module_graph()
+    static PREFETCHED_MODULES: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    static MODULE_CACHE_HITS: Cell<usize> = Cell::new(0);
+    static MODULE_CACHE_MISSES: Cell<usize> = Cell::new(0);
+    static IMPORT_META_HOOK: RefCell<Option<Arc<ImportMetaHook>>> = RefCell::new(None);
+}
+
+/// add a compiled module to the module cache so it can be imported by its cache key, see get_module_cache_key()
+/// path is the path of the script the module was compiled from
+pub(crate) fn cache_module(
+    cx: *mut JSContext,
+    key: &str,
+    path: &str,
+    compiled_module: *mut JSObject,
+) {
+    MODULE_CACHE.with(|cache_rc| {
+        trace!("caching module for {}", key);
+        let cache = &mut *cache_rc.borrow_mut();
+        cache.put(
+            key.to_string(),
+            EsPersistentRooted::new_from_obj(cx, compiled_module),
+        );
+    });
+    MODULE_FILES.with(|files_rc| {
+        let files = &mut *files_rc.borrow_mut();
+        files.insert(key.to_string(), path.to_string());
+    });
+}
+
+fn get_modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn record_cache_lookup(key: &str, cached: bool) {
+    // a module which was fetched before it was imported is not really a hit
+    let prefetched = cached && PREFETCHED_MODULES.with(|p_rc| p_rc.borrow_mut().remove(key));
+    let counter = if cached && !prefetched {
+        &MODULE_CACHE_HITS
+    } else {
+        &MODULE_CACHE_MISSES
     };
-    if !res {
-        if let Some(err) = get_pending_exception(context) {
-            return Err(err);
+    counter.with(|c| c.set(c.get() + 1));
+}
+
+/// remove a module from the cache, path may be the key the module was cached by or the path of its script
+/// returns true if a module was removed
+pub(crate) fn invalidate_module(path: &str) -> bool {
+    let keys: Vec<String> = MODULE_FILES.with(|files_rc| {
+        let files = &*files_rc.borrow();
+        files
+            .iter()
+            .filter(|(key, file_path)| key.as_str() == path || file_path.as_str() == path)
+            .map(|(key, _file_path)| key.clone())
+            .collect()
+    });
+    remove_cached_modules(&keys)
+}
+
+/// remove the modules with these keys from the cache, returns true if a module was removed
+fn remove_cached_modules(keys: &[String]) -> bool {
+    MODULE_FILES.with(|files_rc| {
+        let files = &mut *files_rc.borrow_mut();
+        for key in keys {
+            files.remove(key);
+        }
+    });
+    PREFETCHED_MODULES.with(|p_rc| {
+        let prefetched = &mut *p_rc.borrow_mut();
+        for key in keys {
+            prefetched.remove(key);
         }
-        return Err(EsErrorInfo {
-            message: "ModuleEvaluate failed unknown".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
+    });
+    MODULE_CACHE.with(|cache_rc| {
+        let cache = &mut *cache_rc.borrow_mut();
+        let mut removed = false;
+        for key in keys {
+            trace!("invalidating cached module {}", key);
+            removed |= cache.pop(key).is_some();
+        }
+        removed
+    })
+}
+
+/// remove all modules from the cache
+pub(crate) fn clear_module_cache() {
+    MODULE_CACHE.with(|cache_rc| cache_rc.borrow_mut().clear());
+    MODULE_FILES.with(|files_rc| files_rc.borrow_mut().clear());
+    PREFETCHED_MODULES.with(|p_rc| p_rc.borrow_mut().clear());
+}
+
+/// get the hit and miss counts and the size of the module cache
+pub(crate) fn get_module_cache_stats() -> EsModuleCacheStats {
+    let (size, capacity) = MODULE_CACHE.with(|cache_rc| {
+        let cache = &*cache_rc.borrow();
+        (cache.len(), cache.cap())
+    });
+    EsModuleCacheStats {
+        hits: MODULE_CACHE_HITS.with(|c| c.get()),
+        misses: MODULE_CACHE_MISSES.with(|c| c.get()),
+        size,
+        capacity,
+    }
+}
+
+/// get the cache keys of the cached modules, native modules are skipped
+pub(crate) fn get_cached_module_keys() -> Vec<String> {
+    MODULE_FILES.with(|files_rc| {
+        let files = &mut *files_rc.borrow_mut();
+        // forget modules which were evicted from the cache
+        MODULE_CACHE.with(|cache_rc| {
+            let cache = &*cache_rc.borrow();
+            files.retain(|key, _file_path| cache.contains(key));
         });
+        files
+            .keys()
+            .filter(|key| crate::esnativemodule::get_native_module_script(key.as_str()).is_none())
+            .cloned()
+            .collect()
+    })
+}
+
+/// the ModuleWatcher keeps the last modified times of the files of the cached modules, it is used by the thread
+/// started with EsRuntime::start_module_watcher()
+#[derive(Default)]
+pub(crate) struct ModuleWatcher {
+    modified: HashMap<String, Option<SystemTime>>,
+}
+
+impl ModuleWatcher {
+    /// check the files of all cached modules and reload the modules of which the file was modified since the last
+    /// check, the file of a module which was cached after the last check is only watched from now on
+    /// the file of a module is the path the module code loader resolves its cache key to, so modules which were
+    /// loaded with a relative file name are watched too
+    /// a reloaded module is evaluated again and replaces the old module in the cache, modules which already
+    /// imported the old module keep using it
+    ///
+    /// the files are checked and loaded in the calling thread and the modules a reloaded module imports are fetched
+    /// in the helper threads, only compiling and evaluating happens in the worker thread, so this should not be
+    /// called from the worker thread
+    /// returns the paths of the reloaded modules
+    pub(crate) fn reload_changed_modules(&mut self, rt: &Arc<EsRuntimeInner>) -> Vec<String> {
+        let keys = rt.do_in_es_event_queue_sync(|_sm_rt| get_cached_module_keys());
+        // group the cache keys by the specifier a module is reloaded by, a module may be cached by more than one key
+        let mut keys_by_specifier: HashMap<String, Vec<String>> = HashMap::new();
+        for key in keys {
+            let (module_type, name) = get_module_type(key.as_str());
+            let path = match resolve_module_path(rt, name, name) {
+                Ok(path) => path,
+                // not a file the loader can load
+                Err(_) => continue,
+            };
+            // keep the type prefix of the key so json and text modules are reloaded as such
+            keys_by_specifier
+                .entry(format!("{}{}", module_type.prefix(), path))
+                .or_insert_with(Vec::new)
+                .push(key);
+        }
+        self.modified
+            .retain(|specifier, _modified| keys_by_specifier.contains_key(specifier));
+
+        let mut reloaded = vec![];
+        for (specifier, keys) in keys_by_specifier {
+            let path = get_module_type(specifier.as_str()).1.to_string();
+            let modified = get_modified(path.as_str());
+            match self.modified.insert(specifier.clone(), modified) {
+                Some(prev_modified) if prev_modified != modified && modified.is_some() => {}
+                _ => continue,
+            }
+
+            let script = match load_module_code(rt, specifier.as_str(), path.as_str()) {
+                Ok(script) => script,
+                Err(err) => {
+                    log::error!("could not reload module {}: {}", path, err);
+                    continue;
+                }
+            };
+
+            trace!("reloading changed module {}", path);
+            match reload_module(rt, keys, script) {
+                Ok(()) => reloaded.push(path),
+                Err(err) => log::error!("could not reload module {}: {}", path, err.err_msg()),
+            }
+        }
+        reloaded
     }
+}
 
-    Ok(compiled_module)
+/// compile a reloaded module and cache it by its keys, the modules it imports which are not cached yet are fetched
+/// before it is evaluated, like load_module_async does
+/// a module which fails is removed from the cache
+fn reload_module(
+    rt: &Arc<EsRuntimeInner>,
+    keys: Vec<String>,
+    script: Script,
+) -> Result<(), EsErrorInfo> {
+    let compile_keys = keys.clone();
+    let requests = rt.do_in_es_event_queue_sync(move |sm_rt| {
+        sm_rt.do_with_jsapi(|_rt, cx, _global| {
+            remove_cached_modules(&compile_keys);
+            let compiled_module = compile_module_only(cx, script.get_code(), script.get_path())?;
+            rooted!(in (cx) let module_root = compiled_module);
+            for key in &compile_keys {
+                cache_module(cx, key.as_str(), script.get_path(), compiled_module);
+            }
+            get_uncached_imports(cx, module_root.handle(), script.get_path())
+        })
+    });
+
+    let evaluate_keys = keys.clone();
+    let res = requests
+        .and_then(|requests| futures::executor::block_on(fetch_module_graph(rt.clone(), requests)))
+        .and_then(|_| {
+            rt.do_in_es_event_queue_sync(move |sm_rt| {
+                sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                    rooted!(in (cx) let mut evaluation_promise_root = UndefinedValue());
+                    evaluate_cached_module(
+                        cx,
+                        evaluate_keys[0].as_str(),
+                        evaluation_promise_root.handle_mut(),
+                    )?;
+                    // a reloaded module which uses top level await is not waited for
+                    get_module_evaluation(cx, evaluation_promise_root.handle())
+                        .map(|_evaluation| ())
+                })
+            })
+        });
+    if res.is_err() {
+        rt.do_in_es_event_queue_sync(move |_sm_rt| remove_cached_modules(&keys));
+    }
+    res
 }
 
-thread_local! {
-// store epr in Box because https://doc.servo.org/mozjs_sys/jsgc/struct.Heap.html#method.boxed
-    static MODULE_CACHE: RefCell<LruCache<String, EsPersistentRooted>> = RefCell::new(init_module_cache());
+/// a future which is driven by the module fetcher thread
+type FetchFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+lazy_static! {
+    /// the fetches of dynamic imports are driven by a single thread, a fetch waits for loads which run in the helper
+    /// thread pool so it should never occupy a helper thread itself
+    static ref MODULE_FETCHER: Mutex<mpsc::UnboundedSender<FetchFuture>> =
+        Mutex::new(start_module_fetcher());
+}
+
+fn start_module_fetcher() -> mpsc::UnboundedSender<FetchFuture> {
+    let (tx, rx) = mpsc::unbounded::<FetchFuture>();
+    std::thread::Builder::new()
+        .name("module_fetcher".to_string())
+        .spawn(move || {
+            let mut pool = LocalPool::new();
+            let spawner = pool.spawner();
+            pool.run_until(rx.for_each(move |fetch| {
+                if let Err(err) = spawner.spawn(fetch) {
+                    log::error!("module fetcher could not spawn a fetch: {}", err);
+                }
+                futures::future::ready(())
+            }));
+        })
+        .expect("could not start the module fetcher thread");
+    tx
+}
+
+/// run a fetch in the module fetcher thread
+fn add_fetch_task(fetch: FetchFuture) {
+    let fetcher = MODULE_FETCHER.lock().unwrap();
+    if fetcher.unbounded_send(fetch).is_err() {
+        log::error!("module fetcher thread is gone");
+    }
+}
+
+/// a static import of a module which is not in the cache yet
+pub(crate) struct ModuleRequest {
+    /// the specifier after applying the import map, this is passed to the module code loader
+    file_name: String,
+    /// the path of the importing module
+    ref_path: String,
+    /// the key of the module in the cache, see get_module_cache_key()
+    key: String,
+}
+
+fn get_cached_module(key: &str) -> Option<*mut JSObject> {
+    MODULE_CACHE.with(|cache_rc| {
+        let cache = &mut *cache_rc.borrow_mut();
+        cache.get(&key.to_string()).map(|mpr| mpr.get())
+    })
+}
+
+/// get the static imports of a compiled module which are not in the cache yet, native modules are skipped
+fn get_uncached_imports(
+    cx: *mut JSContext,
+    module: HandleObject,
+    ref_path: &str,
+) -> Result<Vec<ModuleRequest>, EsErrorInfo> {
+    let es_rt_inner = SmRuntime::clone_current_esrt_inner_arc();
+    rooted!(in (cx) let requested_root = unsafe { GetRequestedModules(cx, module.into()) });
+    let len = jsapi_utils::arrays::get_array_length(cx, requested_root.handle())?;
+    let mut requests = vec![];
+    for idx in 0..len {
+        rooted!(in (cx) let mut requested_val_root = UndefinedValue());
+        jsapi_utils::arrays::get_array_element(
+            cx,
+            requested_root.handle(),
+            idx,
+            requested_val_root.handle_mut(),
+        )?;
+        let specifier_str =
+            unsafe { GetRequestedModuleSpecifier(cx, requested_val_root.handle().into()) };
+        let file_name = apply_import_map(
+            &es_rt_inner,
+            jsapi_utils::es_jsstring_to_string(cx, specifier_str),
+            ref_path,
+        );
+        if crate::esnativemodule::get_native_module_script(file_name.as_str()).is_some() {
+            continue;
+        }
+        let key = get_module_cache_key(&es_rt_inner, file_name.as_str(), ref_path);
+        if get_cached_module(key.as_str()).is_none() {
+            requests.push(ModuleRequest {
+                file_name,
+                ref_path: ref_path.to_string(),
+                key,
+            });
+        }
+    }
+    Ok(requests)
+}
+
+/// compile a module without evaluating it and add it to the cache, the modules it imports are loaded when it is
+/// evaluated, or before that with fetch_module_graph()
+/// returns the static imports of the module which are not cached yet
+pub(crate) fn compile_and_cache_module(
+    cx: *mut JSContext,
+    key: &str,
+    path: &str,
+    code: &str,
+) -> Result<Vec<ModuleRequest>, EsErrorInfo> {
+    let compiled_module = compile_module_only(cx, code, path)?;
+    rooted!(in (cx) let module_root = compiled_module);
+    cache_module(cx, key, path, compiled_module);
+    get_uncached_imports(cx, module_root.handle(), path)
+}
+
+/// instantiate and evaluate a module which was added to the cache with compile_and_cache_module()
+pub(crate) fn evaluate_cached_module(
+    cx: *mut JSContext,
+    key: &str,
+    evaluation_promise: MutableHandleValue,
+) -> Result<(), EsErrorInfo> {
+    let module = get_cached_module(key).ok_or_else(|| {
+        EsErrorInfo::new(
+            format!("module {} is not cached", key),
+            key.to_string(),
+            0,
+            0,
+        )
+    })?;
+    rooted!(in (cx) let module_root = module);
+    instantiate_and_evaluate_module(cx, module_root.handle(), key, evaluation_promise)
+}
+
+/// load the modules of the requests and all modules they import with the module code loader
+/// the loader is called for all modules of the same depth in parallel in the helper threads, the loaded modules
+/// are compiled and cached in the worker thread so the import hook does not have to call the loader
+/// all modules which could not be loaded or compiled are reported in a single EsErrorInfo
+///
+/// this should not be awaited in the worker thread
+pub(crate) async fn fetch_module_graph(
+    rt: Arc<EsRuntimeInner>,
+    requests: Vec<ModuleRequest>,
+) -> Result<(), EsErrorInfo> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut failures: Vec<String> = vec![];
+    let mut pending = requests;
+
+    while !pending.is_empty() {
+        let mut loads = vec![];
+        let mut names = vec![];
+        for request in pending.drain(..) {
+            if !seen.insert(request.key.clone()) {
+                continue;
+            }
+            names.push((request.file_name.clone(), request.ref_path.clone()));
+            let (tx, rx) = oneshot::channel();
+            let rt = rt.clone();
+            EsRuntime::add_helper_task(move || {
+                trace!("fetch_module_graph: loading {}", request.file_name);
+                let script_res =
+                    load_module_code(&rt, request.file_name.as_str(), request.ref_path.as_str());
+                let _ = tx.send((request, script_res));
+            });
+            loads.push(rx);
+        }
+
+        let loaded = futures::future::join_all(loads).await;
+        for ((file_name, ref_path), load) in names.into_iter().zip(loaded) {
+            let (request, script_res) = match load {
+                Ok(loaded) => loaded,
+                Err(_canceled) => {
+                    // the helper task was dropped before it loaded the module
+                    failures.push(format!(
+                        "{} (imported by {}): the load was cancelled",
+                        file_name, ref_path
+                    ));
+                    continue;
+                }
+            };
+            let script = match script_res {
+                Ok(script) => script,
+                Err(err) => {
+                    failures.push(format!(
+                        "{} (imported by {}): {}",
+                        request.file_name, request.ref_path, err
+                    ));
+                    continue;
+                }
+            };
+            let key = request.key.clone();
+            let compile_res = rt
+                .do_in_es_event_queue_async(move |sm_rt| {
+                    sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                        // it may have been imported while we were loading it
+                        if get_cached_module(key.as_str()).is_some() {
+                            return Ok(vec![]);
+                        }
+                        let requests = compile_and_cache_module(
+                            cx,
+                            key.as_str(),
+                            script.get_path(),
+                            script.get_code(),
+                        )?;
+                        PREFETCHED_MODULES.with(|p_rc| p_rc.borrow_mut().insert(key));
+                        Ok(requests)
+                    })
+                })
+                .await
+                .and_then(|res| res);
+            match compile_res {
+                Ok(requests) => pending.extend(requests),
+                Err(err) => failures.push(format!(
+                    "{} (imported by {}): {}",
+                    request.file_name,
+                    request.ref_path,
+                    err.err_msg()
+                )),
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(EsErrorInfo::new(
+            format!("could not load modules: {}", failures.join(", ")),
+            "".to_string(),
+            0,
+            0,
+        ))
+    }
+}
+
+/// fetch the static imports of a module which was compiled with SmRuntime::prepare_module() and evaluate it when
+/// they are all loaded, this waits until a module which uses top level await is done
+/// a module which fails is removed from the cache
+///
+/// this should not be awaited in the worker thread
+pub(crate) async fn fetch_and_evaluate_module(
+    rt: Arc<EsRuntimeInner>,
+    file_name: String,
+    requests: Vec<ModuleRequest>,
+) -> Result<(), EsErrorInfo> {
+    if let Err(err) = fetch_module_graph(rt.clone(), requests).await {
+        rt.do_in_es_event_queue_async(move |_sm_rt| {
+            invalidate_module(file_name.as_str());
+        })
+        .await?;
+        return Err(err);
+    }
+
+    let evaluation = rt
+        .do_in_es_event_queue_async(move |sm_rt| sm_rt.evaluate_module(file_name.as_str()))
+        .await??;
+    wait_for_module_evaluation(evaluation).await
+}
+
+/// run fetch_and_evaluate_module() in the module fetcher thread, this may be called from the worker thread
+/// the returned ModuleEvaluation receives the result, when it is dropped a failure is logged
+pub(crate) fn fetch_and_evaluate_module_in_fetcher(
+    rt: Arc<EsRuntimeInner>,
+    file_name: String,
+    requests: Vec<ModuleRequest>,
+) -> ModuleEvaluation {
+    let (tx, rx) = oneshot::channel();
+    add_fetch_task(Box::pin(async move {
+        let log_name = file_name.clone();
+        let res = fetch_and_evaluate_module(rt, file_name, requests).await;
+        if let Err(Err(err)) = tx.send(res) {
+            log::error!("module {} failed: {}", log_name, err.err_msg());
+        }
+    }));
+    rx
+}
+
+/// get the namespace object (the object you get with import * as ns) of a cached module by its cache key
+pub(crate) fn get_module_namespace(
+    cx: *mut JSContext,
+    key: &str,
+    rval: MutableHandleObject,
+) -> Result<(), EsErrorInfo> {
+    let module = get_cached_module(key).ok_or_else(|| {
+        EsErrorInfo::new(
+            format!("module {} is not cached", key),
+            key.to_string(),
+            0,
+            0,
+        )
+    })?;
+    rooted!(in (cx) let module_root = module);
+    let namespace = unsafe { GetModuleNamespace(cx, module_root.handle().into()) };
+    if namespace.is_null() {
+        return Err(get_pending_exception(cx).unwrap_or_else(|| {
+            EsErrorInfo::new(
+                format!("could not get namespace of module {}", key),
+                key.to_string(),
+                0,
+                0,
+            )
+        }));
+    }
+    let mut rval = rval;
+    rval.set(namespace);
+    Ok(())
 }
 
 /// this initializes the LryCache based on your settings
@@ -161,6 +833,81 @@ fn get_path_from_module_private(cx: *mut JSContext, reference_private: RawHandle
     "(unknown)".to_string()
 }
 
+/// the type of a module, this is determined by a prefix of the specifier (json: or text:)
+/// a specifier without a prefix is always loaded as JavaScript, also when it ends with .json
+#[derive(Debug, PartialEq)]
+enum ModuleType {
+    JavaScript,
+    Json,
+    Text,
+}
+
+impl ModuleType {
+    /// the prefix of a specifier of a module of this type
+    fn prefix(&self) -> &'static str {
+        match self {
+            ModuleType::JavaScript => "",
+            ModuleType::Json => "json:",
+            ModuleType::Text => "text:",
+        }
+    }
+}
+
+/// get the type of a module and the specifier without the type prefix
+fn get_module_type(specifier: &str) -> (ModuleType, &str) {
+    if let Some(path) = specifier.strip_prefix("json:") {
+        (ModuleType::Json, path)
+    } else if let Some(path) = specifier.strip_prefix("text:") {
+        (ModuleType::Text, path)
+    } else {
+        (ModuleType::JavaScript, specifier)
+    }
+}
+
+/// load a module with the module code loader of the runtime, json and text modules are wrapped in a module
+/// which has the content as its default export
+/// json is parsed as a JSON Object and text becomes a String
+///
+/// this calls the loader and blocks until it is done, so it should never be called in the worker thread, see
+/// fetch_module_graph()
+fn load_module_code(
+    es_rt_inner: &EsRuntimeInner,
+    specifier: &str,
+    ref_path: &str,
+) -> Result<Script, LoaderError> {
+    let (module_type, path) = get_module_type(specifier);
+    let loader = es_rt_inner
+        .module_source_loader
+        .as_ref()
+        .ok_or_else(|| LoaderError::NotFound(format!("{} (no module code loader)", path)))?;
+    let script = futures::executor::block_on(loader.load_module(path, ref_path))?;
+    if module_type == ModuleType::JavaScript {
+        return Ok(script);
+    }
+
+    trace!("wrapping {:?} module {}", module_type, script.get_path());
+    // a json string is also a valid es string literal
+    let literal = serde_json::to_string(script.get_code())
+        .map_err(|e| LoaderError::Io(format!("{}: {}", script.get_path(), e)))?;
+    let code = if module_type == ModuleType::Json {
+        format!("export default JSON.parse({});", literal)
+    } else {
+        format!("export default {};", literal)
+    };
+    Ok(Script::new(script.get_path(), code.as_str()))
+}
+
+/// remap a specifier with the ImportMap of the EsRuntime, if any
+fn apply_import_map(es_rt_inner: &EsRuntimeInner, specifier: String, ref_path: &str) -> String {
+    if let Some(import_map) = &es_rt_inner.import_map {
+        if let Some(mapped) = import_map.resolve(specifier.as_str(), ref_path) {
+            trace!("import map: {} -> {}", specifier, mapped);
+            return mapped;
+        }
+    }
+    specifier
+}
+
 /// native function used for dynamic imports
 unsafe extern "C" fn module_dynamic_import(
     cx: *mut JSContext,
@@ -197,8 +944,13 @@ unsafe extern "C" fn module_dynamic_import(
         specifier_val_root.handle(),
     );
 
-    let file_name = jsapi_utils::es_jsstring_to_string(cx, *specifier);
+    let rt_arc = SmRuntime::clone_current_esrt_inner_arc();
     let ref_path = get_path_from_module_private(cx, reference_private);
+    let file_name = apply_import_map(
+        &rt_arc,
+        jsapi_utils::es_jsstring_to_string(cx, *specifier),
+        ref_path.as_str(),
+    );
 
     trace!(
         "module_dynamic_import called: {} from ref: {}",
@@ -207,22 +959,29 @@ unsafe extern "C" fn module_dynamic_import(
     );
 
     let closure_id = register_cached_object(cx, *closure_root);
-    let rt_arc = SmRuntime::clone_current_esrt_inner_arc();
-
-    // todo if the module is already cache we could just run an async job via
-    // rt_arc.do_in_spidermonkey_runtime_thread
-    // instead of stepping into a different thread
+    // native modules only live in this thread so get them before we step into a helper thread
+    let native_script = crate::esnativemodule::get_native_module_script(file_name.as_str());
+    let key = get_module_cache_key(&rt_arc, file_name.as_str(), ref_path.as_str());
 
-    let load_task = move || {
+    let load_task = async move {
         trace!(
             "module_dynamic_import: {}, load_task running",
             file_name.as_str()
         );
-        // load mod code here (in helper thread)
-        let script: Option<Script> = if let Some(loader) = &rt_arc.module_source_loader {
-            loader(file_name.as_str(), ref_path.as_str())
+        // load the module and its static imports (in helper threads), they are compiled and cached in the
+        // worker thread so only the evaluation is left when we get back there
+        let fetch_res = if native_script.is_some() {
+            Ok(())
         } else {
-            None
+            fetch_module_graph(
+                rt_arc.clone(),
+                vec![ModuleRequest {
+                    file_name: file_name.clone(),
+                    ref_path: ref_path.clone(),
+                    key: key.clone(),
+                }],
+            )
+            .await
         };
 
         trace!(
@@ -231,101 +990,107 @@ unsafe extern "C" fn module_dynamic_import(
         );
 
         rt_arc.do_in_es_event_queue(move |sm_rt| {
-            // compile module / get from cache here
+            // evaluate module here
             // resolve or reject promise here (in event queue)
             trace!(
                 "module_dynamic_import: {}, load_task: back in do_in_spidermonkey_runtime_thread",
                 file_name.as_str()
             );
             sm_rt.do_with_jsapi(|_rt, cx, _global| {
-                // check if was cached async
-                // todo replace with a bool
-
-                trace!(
-                    "module_dynamic_import: {}, load_task: back in do_in_spidermonkey_runtime_thread, check cache",
-                    file_name.as_str()
-                );
-
-                let is_cached = MODULE_CACHE.with(|cache_rc| {
-                    let cache = &*cache_rc.borrow();
-                    cache.contains(&file_name)
-                });
+                let is_cached = get_cached_module(key.as_str()).is_some();
+                record_cache_lookup(key.as_str(), is_cached);
 
-                let closure_epr = crate::spidermonkeyruntimewrapper::remove_cached_object(closure_id);
+                let closure_epr =
+                    crate::spidermonkeyruntimewrapper::remove_cached_object(closure_id);
                 rooted!(in (cx) let closure_root = closure_epr.get());
                 rooted!(in (cx) let mut promise_val_root = NullValue());
                 rooted!(in (cx) let mut specifier_val_root = NullValue());
                 rooted!(in (cx) let mut reference_private_val_root = NullValue());
-                jsapi_utils::objects::get_es_obj_prop_val(cx, closure_root.handle(), "promise", promise_val_root.handle_mut()).ok().expect("could not get promise prop from closure");
-                jsapi_utils::objects::get_es_obj_prop_val(cx, closure_root.handle(), "specifier", specifier_val_root.handle_mut()).ok().expect("could not get specifier prop from closure");
-                jsapi_utils::objects::get_es_obj_prop_val(cx, closure_root.handle(), "reference_private", reference_private_val_root.handle_mut()).ok().expect("could not get reference_private prop from closure");
-                rooted!(in (cx) let mut promise_root = promise_val_root.to_object());
-                rooted!(in (cx) let mut specifier_root = specifier_val_root.to_string());
-
-
-                if is_cached {
-                    // resolve promise
-                    trace!("dyn module {} was cached, finish import", file_name.as_str());
-                    FinishDynamicModuleImport_NoTLA(cx, DynamicImportStatus::Ok , reference_private_val_root.handle().into(), specifier_root.handle().into(), promise_root.handle().into());
-
-                } else if let Some(script_code) = script {
-
-                    trace!("dyn module {} was loaded, compile", file_name.as_str());
-
-                    let compiled_mod_obj_res = compile_module(
+                jsapi_utils::objects::get_es_obj_prop_val(
+                    cx,
+                    closure_root.handle(),
+                    "promise",
+                    promise_val_root.handle_mut(),
+                )
+                .ok()
+                .expect("could not get promise prop from closure");
+                jsapi_utils::objects::get_es_obj_prop_val(
+                    cx,
+                    closure_root.handle(),
+                    "specifier",
+                    specifier_val_root.handle_mut(),
+                )
+                .ok()
+                .expect("could not get specifier prop from closure");
+                jsapi_utils::objects::get_es_obj_prop_val(
+                    cx,
+                    closure_root.handle(),
+                    "reference_private",
+                    reference_private_val_root.handle_mut(),
+                )
+                .ok()
+                .expect("could not get reference_private prop from closure");
+                rooted!(in (cx) let promise_root = promise_val_root.to_object());
+                rooted!(in (cx) let specifier_root = specifier_val_root.to_string());
+
+                rooted!(in (cx) let mut evaluation_promise_root = UndefinedValue());
+                let evaluate_res = if let Err(err) = fetch_res {
+                    Err(err.err_msg())
+                } else if is_cached {
+                    trace!("dyn module {} is cached, evaluate", file_name.as_str());
+                    evaluate_cached_module(cx, key.as_str(), evaluation_promise_root.handle_mut())
+                        .map_err(|err| format!("module failed: {}", err.err_msg()))
+                } else if let Some(script_code) = native_script {
+                    trace!(
+                        "dyn module {} is a native module, compile",
+                        file_name.as_str()
+                    );
+                    compile_module_with_promise(
                         cx,
                         script_code.get_code(),
                         script_code.get_path(),
-                    );
-
-                    if let Ok(compiled_mod_obj) = compiled_mod_obj_res {
-                        MODULE_CACHE.with(|cache_rc| {
-                            let cache = &mut *cache_rc.borrow_mut();
-                            let mod_epr = EsPersistentRooted::new_from_obj(cx, compiled_mod_obj);
-                            cache.put(file_name.clone(), mod_epr);
-                        });
-
-                        trace!("dyn module {} was loaded, compiled and cached, finish", file_name.as_str());
-
-                        FinishDynamicModuleImport_NoTLA(cx, DynamicImportStatus::Ok, reference_private_val_root.handle().into(), specifier_root.handle().into(), promise_root.handle().into());
+                        evaluation_promise_root.handle_mut(),
+                    )
+                    .map(|compiled_mod_obj| {
+                        cache_module(cx, key.as_str(), script_code.get_path(), compiled_mod_obj);
+                    })
+                    .map_err(|err| format!("module failed to compile: {}", err.err_msg()))
+                } else {
+                    Err(format!("module not found: {}", file_name))
+                };
 
-                    } else {
+                match evaluate_res {
+                    Ok(()) => {
+                        trace!("dyn module {} was evaluated, finish", file_name.as_str());
+                        finish_dynamic_import(
+                            cx,
+                            evaluation_promise_root.handle(),
+                            reference_private_val_root.handle(),
+                            specifier_root.handle(),
+                            promise_root.handle(),
+                        );
+                    }
+                    Err(err_str) => {
                         // reject promise
-
-                        trace!("dyn module {} was not compiled ok, rejecting promise", file_name.as_str());
-
-                        let err_str= format!("module failed to compile: {}", compiled_mod_obj_res.err().unwrap().err_msg());
-                        rooted!(in (cx) let mut prom_reject_val = UndefinedValue());
-                        jsapi_utils::new_es_value_from_str(cx, err_str.as_str(), prom_reject_val.handle_mut());
-
                         trace!("rejecting dynamic module promise: failed {}", err_str);
+                        rooted!(in (cx) let mut prom_reject_val = UndefinedValue());
+                        jsapi_utils::new_es_value_from_str(
+                            cx,
+                            err_str.as_str(),
+                            prom_reject_val.handle_mut(),
+                        );
                         jsapi_utils::promises::reject_promise(
                             cx,
                             promise_root.handle(),
                             prom_reject_val.handle(),
                         )
-                            .ok()
-                            .expect("promise rejection failed / 1");
+                        .expect("promise rejection failed");
                     }
-                } else {
-                    // reject promise
-                    let err_str= format!("module not found: {}", file_name);
-                    trace!("rejecting dynamic module promise: failed {}", err_str);
-                    rooted!(in (cx) let mut prom_reject_val = UndefinedValue());
-                    jsapi_utils::new_es_value_from_str(cx, err_str.as_str(), prom_reject_val.handle_mut());
-
-                    jsapi_utils::promises::reject_promise(
-                        cx,
-                        promise_root.handle(),
-                        prom_reject_val.handle(),
-                    )
-                        .ok()
-                        .expect("promise rejection failed / 2");
                 }
             });
         });
     };
-    EsRuntime::add_helper_task(load_task);
+    add_fetch_task(Box::pin(load_task));
 
     true
 }
@@ -335,14 +1100,12 @@ unsafe extern "C" fn set_module_metadata(
     private_value: RawHandleValue,
     meta_object: RawHandleObject,
 ) -> bool {
-    // the goal here is to set the "url" prop on meta_object which is the full_path prop of private_value
-    // i think :)
-
-    // lets just see what we get here first
+    // the path of the module as returned by the module code loader or as passed to compile_module
     let path = get_path_from_module_private(cx, private_value);
+    let url = get_module_url(path.as_str());
 
     rooted!(in (cx) let mut path_root = UndefinedValue());
-    jsapi_utils::new_es_value_from_str(cx, path.as_str(), path_root.handle_mut());
+    jsapi_utils::new_es_value_from_str(cx, url.as_str(), path_root.handle_mut());
 
     jsapi_utils::objects::set_es_obj_prop_value_raw(
         cx,
@@ -351,87 +1114,306 @@ unsafe extern "C" fn set_module_metadata(
         path_root.handle().into(),
     );
 
+    // import.meta.resolve(specifier) resolves a specifier relative to this module
+    rooted!(in (cx) let mut resolve_root = NULL_JSOBJECT);
+    let ref_path = path.clone();
+    jsapi_utils::functions::new_callback(
+        cx,
+        resolve_root.handle_mut(),
+        move |cx, args: Vec<HandleValue>, rval: MutableHandleValue| {
+            if args.is_empty() || !args[0].is_string() {
+                return Err("import.meta.resolve expects a specifier".to_string());
+            }
+            let specifier = jsapi_utils::es_jsstring_to_string(cx, args[0].to_string());
+            let resolved =
+                resolve_specifier(specifier.as_str(), ref_path.as_str()).map_err(|err| {
+                    format!("could not resolve {} from {}: {}", specifier, ref_path, err)
+                })?;
+            jsapi_utils::new_es_value_from_str(cx, resolved.as_str(), rval);
+            Ok(())
+        },
+    );
+    rooted!(in (cx) let resolve_val_root = ObjectValue(*resolve_root));
+    jsapi_utils::objects::set_es_obj_prop_value_raw(
+        cx,
+        meta_object,
+        "resolve",
+        resolve_val_root.handle().into(),
+    );
+
+    let hook_opt = IMPORT_META_HOOK.with(|hook_rc| hook_rc.borrow().clone());
+    if let Some(hook) = hook_opt {
+        for (name, value) in hook(url.as_str()) {
+            rooted!(in (cx) let mut value_root = UndefinedValue());
+            value.to_es_value(cx, value_root.handle_mut());
+            jsapi_utils::objects::set_es_obj_prop_value_raw(
+                cx,
+                meta_object,
+                name.as_str(),
+                value_root.handle().into(),
+            );
+        }
+    }
+
+    crate::esnativemodule::init_native_module_meta(
+        cx,
+        path.as_str(),
+        HandleObject::from_raw(meta_object),
+    );
+
     true
 }
 
+/// get the absolute path of a module (import.meta.url), a path which is not absolute (e.g. the file name of a module
+/// which was loaded with load_module_sync) is resolved against the current working dir
+/// native modules keep their name
+fn get_module_url(path: &str) -> String {
+    if Path::new(path).is_absolute()
+        || crate::esnativemodule::get_native_module_script(path).is_some()
+    {
+        return path.to_string();
+    }
+    match std::env::current_dir() {
+        Ok(cwd) => normalize(cwd.join(path).as_path())
+            .to_string_lossy()
+            .to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// resolve a specifier to the absolute path of the module it would import, without loading the module
+/// this applies the import map and asks the module code loader to resolve the path
+fn resolve_specifier(specifier: &str, ref_path: &str) -> Result<String, LoaderError> {
+    let es_rt_inner = SmRuntime::clone_current_esrt_inner_arc();
+    let file_name = apply_import_map(&es_rt_inner, specifier.to_string(), ref_path);
+    if crate::esnativemodule::get_native_module_script(file_name.as_str()).is_some() {
+        return Ok(file_name);
+    }
+    let (_module_type, path) = get_module_type(file_name.as_str());
+    resolve_module_path(&es_rt_inner, path, ref_path)
+}
+
+/// ask the module code loader to resolve the path of a module (without type prefix) to an absolute path
+fn resolve_module_path(
+    es_rt_inner: &EsRuntimeInner,
+    path: &str,
+    ref_path: &str,
+) -> Result<String, LoaderError> {
+    let resolved = match es_rt_inner.module_source_loader.as_ref() {
+        Some(loader) => loader.resolve_module(path, ref_path)?,
+        None => resolve_relative_path(path, ref_path),
+    };
+    Ok(get_module_url(resolved.as_str()))
+}
+
+/// get the key of a module in the module cache for a specifier (after applying the import map)
+/// this is the absolute path the module code loader resolves the specifier to with the json: or text: prefix of the
+/// specifier, so the same relative specifier imported from different directories gives different modules
+/// native modules are cached by their name
+pub(crate) fn get_module_cache_key(
+    es_rt_inner: &EsRuntimeInner,
+    file_name: &str,
+    ref_path: &str,
+) -> String {
+    if crate::esnativemodule::get_native_module_script(file_name).is_some() {
+        return file_name.to_string();
+    }
+    let (module_type, path) = get_module_type(file_name);
+    // a module which can not be resolved will fail to load, its key only has to be unique
+    let resolved = resolve_module_path(es_rt_inner, path, ref_path)
+        .unwrap_or_else(|_| get_module_url(resolve_relative_path(path, ref_path).as_str()));
+    format!("{}{}", module_type.prefix(), resolved)
+}
+
+/// set the hook which is used to add custom fields to import.meta, see EsRuntimeBuilder::import_meta_hook()
+pub(crate) fn set_import_meta_hook(hook: Arc<ImportMetaHook>) {
+    IMPORT_META_HOOK.with(|hook_rc| {
+        hook_rc.replace(Some(hook));
+    });
+}
+
+/// finish a dynamic import when the evaluation Promise of the imported module is settled
+/// if there is no evaluation Promise (the module was cached or top level await is not enabled) the import is
+/// finished right away
+fn finish_dynamic_import(
+    cx: *mut JSContext,
+    evaluation_promise: HandleValue,
+    reference_private: HandleValue,
+    specifier: mozjs::rust::Handle<*mut JSString>,
+    promise: HandleObject,
+) {
+    rooted!(in (cx) let mut evaluation_promise_root = NULL_JSOBJECT);
+    if evaluation_promise.is_object() {
+        evaluation_promise_root.set(evaluation_promise.to_object());
+    } else {
+        evaluation_promise_root.set(jsapi_utils::promises::new_promise(cx));
+        rooted!(in (cx) let undefined_root = UndefinedValue());
+        jsapi_utils::promises::resolve_promise(
+            cx,
+            evaluation_promise_root.handle(),
+            undefined_root.handle(),
+        )
+        .expect("could not resolve evaluation promise");
+    }
+    unsafe {
+        FinishDynamicModuleImport(
+            cx,
+            evaluation_promise_root.handle().into(),
+            reference_private.into(),
+            specifier.into(),
+            promise.into(),
+        )
+    };
+}
+
 /// native function used a import function for module loading
 unsafe extern "C" fn import_module(
     cx: *mut JSContext,
     reference_private: RawHandleValue,
     specifier: RawHandle<*mut JSString>,
 ) -> *mut JSObject {
-    let file_name = jsapi_utils::es_jsstring_to_string(cx, *specifier);
     let ref_path = get_path_from_module_private(cx, reference_private);
+    let es_rt_inner = SmRuntime::clone_current_esrt_inner_arc();
+    let file_name = apply_import_map(
+        &es_rt_inner,
+        jsapi_utils::es_jsstring_to_string(cx, *specifier),
+        ref_path.as_str(),
+    );
+    let key = get_module_cache_key(&es_rt_inner, file_name.as_str(), ref_path.as_str());
 
-    trace!("import_module {} from ref {}", file_name, ref_path);
+    trace!(
+        "import_module {} ({}) from ref {}",
+        file_name,
+        key,
+        ref_path
+    );
 
     // see if we have that module
     let cached: Option<*mut JSObject> = MODULE_CACHE.with(|cache_rc| {
         let cache = &mut *cache_rc.borrow_mut();
-        if let Some(mpr) = cache.get(&file_name) {
-            trace!("found a cached module for {}", &file_name);
+        if let Some(mpr) = cache.get(&key) {
+            trace!("found a cached module for {}", &key);
             // set rval here
             return Some(mpr.get());
         }
         None
     });
+    record_cache_lookup(key.as_str(), cached.is_some());
     if let Some(c) = cached {
         return c;
     };
 
-    // see if we got a module code loader
-    let module_code_opt: Option<Script> = SM_RT.with(|sm_rt_rc| {
-        let sm_rt = sm_rt_rc.borrow();
-        let es_rt_inner = sm_rt.clone_esrt_inner();
-        if let Some(module_source_loader) = &es_rt_inner.module_source_loader {
-            module_source_loader(file_name.as_str(), ref_path.as_str())
-        } else {
-            None
-        }
-    });
+    // modules from the module code loader are fetched before the module which imports them is evaluated (see
+    // fetch_module_graph()), the loader is never called here because that would block the worker thread
+    let module_code_res: Result<Script, String> =
+        match crate::esnativemodule::get_native_module_script(file_name.as_str()) {
+            Some(script) => Ok(script),
+            None => Err("the module was not loaded before it was imported".to_string()),
+        };
 
-    if let Some(module_code) = module_code_opt {
-        let compiled_mod_obj_res = jsapi_utils::modules::compile_module(
-            cx,
-            module_code.get_code(),
-            module_code.get_path(),
-        );
+    match module_code_res {
+        Ok(module_code) => {
+            // the module is instantiated and evaluated together with the module which imports it, so modules which
+            // use top level await are awaited by their importers
+            let compiled_mod_obj_res =
+                compile_module_only(cx, module_code.get_code(), module_code.get_path());
+
+            if compiled_mod_obj_res.is_err() {
+                let err = compiled_mod_obj_res.err().unwrap();
+                let err_str = format!("error loading module: {}", err.err_msg());
+                log::debug!("error loading module, returning null: {}", err_str);
+                report_exception2(cx, err_str);
+                return *ptr::null_mut::<*mut JSObject>();
+            }
+
+            let compiled_module: *mut JSObject = compiled_mod_obj_res.ok().unwrap();
 
-        if compiled_mod_obj_res.is_err() {
-            let err = compiled_mod_obj_res.err().unwrap();
-            let err_str = format!("error loading module: {}", err.err_msg());
+            cache_module(cx, key.as_str(), module_code.get_path(), compiled_module);
+
+            compiled_module
+        }
+        Err(err) => {
+            let err_str = format!(
+                "could not load module {} (imported by {}): {}",
+                file_name, ref_path, err
+            );
             log::debug!("error loading module, returning null: {}", err_str);
             report_exception2(cx, err_str);
-            return *ptr::null_mut::<*mut JSObject>();
+            *ptr::null_mut::<*mut JSObject>()
         }
-
-        let compiled_module: *mut JSObject = compiled_mod_obj_res.ok().unwrap();
-
-        MODULE_CACHE.with(|cache_rc| {
-            trace!("caching module for {}", &file_name);
-            let cache = &mut *cache_rc.borrow_mut();
-            let mut mpr = EsPersistentRooted::default();
-            mpr.init(cx, compiled_module);
-            cache.put(file_name, mpr);
-        });
-
-        compiled_module
-    } else {
-        NullValue().to_object()
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use crate::jsapi_utils::modules::compile_module;
+    use crate::esmoduleloader::FileSystemModuleLoader;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
+    use crate::esvaluefacade::EsValueFacade;
+    use crate::jsapi_utils::modules::{compile_module, get_module_type, ModuleType};
     use crate::jsapi_utils::tests::test_with_sm_rt;
+    use hirofa_utils::js_utils::Script;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
     use std::time::Duration;
 
+    #[test]
+    fn test_module_type() {
+        assert_eq!(
+            get_module_type("./config.json"),
+            (ModuleType::JavaScript, "./config.json")
+        );
+        assert_eq!(
+            get_module_type("json:./config.js"),
+            (ModuleType::Json, "./config.js")
+        );
+        assert_eq!(
+            get_module_type("text:./tpl.html"),
+            (ModuleType::Text, "./tpl.html")
+        );
+        assert_eq!(
+            get_module_type("binary:./data.bin"),
+            (ModuleType::JavaScript, "binary:./data.bin")
+        );
+        assert_eq!(
+            get_module_type("./util.mjs"),
+            (ModuleType::JavaScript, "./util.mjs")
+        );
+    }
+
+    #[test]
+    fn test_json_and_text_modules() {
+        log::info!("test: test_json_and_text_modules");
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(Box::new(|path: &str, _ref_path: &str| {
+                let code = match path {
+                    "./config.json" => "{\"name\": \"test\", \"list\": [1, 2]}",
+                    "./tpl.html" => "<p class=\"a\">'quoted'\n</p>",
+                    _ => return None,
+                };
+                Some(Script::new(&path[1..], code))
+            }))
+            .build();
+        rt.load_module_sync(
+            "import config from 'json:./config.json';\nimport tpl from 'text:./tpl.html';\n\n\
+             globalThis.module_types_res = config.name + config.list.length + tpl;",
+            "/test_json_and_text_modules.mes",
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("module_types_res;", "test_json_and_text_modules.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_string(), "test2<p class=\"a\">'quoted'\n</p>");
+    }
+
     #[test]
     fn test_module() {
         log::info!("test: test_module");
         let res = test_with_sm_rt(|sm_rt| {
+            // compile_module does not load the modules it imports, they have to be cached first
+            sm_rt
+                .load_module("export const other = Math.sqrt(8);", "test_mod.es")
+                .expect("module failed");
             sm_rt.do_with_jsapi(|_rt, cx, _global| {
 
             let mod_script =
@@ -468,6 +1450,10 @@ mod tests {
     fn test_module_gc() {
         log::info!("test: test_module_gc");
         let res = test_with_sm_rt(|sm_rt| {
+            // compile_module does not load the modules it imports, they have to be cached first
+            sm_rt
+                .load_module("export const other = Math.sqrt(8);", "test_mod.es")
+                .expect("module failed");
             sm_rt.do_with_jsapi(|_rt, cx, _global| {
 
                 let mod_script2 =
@@ -668,4 +1654,414 @@ mod tests {
         log::debug!("test_dynamic_import5: import should be done now");
         // 'foo_test_mod.mes'
     }
+
+    #[test]
+    fn test_concurrent_dynamic_imports() {
+        log::info!("test: test_concurrent_dynamic_imports");
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(Box::new(|path: &str, _ref_path: &str| {
+                let num = path.strip_prefix("./concurrent_")?.strip_suffix(".mes")?;
+                std::thread::sleep(Duration::from_millis(20));
+                Some(Script::new(
+                    format!("/concurrent_{}.mes", num).as_str(),
+                    format!("export const val = {};", num).as_str(),
+                ))
+            }))
+            .build();
+        // more imports than there are helper threads
+        let count = 4 * std::cmp::max(2, num_cpus::get());
+        let prom_esvf = rt
+            .eval_sync(
+                format!(
+                    "Promise.all(Array.from({{length: {}}}, (_, i) => import('./concurrent_' + i + '.mes').then((m) => m.val)))\
+                     .then((vals) => vals.reduce((a, b) => a + b, 0));",
+                    count
+                )
+                .as_str(),
+                "/test_concurrent_dynamic_imports.es",
+            )
+            .expect("script failed");
+        let prom_res = prom_esvf
+            .get_promise_result_blocking(Duration::from_secs(30))
+            .expect("imports timed out");
+        match prom_res {
+            Ok(sum) => assert_eq!(sum.get_i32() as usize, count * (count - 1) / 2),
+            Err(err) => panic!("imports failed: {}", err.get_string()),
+        }
+    }
+
+    #[test]
+    fn test_module_cache() {
+        log::info!("test: test_module_cache");
+        let dir = std::env::temp_dir().join("es_module_cache_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+        let lib = dir.join("lib.mjs");
+        std::fs::write(
+            &lib,
+            "globalThis.lib_version = 1;\nexport const version = 1;",
+        )
+        .unwrap();
+
+        let loader = crate::esmoduleloader::FileSystemModuleLoader::new(&dir);
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(loader.into_module_code_loader())
+            .build();
+        rt.load_module_sync(
+            "import {version} from './lib.mjs';",
+            dir.join("a.mjs").to_str().unwrap(),
+        )
+        .expect("module a failed");
+        rt.load_module_sync(
+            "import {version} from './lib.mjs';",
+            dir.join("b.mjs").to_str().unwrap(),
+        )
+        .expect("module b failed");
+
+        let stats = rt.module_cache_stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 3);
+        assert_eq!(stats.capacity, 50);
+
+        let rt_inner = rt.do_in_es_event_queue_sync(|sm_rt| sm_rt.clone_esrt_inner());
+        let mut watcher = super::ModuleWatcher::default();
+        assert!(watcher.reload_changed_modules(&rt_inner).is_empty());
+
+        // change the file and make sure its modified time changes
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(
+            &lib,
+            "globalThis.lib_version = 2;\nexport const version = 2;",
+        )
+        .unwrap();
+        let reloaded = watcher.reload_changed_modules(&rt_inner);
+        assert_eq!(reloaded, vec![lib.to_str().unwrap().to_string()]);
+        let esvf = rt
+            .eval_sync("lib_version;", "test_module_cache.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 2);
+
+        assert!(rt.invalidate_module(lib.to_str().unwrap()));
+        assert!(!rt.invalidate_module(lib.to_str().unwrap()));
+        assert_eq!(rt.module_cache_stats().size, 2);
+
+        rt.clear_module_cache();
+        assert_eq!(rt.module_cache_stats().size, 0);
+    }
+
+    #[test]
+    fn test_module_watcher_relative_name() {
+        log::info!("test: test_module_watcher_relative_name");
+        let dir = std::env::temp_dir().join("es_module_watcher_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+        std::fs::write(dir.join("dep.mjs"), "export const dep = 3;").unwrap();
+        let main = dir.join("main.mjs");
+        std::fs::write(&main, "globalThis.module_watcher_res = 1;").unwrap();
+
+        let rt = EsRuntimeBuilder::new()
+            .async_module_code_loader(Box::new(FileSystemModuleLoader::new(&dir)))
+            .build();
+        // a module file name which is not a path is resolved by the loader
+        rt.load_module_sync(std::fs::read_to_string(&main).unwrap().as_str(), "main.mjs")
+            .expect("module failed");
+
+        let rt_inner = rt.do_in_es_event_queue_sync(|sm_rt| sm_rt.clone_esrt_inner());
+        let mut watcher = super::ModuleWatcher::default();
+        assert!(watcher.reload_changed_modules(&rt_inner).is_empty());
+
+        // the new version imports a module which was not loaded yet
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(
+            &main,
+            "import {dep} from './dep.mjs';\nglobalThis.module_watcher_res = dep;",
+        )
+        .unwrap();
+        let reloaded = watcher.reload_changed_modules(&rt_inner);
+        assert_eq!(reloaded, vec![main.to_str().unwrap().to_string()]);
+        let esvf = rt
+            .eval_sync(
+                "module_watcher_res;",
+                "test_module_watcher_relative_name.es",
+            )
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 3);
+    }
+
+    #[test]
+    fn test_module_cache_key() {
+        log::info!("test: test_module_cache_key");
+        let dir = std::env::temp_dir().join("es_module_cache_key_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        for sub in &["a", "b"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+            std::fs::write(
+                dir.join(sub).join("util.mjs"),
+                format!("export const name = '{}';", sub),
+            )
+            .unwrap();
+            std::fs::write(
+                dir.join(sub).join("main.mjs"),
+                "import {name} from './util.mjs';\nexport const res = name;",
+            )
+            .unwrap();
+        }
+        let dir = std::fs::canonicalize(dir).unwrap();
+
+        let rt = EsRuntimeBuilder::new()
+            .async_module_code_loader(Box::new(FileSystemModuleLoader::new(&dir)))
+            .build();
+        rt.load_module_sync(
+            "import {res as a} from './a/main.mjs';\nimport {res as b} from './b/main.mjs';\n\
+             globalThis.module_cache_key_res = a + b;",
+            dir.join("main.mjs").to_str().unwrap(),
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("module_cache_key_res;", "test_module_cache_key.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_string(), "ab");
+        // the root module, both main modules and both util modules
+        assert_eq!(rt.module_cache_stats().size, 5);
+
+        // a module which was loaded by a relative file name is cached by its resolved path
+        let counted_src =
+            "globalThis.module_cache_key_count = (globalThis.module_cache_key_count || 0) + 1;";
+        std::fs::write(dir.join("counted.mjs"), counted_src).unwrap();
+        rt.load_module_sync(counted_src, "counted.mjs")
+            .expect("module failed");
+        rt.load_module_sync(
+            "import './counted.mjs';",
+            dir.join("importer.mjs").to_str().unwrap(),
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("module_cache_key_count;", "test_module_cache_key.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 1);
+    }
+
+    #[test]
+    fn test_top_level_await() {
+        log::info!("test: test_top_level_await");
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(Box::new(|path: &str, _ref_path: &str| {
+                if path.eq("./slow_dep.mes") {
+                    Some(Script::new(
+                        "/slow_dep.mes",
+                        "await new Promise((resolve) => {setTimeout(resolve, 20);});\nexport const dep_done = true;",
+                    ))
+                } else {
+                    None
+                }
+            }))
+            .build();
+
+        let module = rt
+            .load_module_sync(
+                "import {dep_done} from './slow_dep.mes';\n\
+                 export const res = await new Promise((resolve) => {setTimeout(() => {resolve(dep_done ? 42 : 0);}, 20);});",
+                "/test_top_level_await.mes",
+            )
+            .expect("module failed");
+        assert_eq!(
+            module
+                .get_export("res")
+                .expect("could not get export")
+                .get_i32(),
+            42
+        );
+
+        let module = futures::executor::block_on(rt.load_module_async(
+            "export const res = await Promise.resolve(43);",
+            "/test_top_level_await_async.mes",
+        ))
+        .expect("module failed");
+        assert_eq!(
+            module
+                .get_export("res")
+                .expect("could not get export")
+                .get_i32(),
+            43
+        );
+
+        let err = rt
+            .load_module_sync(
+                "await new Promise((resolve, reject) => {setTimeout(() => {reject(new Error('tla failed'));}, 20);});",
+                "/test_top_level_await_rejected.mes",
+            )
+            .err()
+            .expect("module should fail");
+        assert!(err.err_msg().contains("tla failed"));
+
+        let err = rt
+            .load_module_sync(
+                "throw Error('sync failure');",
+                "/test_top_level_await_throws.mes",
+            )
+            .err()
+            .expect("module should fail");
+        assert!(err.err_msg().contains("sync failure"));
+    }
+
+    #[test]
+    fn test_import_meta() {
+        log::info!("test: test_import_meta");
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(Box::new(|_path: &str, _ref_path: &str| {
+                panic!("import.meta.resolve should not load modules")
+            }))
+            .import_meta_hook(Box::new(|path: &str| {
+                let mut env = std::collections::HashMap::new();
+                env.insert(
+                    "MODE".to_string(),
+                    EsValueFacade::new_str(format!("test:{}", path)),
+                );
+                let mut fields = std::collections::HashMap::new();
+                fields.insert("env".to_string(), EsValueFacade::new_obj(env));
+                fields
+            }))
+            .build();
+        rt.load_module_sync(
+            "let res = [import.meta.url, import.meta.resolve('./data/sibling.txt'), import.meta.resolve('../lib/util.mes'), import.meta.env.MODE];\n\
+             globalThis.import_meta_res = res.join('|');",
+            "/app/main.mes",
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("import_meta_res;", "test_import_meta.es")
+            .expect("script failed");
+        assert_eq!(
+            esvf.get_string(),
+            "/app/main.mes|/app/data/sibling.txt|/lib/util.mes|test:/app/main.mes"
+        );
+
+        // the file name of a module which is not a path is resolved against the working dir
+        rt.load_module_sync(
+            "globalThis.import_meta_url = import.meta.url;",
+            "test_import_meta_url.mes",
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync("import_meta_url;", "test_import_meta.es")
+            .expect("script failed");
+        let cwd = std::env::current_dir().expect("no working dir");
+        assert_eq!(
+            esvf.get_string(),
+            cwd.join("test_import_meta_url.mes").to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_import_meta_resolve_with_loader() {
+        log::info!("test: test_import_meta_resolve_with_loader");
+        let dir = std::env::temp_dir().join("es_modules_test_import_meta_resolve");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        std::fs::write(dir.join("data/sibling.txt"), "sibling").unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+
+        let rt = EsRuntimeBuilder::new()
+            .async_module_code_loader(Box::new(FileSystemModuleLoader::new(&dir)))
+            .build();
+        rt.load_module_sync(
+            "let res = [import.meta.resolve('./data/sibling.txt')];\n\
+             try {import.meta.resolve('./nope.txt'); res.push('resolved');} catch(e) {res.push('not found');}\n\
+             globalThis.import_meta_resolve_res = res.join('|');",
+            dir.join("main.mes").to_str().unwrap(),
+        )
+        .expect("module failed");
+        let esvf = rt
+            .eval_sync(
+                "import_meta_resolve_res;",
+                "test_import_meta_resolve_with_loader.es",
+            )
+            .expect("script failed");
+        assert_eq!(
+            esvf.get_string(),
+            format!("{}|not found", dir.join("data/sibling.txt").display())
+        );
+    }
+
+    #[test]
+    fn test_fetch_module_graph() {
+        log::info!("test: test_fetch_module_graph");
+        let loaded_in_worker = Arc::new(AtomicBool::new(false));
+        let loaded_in_worker2 = loaded_in_worker.clone();
+        let rt = EsRuntimeBuilder::new()
+            .module_code_loader(Box::new(move |path: &str, _ref_path: &str| {
+                if hirofa_utils::eventloop::EventLoop::is_a_pool_thread() {
+                    loaded_in_worker2.store(true, Ordering::SeqCst);
+                }
+                // a slow loader
+                std::thread::sleep(Duration::from_millis(50));
+                let code = match path {
+                    "./a.mes" => "import {c} from './c.mes';\nexport const a = c + 1;",
+                    "./b.mes" => "import {c} from './c.mes';\nexport const b = c + 2;",
+                    "./c.mes" => "export const c = 1;",
+                    "./d.mes" => "export const d = 4;",
+                    _ => return None,
+                };
+                Some(Script::new(&path[1..], code))
+            }))
+            .build();
+
+        let module = rt
+            .load_module_sync(
+                "import {a} from './a.mes';\nimport {b} from './b.mes';\nexport const res = a + b;",
+                "/test_fetch_module_graph.mes",
+            )
+            .expect("module failed");
+        assert_eq!(
+            module
+                .get_export("res")
+                .expect("could not get export")
+                .get_i32(),
+            5
+        );
+        assert!(!loaded_in_worker.load(Ordering::SeqCst));
+
+        // a module which is loaded in the worker thread is evaluated after its imports were fetched
+        let evaluation = rt
+            .do_in_es_event_queue_sync(|sm_rt| {
+                sm_rt.load_module_with_evaluation(
+                    "import {d} from './d.mes';\nglobalThis.fetch_module_graph_d = d;",
+                    "/test_fetch_module_graph_sync.mes",
+                )
+            })
+            .expect("module failed");
+        futures::executor::block_on(super::wait_for_module_evaluation(evaluation))
+            .expect("module failed");
+        let esvf = rt
+            .eval_sync("fetch_module_graph_d;", "test_fetch_module_graph.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 4);
+        assert!(!loaded_in_worker.load(Ordering::SeqCst));
+
+        // the worker thread can't wait for the imports of a module which has to be done when it is loaded
+        let err = rt
+            .do_in_es_event_queue_sync(|sm_rt| {
+                sm_rt.load_module_with_exports(
+                    "import {e} from './e.mes';",
+                    "/test_fetch_module_graph_exports.mes",
+                )
+            })
+            .err()
+            .expect("module should fail");
+        assert!(err.err_msg().contains("not loaded yet"));
+        assert!(!loaded_in_worker.load(Ordering::SeqCst));
+
+        let err = rt
+            .load_module_sync(
+                "import {a} from './a.mes';\nimport {x} from './missing1.mes';\nimport {y} from './missing2.mes';",
+                "/test_fetch_module_graph_missing.mes",
+            )
+            .err()
+            .expect("module should fail");
+        assert!(err.err_msg().contains("./missing1.mes"));
+        assert!(err.err_msg().contains("./missing2.mes"));
+    }
 }
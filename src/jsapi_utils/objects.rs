@@ -198,12 +198,12 @@ pub fn freeze_object(context: *mut JSContext, obj: HandleObject) -> Result<(), E
     } else if let Some(err) = crate::jsapi_utils::get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -215,12 +215,12 @@ pub fn deep_freeze_object(context: *mut JSContext, obj: HandleObject) -> Result<
     } else if let Some(err) = crate::jsapi_utils::get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -1,5 +1,9 @@
+use crate::esruntime::RejectionHandler;
 use crate::jsapi_utils::objects::NULL_JSOBJECT;
-use crate::jsapi_utils::{get_pending_exception, EsErrorInfo};
+use crate::jsapi_utils::rooting::EsPersistentRooted;
+use crate::jsapi_utils::{error_info_from_thrown_value, get_pending_exception, EsErrorInfo};
+use crate::spidermonkeyruntimewrapper::{has_pending_promise_jobs, SM_RT};
+use hirofa_utils::eventloop::EventLoop;
 use mozjs::jsapi::AddPromiseReactions;
 use mozjs::jsapi::GetPromiseResult;
 use mozjs::jsapi::GetPromiseState;
@@ -7,16 +11,20 @@ use mozjs::jsapi::HandleObject as RawHandleObject;
 use mozjs::jsapi::IsPromiseObject;
 use mozjs::jsapi::JSContext;
 use mozjs::jsapi::JSObject;
+use mozjs::jsapi::PromiseRejectionHandlingState;
 use mozjs::jsapi::PromiseState;
 use mozjs::jsapi::SetPromiseRejectionTrackerCallback;
 use mozjs::jsapi::StackFormat;
 use mozjs::jsval::JSVal;
+use mozjs::panic::wrap_panic;
 use mozjs::rust::jsapi_wrapped::NewPromiseObject;
 use mozjs::rust::jsapi_wrapped::RejectPromise;
 use mozjs::rust::jsapi_wrapped::ResolvePromise;
 use mozjs::rust::{HandleObject, HandleValue, MutableHandleValue};
+use std::cell::{Cell, RefCell};
 use std::os::raw::c_void;
 use std::ptr;
+use std::sync::Arc;
 
 /// Returns true if the given object is an unwrapped PromiseObject, false otherwise.
 pub fn object_is_promise(obj: HandleObject) -> bool {
@@ -149,12 +157,12 @@ pub fn resolve_promise(
     } else if let Some(err) = get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error resolving promise".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error resolving promise".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
@@ -170,24 +178,27 @@ pub fn reject_promise(
     } else if let Some(err) = get_pending_exception(context) {
         Err(err)
     } else {
-        Err(EsErrorInfo {
-            message: "unknown error rejecting promise".to_string(),
-            filename: "".to_string(),
-            lineno: 0,
-            column: 0,
-        })
+        Err(EsErrorInfo::new(
+            "unknown error rejecting promise".to_string(),
+            "".to_string(),
+            0,
+            0,
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::esruntime::tests::init_test_runtime;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
     use crate::jsapi_utils;
     use crate::jsapi_utils::get_pending_exception;
     use crate::jsapi_utils::promises::object_is_promise;
     use crate::jsapi_utils::tests::test_with_sm_rt;
     use log::trace;
     use mozjs::jsval::UndefinedValue;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     #[test]
     fn test_x() {
@@ -272,30 +283,193 @@ mod tests {
         .ok()
         .expect("script test_promise_rejection_log.es failed");
     }
+
+    #[test]
+    fn test_rejection_handlers() {
+        log::info!("test: test_rejection_handlers");
+        let reports = Arc::new(Mutex::new(vec![]));
+        let unhandled_reports = reports.clone();
+        let handled_reports = reports.clone();
+        let rt = EsRuntimeBuilder::new()
+            .on_unhandled_rejection(Box::new(move |err| {
+                let is_error = err.do_with_thrown_value(|reason| reason.is_error());
+                unhandled_reports
+                    .lock()
+                    .unwrap()
+                    .push(format!("unhandled {} {:?}", err.message, is_error));
+            }))
+            .on_rejection_handled(Box::new(move |err| {
+                handled_reports
+                    .lock()
+                    .unwrap()
+                    .push(format!("handled {}", err.message));
+            }))
+            .build();
+        let wait_for_reports = |count: usize| {
+            for _ in 0..100 {
+                if reports.lock().unwrap().len() >= count {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        // rejections which are handled in the same job or in a later Promise job are not reported
+        rt.eval_sync(
+            "Promise.reject(new Error('caught')).catch(() => {});\
+             (async function(){let p = Promise.reject(new Error('awaited')); await null; p.catch(() => {});})();",
+            "test_rejection_handlers0.es",
+        )
+        .expect("script test_rejection_handlers0.es failed");
+
+        rt.eval_sync(
+            "this.p = Promise.reject(new TypeError('poof')); true;",
+            "test_rejection_handlers.es",
+        )
+        .expect("script test_rejection_handlers.es failed");
+        wait_for_reports(1);
+        rt.eval_sync(
+            "this.p.catch(() => {}); true;",
+            "test_rejection_handlers2.es",
+        )
+        .expect("script test_rejection_handlers2.es failed");
+        wait_for_reports(2);
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![
+                "unhandled poof Some(true)".to_string(),
+                "handled poof".to_string()
+            ]
+        );
+    }
 }
 
 /// this initializes a default rejectiontracker which logs when a promise was rejected which did not have a rejection handler
+/// if handlers were set with set_rejection_handlers() those are called instead
+/// rejections are reported when there are no more pending promise jobs
 pub fn init_rejection_tracker(cx: *mut JSContext) {
     unsafe {
         SetPromiseRejectionTrackerCallback(cx, Some(promise_rejection_tracker), ptr::null_mut())
     };
 }
 
+/// the handlers which are called by the rejectiontracker of the current worker thread
+#[derive(Default, Clone)]
+pub(crate) struct RejectionHandlers {
+    pub(crate) unhandled: Option<Arc<RejectionHandler>>,
+    pub(crate) handled: Option<Arc<RejectionHandler>>,
+}
+
+thread_local! {
+    static REJECTION_HANDLERS: RefCell<RejectionHandlers> = RefCell::new(RejectionHandlers::default());
+    /// rejections which are reported when the microtask queue is empty
+    static PENDING_REJECTIONS: RefCell<Vec<PendingRejection>> = RefCell::new(vec![]);
+    static REJECTION_REPORT_SCHEDULED: Cell<bool> = Cell::new(false);
+}
+
+struct PendingRejection {
+    promise: EsPersistentRooted,
+    unhandled: bool,
+    /// the stack at the moment of rejection, this is logged when there is no unhandled rejection handler
+    stack: Option<String>,
+}
+
+/// set the handlers for rejected promises of the current worker thread
+pub(crate) fn set_rejection_handlers(handlers: RejectionHandlers) {
+    REJECTION_HANDLERS.with(|handlers_rc| {
+        handlers_rc.replace(handlers);
+    });
+}
+
+/// the tracker only records the rejection, it may be handled before the microtask queue is empty
 unsafe extern "C" fn promise_rejection_tracker(
     cx: *mut JSContext,
     _muted_errors: bool,
-    _promise: mozjs::jsapi::HandleObject,
-    _state: mozjs::jsapi::PromiseRejectionHandlingState,
+    promise: mozjs::jsapi::HandleObject,
+    state: PromiseRejectionHandlingState,
     _data: *mut c_void,
 ) {
-    capture_stack!(in (cx) let stack);
-    let str_stack = stack
-        .unwrap()
-        .as_string(None, StackFormat::SpiderMonkey)
-        .unwrap();
-
-    log::error!(
-        "promise without rejection handler was rejected from:\n{}",
-        str_stack
-    );
+    wrap_panic(&mut || {
+        let unhandled = matches!(state, PromiseRejectionHandlingState::Unhandled);
+        if !unhandled {
+            // a rejection which was handled before it was reported is not reported at all
+            let was_pending = PENDING_REJECTIONS.with(|pending_rc| {
+                let pending = &mut *pending_rc.borrow_mut();
+                let len = pending.len();
+                pending.retain(|rejection| {
+                    !(rejection.unhandled && rejection.promise.get() == promise.get())
+                });
+                pending.len() != len
+            });
+            if was_pending {
+                return;
+            }
+        }
+
+        let has_handlers = REJECTION_HANDLERS.with(|handlers_rc| {
+            let handlers = &*handlers_rc.borrow();
+            handlers.unhandled.is_some() || handlers.handled.is_some()
+        });
+        if !unhandled && !has_handlers {
+            return;
+        }
+
+        let stack = if unhandled && !has_handlers {
+            capture_stack!(in (cx) let stack);
+            stack.and_then(|stack| stack.as_string(None, StackFormat::SpiderMonkey))
+        } else {
+            None
+        };
+
+        PENDING_REJECTIONS.with(|pending_rc| {
+            pending_rc.borrow_mut().push(PendingRejection {
+                promise: EsPersistentRooted::new_from_obj(cx, promise.get()),
+                unhandled,
+                stack,
+            });
+        });
+        if !REJECTION_REPORT_SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+            EventLoop::add_local_void(report_rejections);
+        }
+    });
+}
+
+/// report the pending rejections once all promise jobs have run
+fn report_rejections() {
+    if has_pending_promise_jobs() {
+        EventLoop::add_local_void(report_rejections);
+        return;
+    }
+    REJECTION_REPORT_SCHEDULED.with(|scheduled| scheduled.set(false));
+
+    let pending = PENDING_REJECTIONS.with(|pending_rc| pending_rc.replace(vec![]));
+    if pending.is_empty() {
+        return;
+    }
+    let handlers = REJECTION_HANDLERS.with(|handlers_rc| handlers_rc.borrow().clone());
+
+    SM_RT.with(|sm_rt_rc| {
+        let sm_rt = &*sm_rt_rc.borrow();
+        sm_rt.do_with_jsapi(|_rt, cx, _global| {
+            for rejection in pending {
+                let handler_opt = if rejection.unhandled {
+                    handlers.unhandled.clone()
+                } else {
+                    handlers.handled.clone()
+                };
+                if let Some(handler) = handler_opt {
+                    rooted!(in (cx) let promise_root = rejection.promise.get());
+                    rooted!(in (cx) let reason_root = get_promise_result_raw(promise_root.handle().into()));
+                    // the reason is converted lazily, see EsErrorInfo::do_with_thrown_value()
+                    let err = error_info_from_thrown_value(cx, reason_root.handle());
+                    wrap_panic(&mut || handler(err.clone()));
+                } else if let Some(stack) = rejection.stack {
+                    log::error!(
+                        "promise without rejection handler was rejected from:\n{}",
+                        stack
+                    );
+                }
+            }
+        });
+    });
 }
@@ -83,12 +83,12 @@ pub fn execute_script(
         return if let Some(err) = err_opt {
             Err(err)
         } else {
-            Err(EsErrorInfo {
-                message: "unknown error while executing script occured".to_string(),
-                filename: "execute_script".to_string(),
-                lineno: 0,
-                column: 0,
-            })
+            Err(EsErrorInfo::new(
+                "unknown error while executing script occured".to_string(),
+                "execute_script".to_string(),
+                0,
+                0,
+            ))
         };
     }
 
@@ -16,7 +16,7 @@ use mozjs::rust::{HandleObject, MutableHandleObject};
 // but hey, it was good practice and it's nice to see i came to pretty much the same solution for a problem
 
 use crate::jsapi_utils::arrays;
-use crate::jsapi_utils::EsErrorInfo;
+use crate::jsapi_utils::{get_pending_exception_or_generic_err, EsErrorInfo};
 
 macro_rules! typed_array {
     (
@@ -67,10 +67,27 @@ macro_rules! typed_array {
                 cx: *mut JSContext,
                 ret: MutableHandleObject,
                 vec: Vec<$rust_type>,
+            ) -> Result<(), EsErrorInfo> {
+                $struct_ident::new_instance_from_slice(cx, ret, vec.as_slice())
+            }
+            /// create a new instance and copy the contents of a slice to it
+            pub fn new_instance_from_slice(
+                cx: *mut JSContext,
+                ret: MutableHandleObject,
+                vec: &[$rust_type],
             ) -> Result<(), EsErrorInfo> {
                 trace!("new_typed_array_from_vec / 1");
 
                 $struct_ident::new_instance(cx, ret, vec.len());
+                if ret.get().is_null() {
+                    return Err(get_pending_exception_or_generic_err(
+                        cx,
+                        "could not create typed array",
+                    ));
+                }
+                if vec.is_empty() {
+                    return Ok(());
+                }
 
                 let mut len: usize = 0;
                 let mut data = std::ptr::null_mut();
@@ -112,6 +129,9 @@ macro_rules! typed_array {
                 };
                 trace!("to_vec / 2");
                 let ulen = len as usize;
+                if ulen == 0 {
+                    return Ok(vec![]);
+                }
                 // copy data first
                 let mut vec = Vec::new();
                 trace!("to_vec / 3");
@@ -151,6 +171,16 @@ typed_array!(
     u8,
     u32
 );
+typed_array!(
+    Uint8ClampedArray,
+    JS_IsUint8ClampedArray,
+    JS_NewUint8ClampedArray,
+    GetUint8ClampedArrayLengthAndData,
+    set_array_element_u32,
+    to_int32,
+    u8,
+    u32
+);
 typed_array!(
     Int16Array,
     JS_IsInt16Array,
@@ -212,12 +242,82 @@ typed_array!(
     f64
 );
 
+/// check if an object is any kind of typed array
+pub fn is_typed_array(obj: *mut JSObject) -> bool {
+    unsafe { mozjs::jsapi::JS_IsTypedArrayObject(obj) }
+}
+
+pub struct ArrayBuffer {}
+
+impl ArrayBuffer {
+    pub fn is_instance(obj: *mut JSObject) -> bool {
+        unsafe { mozjs::jsapi::IsArrayBufferObject(obj) }
+    }
+    pub fn new_instance(cx: *mut JSContext, ret: MutableHandleObject, len: usize) {
+        let mut ret = ret;
+        ret.set(unsafe { mozjs::jsapi::NewArrayBuffer(cx, len) });
+    }
+    /// create a new ArrayBuffer and copy the contents of a slice to it
+    pub fn new_instance_from_slice(
+        cx: *mut JSContext,
+        ret: MutableHandleObject,
+        bytes: &[u8],
+    ) -> Result<(), EsErrorInfo> {
+        ArrayBuffer::new_instance(cx, ret, bytes.len());
+        if ret.get().is_null() {
+            return Err(get_pending_exception_or_generic_err(
+                cx,
+                "could not create ArrayBuffer",
+            ));
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut len: usize = 0;
+        let mut data = std::ptr::null_mut();
+        let mut is_shared_mem = false;
+        unsafe {
+            mozjs::jsapi::GetArrayBufferLengthAndData(
+                ret.get(),
+                &mut len,
+                &mut is_shared_mem,
+                &mut data,
+            );
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, len);
+        };
+        Ok(())
+    }
+    pub fn convert_to_vec(_cx: *mut JSContext, buf: HandleObject) -> Result<Vec<u8>, EsErrorInfo> {
+        let mut len: usize = 0;
+        let mut data = std::ptr::null_mut();
+        let mut is_shared_mem = false;
+        unsafe {
+            mozjs::jsapi::GetArrayBufferLengthAndData(
+                buf.get(),
+                &mut len,
+                &mut is_shared_mem,
+                &mut data,
+            );
+        };
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let mut vec = Vec::with_capacity(len);
+        unsafe {
+            std::ptr::copy_nonoverlapping(data, vec.as_mut_ptr(), len);
+            vec.set_len(len);
+        };
+        Ok(vec)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::esruntime::tests::init_test_runtime;
     use crate::jsapi_utils::arrays::{get_array_element, get_array_length, set_array_element_i32};
     use crate::jsapi_utils::objects::NULL_JSOBJECT;
-    use crate::jsapi_utils::typed_arrays::Int8Array;
+    use crate::jsapi_utils::typed_arrays::{ArrayBuffer, Float64Array, Int8Array};
     use crate::spidermonkeyruntimewrapper::SmRuntime;
     use log::trace;
     use mozjs::jsval::UndefinedValue;
@@ -288,4 +388,32 @@ pub mod tests {
             });
         });
     }
+
+    #[test]
+    fn test_array_buffer_conversion() {
+        let rt = init_test_runtime();
+        rt.do_in_es_event_queue_sync(|sm_rt: &SmRuntime| {
+            sm_rt.do_with_jsapi(|_rt, cx, _global| {
+                let bytes: Vec<u8> = vec![1, 2, 3, 255];
+
+                rooted!(in (cx) let mut buf_obj_root = NULL_JSOBJECT);
+                ArrayBuffer::new_instance_from_slice(cx, buf_obj_root.handle_mut(), &bytes)
+                    .expect("new_instance_from_slice failed");
+
+                assert!(ArrayBuffer::is_instance(buf_obj_root.get()));
+                assert!(!Int8Array::is_instance(buf_obj_root.get()));
+
+                let converted_vec = ArrayBuffer::convert_to_vec(cx, buf_obj_root.handle())
+                    .expect("could not convert to vec");
+                assert_eq!(converted_vec, bytes);
+
+                rooted!(in (cx) let mut empty_obj_root = NULL_JSOBJECT);
+                Float64Array::new_instance_from_slice(cx, empty_obj_root.handle_mut(), &[])
+                    .expect("new_instance_from_slice failed");
+                let converted_vec = Float64Array::convert_to_vec(cx, empty_obj_root.handle())
+                    .expect("could not convert to vec");
+                assert!(converted_vec.is_empty());
+            });
+        });
+    }
 }
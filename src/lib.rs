@@ -104,6 +104,10 @@ extern crate log;
 extern crate lazy_static;
 
 mod es_sys_scripts;
+pub mod esimportmap;
+pub mod esmodule;
+pub mod esmoduleloader;
+pub mod esnativemodule;
 #[macro_use]
 
 pub mod esreflection;
@@ -1,6 +1,10 @@
+use crate::esmodule::EsModule;
+use crate::esruntime::UncaughtExceptionHandler;
 use crate::esruntimeinner::EsRuntimeInner;
 use crate::esvaluefacade::EsValueFacade;
 use crate::jsapi_utils;
+use crate::jsapi_utils::modules::{ModuleEvaluation, ModuleRequest};
+use crate::jsapi_utils::objects::NULL_JSOBJECT;
 use crate::jsapi_utils::rooting::EsPersistentRooted;
 use crate::jsapi_utils::EsErrorInfo;
 use hirofa_utils::auto_id_map::AutoIdMap;
@@ -16,6 +20,7 @@ use mozjs::jsapi::NewArrayObject;
 use mozjs::jsapi::OnNewGlobalHookOption;
 use mozjs::jsapi::SetJobQueue;
 use mozjs::jsapi::JS::HandleValueArray;
+use mozjs::jsapi::{JS_AddInterruptCallback, JS_RequestInterruptCallback};
 use mozjs::jsval::{ObjectValue, UndefinedValue};
 use mozjs::panic::wrap_panic;
 use mozjs::rust::wrappers::JS_CallFunctionValue;
@@ -23,13 +28,16 @@ use mozjs::rust::Runtime;
 use mozjs::rust::SIMPLE_GLOBAL_CLASS;
 use mozjs::rust::{HandleObject, JSEngine};
 use mozjs::rust::{JSEngineHandle, RealmOptions};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::os::raw::c_void;
 use std::ptr;
 use std::rc::Rc;
 use std::str;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
 lazy_static! {
     static ref ENGINE_HANDLE_PRODUCER: EventLoop = EventLoop::new();
@@ -60,6 +68,229 @@ thread_local! {
     /// this only exists for the worker thread of the MicroTaskManager
     pub(crate) static SM_RT: RefCell<SmRuntime> = RefCell::new(SmRuntime::new());
     static GLOBAL_OPS: RefCell<HashMap<&'static str, Box<GlobalOp>>> = RefCell::new(HashMap::new());
+    /// the InterruptState of the EsRuntimeInner which owns this thread, used by the interrupt callback
+    static INTERRUPT_STATE: RefCell<Option<Arc<InterruptState>>> = RefCell::new(None);
+    /// the number of nested do_with_jsapi calls, a job is running while this is not 0
+    static JSAPI_DEPTH: Cell<usize> = Cell::new(0);
+    /// the number of promise jobs which were enqueued but did not run yet
+    static PENDING_PROMISE_JOBS: Cell<usize> = Cell::new(0);
+    /// the handler for exceptions which were not caught by a script, e.g. in a Promise reaction or a timer
+    static UNCAUGHT_EXCEPTION_HANDLER: RefCell<Option<Arc<UncaughtExceptionHandler>>> = RefCell::new(None);
+}
+
+/// returns true if there are promise jobs which were enqueued in this thread and did not run yet
+pub(crate) fn has_pending_promise_jobs() -> bool {
+    PENDING_PROMISE_JOBS.with(|pending| pending.get() > 0)
+}
+
+/// report an exception which could not be returned to the caller of a job, e.g. an exception thrown
+/// in a Promise reaction or a timer, if no handler was set with
+/// EsRuntimeBuilder::on_uncaught_exception() the error is logged
+pub(crate) fn report_uncaught_exception(err: EsErrorInfo) {
+    let handler_opt = UNCAUGHT_EXCEPTION_HANDLER.with(|handler_rc| handler_rc.borrow().clone());
+    if let Some(handler) = handler_opt {
+        handler(err);
+    } else {
+        log::error!("uncaught exception: {}", err);
+    }
+}
+
+/// how long the watchdog thread sleeps when there is no deadline before it checks if the runtime was dropped
+const WATCHDOG_IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct InterruptStateInner {
+    pending: Option<&'static str>,
+    terminated: Option<&'static str>,
+    /// true while the worker thread is running a job (see do_with_jsapi)
+    running: bool,
+    /// the id and expiry time of the deadline of the job which is currently running
+    active_deadline: Option<(usize, Instant)>,
+    deadline_counter: usize,
+    watchdog_started: bool,
+}
+
+/// the InterruptState is used to terminate a running script from another thread
+/// it is shared between the EsRuntimeInner and the worker thread of the runtime
+#[derive(Default)]
+pub(crate) struct InterruptState {
+    inner: Mutex<InterruptStateInner>,
+    /// notifies the watchdog thread when a deadline was started or ended
+    deadline_changed: Condvar,
+    context: AtomicPtr<JSContext>,
+}
+
+impl InterruptState {
+    /// request the running script to be terminated, reason will end up in the EsErrorInfo as "terminated: {reason}"
+    /// if no job is running the request is ignored
+    pub(crate) fn request(&self, reason: &'static str) {
+        let inner = &mut *self.inner.lock().unwrap();
+        if !inner.running {
+            trace!("ignoring interrupt request, no job is running");
+            return;
+        }
+        inner.pending = Some(reason);
+        let cx = self.context.load(Ordering::SeqCst);
+        if !cx.is_null() {
+            // this is the one jsapi function which may be called from any thread
+            unsafe { JS_RequestInterruptCallback(cx) };
+        }
+    }
+
+    /// called when the worker thread starts running a job
+    fn start_job(&self) {
+        let inner = &mut *self.inner.lock().unwrap();
+        inner.running = true;
+        inner.pending = None;
+        inner.terminated = None;
+    }
+
+    /// called when the worker thread is done running a job, interrupts which did not reach the job are forgotten
+    fn end_job(&self) {
+        let inner = &mut *self.inner.lock().unwrap();
+        inner.running = false;
+        inner.pending = None;
+    }
+
+    /// start a deadline for the job which is currently running in the worker thread
+    /// deadlines are watched by a single watchdog thread which is started when the first deadline is started
+    fn start_deadline(self: &Arc<Self>, timeout: Duration) -> usize {
+        let inner = &mut *self.inner.lock().unwrap();
+        inner.deadline_counter += 1;
+        inner.active_deadline = Some((inner.deadline_counter, Instant::now() + timeout));
+        if !inner.watchdog_started {
+            inner.watchdog_started = true;
+            let weak_state = Arc::downgrade(self);
+            thread::spawn(move || run_watchdog(weak_state));
+        }
+        self.deadline_changed.notify_all();
+        inner.deadline_counter
+    }
+
+    fn end_deadline(&self, deadline_id: usize) {
+        let inner = &mut *self.inner.lock().unwrap();
+        if matches!(inner.active_deadline, Some((id, _)) if id == deadline_id) {
+            inner.active_deadline = None;
+            self.deadline_changed.notify_all();
+        }
+    }
+
+    fn take_pending(&self) -> bool {
+        let inner = &mut *self.inner.lock().unwrap();
+        if let Some(reason) = inner.pending.take() {
+            inner.terminated = Some(reason);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_terminated(&self) -> Option<&'static str> {
+        self.inner.lock().unwrap().terminated.take()
+    }
+}
+
+/// called by spidermonkey when an interrupt was requested, returning false terminates the running script
+unsafe extern "C" fn interrupt_callback(_cx: *mut JSContext) -> bool {
+    let terminate = INTERRUPT_STATE.with(|state_rc| {
+        if let Some(state) = &*state_rc.borrow() {
+            state.take_pending()
+        } else {
+            false
+        }
+    });
+    if terminate {
+        debug!("terminating running script");
+    }
+    !terminate
+}
+
+/// terminates the running script when its deadline expires, stops when the InterruptState is dropped
+fn run_watchdog(weak_state: Weak<InterruptState>) {
+    while let Some(state) = weak_state.upgrade() {
+        let inner = state.inner.lock().unwrap();
+        let active_deadline = inner.active_deadline;
+        let expired = match active_deadline {
+            Some((deadline_id, expires)) => {
+                let now = Instant::now();
+                if now >= expires {
+                    drop(inner);
+                    Some(deadline_id)
+                } else {
+                    let _ = state.deadline_changed.wait_timeout(inner, expires - now);
+                    None
+                }
+            }
+            None => {
+                let _ = state
+                    .deadline_changed
+                    .wait_timeout(inner, WATCHDOG_IDLE_INTERVAL);
+                None
+            }
+        };
+        if let Some(deadline_id) = expired {
+            state.end_deadline(deadline_id);
+            debug!("deadline {} expired, terminating script", deadline_id);
+            state.request("timeout");
+        }
+    }
+    trace!("watchdog stopped");
+}
+
+/// marks the worker thread as running a job for as long as it lives, see do_with_jsapi
+struct RunningJobGuard {}
+
+impl RunningJobGuard {
+    fn new() -> Self {
+        if JSAPI_DEPTH.with(|depth| depth.replace(depth.get() + 1)) == 0 {
+            INTERRUPT_STATE.with(|state_rc| {
+                if let Some(state) = &*state_rc.borrow() {
+                    state.start_job();
+                }
+            });
+        }
+        RunningJobGuard {}
+    }
+}
+
+impl Drop for RunningJobGuard {
+    fn drop(&mut self) {
+        if JSAPI_DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get()
+        }) == 0
+        {
+            INTERRUPT_STATE.with(|state_rc| {
+                if let Some(state) = &*state_rc.borrow() {
+                    state.end_job();
+                }
+            });
+        }
+    }
+}
+
+/// replace the error of a script which was terminated by an interrupt with a "terminated: {reason}" error
+fn check_terminated<R>(res: Result<R, EsErrorInfo>) -> Result<R, EsErrorInfo> {
+    match res {
+        Err(err) => {
+            let terminated = INTERRUPT_STATE.with(|state_rc| {
+                if let Some(state) = &*state_rc.borrow() {
+                    state.take_terminated()
+                } else {
+                    None
+                }
+            });
+            if let Some(reason) = terminated {
+                Err(EsErrorInfo {
+                    message: format!("terminated: {}", reason),
+                    ..err
+                })
+            } else {
+                Err(err)
+            }
+        }
+        ok => ok,
+    }
 }
 
 impl SmRuntime {
@@ -78,6 +309,40 @@ impl SmRuntime {
         })
     }
 
+    /// set the InterruptState of the EsRuntimeInner which owns this SmRuntime
+    pub(crate) fn init_interrupt_state(&self, state: Arc<InterruptState>) {
+        self.do_with_jsapi(|_rt, cx, _global| {
+            state.context.store(cx, Ordering::SeqCst);
+        });
+        INTERRUPT_STATE.with(|state_rc| {
+            state_rc.replace(Some(state));
+        });
+    }
+
+    /// set the handler for uncaught exceptions of the worker thread of this runtime
+    pub(crate) fn init_uncaught_exception_handler(&self, handler: Arc<UncaughtExceptionHandler>) {
+        UNCAUGHT_EXCEPTION_HANDLER.with(|handler_rc| {
+            handler_rc.replace(Some(handler));
+        });
+    }
+
+    /// run a job with a deadline, if the job has not completed when the timeout expires the running
+    /// script is terminated with a "terminated: timeout" error
+    pub fn with_deadline<R, J>(&self, timeout: Duration, job: J) -> Result<R, EsErrorInfo>
+    where
+        J: FnOnce(&SmRuntime) -> Result<R, EsErrorInfo>,
+    {
+        let state_opt = INTERRUPT_STATE.with(|state_rc| state_rc.borrow().clone());
+        if let Some(state) = state_opt {
+            let deadline_id = state.start_deadline(timeout);
+            let res = job(self);
+            state.end_deadline(deadline_id);
+            check_terminated(res)
+        } else {
+            job(self)
+        }
+    }
+
     /// add a function to the global object
     /// this function will be callable from javascript just by using func_name();
     /// # Example
@@ -127,7 +392,9 @@ impl SmRuntime {
 
         let context = runtime.cx();
         let h_option = OnNewGlobalHookOption::FireOnNewGlobalHook;
-        let c_option = RealmOptions::default();
+        let mut c_option = RealmOptions::default();
+        // ModuleEvaluate returns a Promise for every module when top level await is enabled
+        c_option.creationOptions_.topLevelAwait_ = true;
 
         let global_obj;
 
@@ -149,6 +416,7 @@ impl SmRuntime {
 
         ret.init_promise_callbacks();
         ret.init_import_callbacks();
+        ret.init_interrupt_callback();
 
         ret
     }
@@ -164,6 +432,13 @@ impl SmRuntime {
         });
     }
 
+    fn init_interrupt_callback(&self) {
+        // this is used to terminate running scripts, see InterruptState
+        self.do_with_jsapi(|_rt, cx, _global| unsafe {
+            assert!(JS_AddInterruptCallback(cx, Some(interrupt_callback)));
+        });
+    }
+
     fn init_import_callbacks(&mut self) {
         // this tells the runtime how to resolve modules
         self.do_with_jsapi(|rt, _cx, _global| {
@@ -182,29 +457,154 @@ impl SmRuntime {
         self.do_with_jsapi(|rt, _cx, global| {
             trace!("smrt.call {} in thread {}", func_name, thread_id::get());
 
-            self.call_obj_method_name(rt, global, obj_names, func_name, arguments)
+            check_terminated(self.call_obj_method_name(rt, global, obj_names, func_name, arguments))
         })
     }
 
     /// load and execute a script module
+    /// when the module uses top level await it may still be running when this returns, when it imports modules
+    /// which are not cached yet it is only run after those were loaded in the helper threads
     pub fn load_module(&self, module_src: &str, module_file_name: &str) -> Result<(), EsErrorInfo> {
+        self.load_module_with_evaluation(module_src, module_file_name)
+            .map(|_evaluation| ())
+    }
+
+    /// load and execute a script module, when the module uses top level await and is still running
+    /// a ModuleEvaluation is returned which receives the result when the module is done
+    /// when the module imports modules which are not cached yet those are fetched in the module fetcher thread and
+    /// the module is evaluated after that, a ModuleEvaluation is returned in that case too
+    pub(crate) fn load_module_with_evaluation(
+        &self,
+        module_src: &str,
+        module_file_name: &str,
+    ) -> Result<Option<ModuleEvaluation>, EsErrorInfo> {
+        let requests = self.prepare_module(module_src, module_file_name)?;
+        if requests.is_empty() {
+            return self.evaluate_module(module_file_name);
+        }
+        Ok(Some(
+            jsapi_utils::modules::fetch_and_evaluate_module_in_fetcher(
+                self.clone_esrt_inner(),
+                module_file_name.to_string(),
+                requests,
+            ),
+        ))
+    }
+
+    /// compile a script module and add it to the module cache without running it
+    /// returns the static imports of the module which are not cached yet so they can be fetched before the module
+    /// is evaluated with evaluate_module()
+    pub(crate) fn prepare_module(
+        &self,
+        module_src: &str,
+        module_file_name: &str,
+    ) -> Result<Vec<ModuleRequest>, EsErrorInfo> {
+        trace!(
+            "smrt.prepare_module {} in thread {}",
+            module_file_name,
+            thread_id::get()
+        );
+
+        let key = self.get_module_cache_key(module_file_name);
+        self.do_with_jsapi(|_rt, cx, _global| {
+            let prepare_res = jsapi_utils::modules::compile_and_cache_module(
+                cx,
+                key.as_str(),
+                module_file_name,
+                module_src,
+            );
+            check_terminated(prepare_res)
+        })
+    }
+
+    /// run a script module which was compiled with prepare_module(), when the module uses top level await and
+    /// is still running a ModuleEvaluation is returned which receives the result when the module is done
+    pub(crate) fn evaluate_module(
+        &self,
+        module_file_name: &str,
+    ) -> Result<Option<ModuleEvaluation>, EsErrorInfo> {
         trace!(
-            "smrt.load_module {} in thread {}",
+            "smrt.evaluate_module {} in thread {}",
             module_file_name,
             thread_id::get()
         );
 
+        let key = self.get_module_cache_key(module_file_name);
         self.do_with_jsapi(|_rt, cx, _global| {
-            let load_res = jsapi_utils::modules::compile_module(cx, module_src, module_file_name);
+            rooted!(in (cx) let mut evaluation_promise_root = UndefinedValue());
+            let evaluate_res = jsapi_utils::modules::evaluate_cached_module(
+                cx,
+                key.as_str(),
+                evaluation_promise_root.handle_mut(),
+            )
+            .and_then(|_| {
+                jsapi_utils::modules::get_module_evaluation(cx, evaluation_promise_root.handle())
+            });
 
-            if let Some(err) = load_res.err() {
-                return Err(err);
+            if evaluate_res.is_err() {
+                // don't keep a module which failed in the cache
+                jsapi_utils::modules::invalidate_module(key.as_str());
             }
+            check_terminated(evaluate_res)
+        })
+    }
+
+    /// load a module and return an EsModule handle which can be used to access its exports
+    /// this fails for modules which import modules which are not cached yet and for modules which use top level
+    /// await and are still running after being evaluated, use EsRuntime::load_module_async() for those
+    pub fn load_module_with_exports(
+        &self,
+        module_src: &str,
+        module_file_name: &str,
+    ) -> Result<EsModule, EsErrorInfo> {
+        let requests = self.prepare_module(module_src, module_file_name)?;
+        if !requests.is_empty() {
+            // we can't wait for the module fetcher in this thread
+            jsapi_utils::modules::invalidate_module(module_file_name);
+            return Err(EsErrorInfo::new(
+                format!(
+                    "module {} imports modules which are not loaded yet",
+                    module_file_name
+                ),
+                module_file_name.to_string(),
+                0,
+                0,
+            ));
+        }
+        if self.evaluate_module(module_file_name)?.is_some() {
+            return Err(EsErrorInfo::new(
+                format!("module {} is still running", module_file_name),
+                module_file_name.to_string(),
+                0,
+                0,
+            ));
+        }
+        self.get_module_exports(module_file_name)
+    }
 
-            Ok(())
+    /// get an EsModule handle for a module which was loaded with load_module()
+    pub(crate) fn get_module_exports(
+        &self,
+        module_file_name: &str,
+    ) -> Result<EsModule, EsErrorInfo> {
+        let key = self.get_module_cache_key(module_file_name);
+        self.do_with_jsapi(|_rt, cx, _global| {
+            rooted!(in (cx) let mut ns_root = NULL_JSOBJECT);
+            jsapi_utils::modules::get_module_namespace(cx, key.as_str(), ns_root.handle_mut())?;
+            Ok(EsModule::new(cx, module_file_name, ns_root.get()))
         })
     }
 
+    /// get the key a module which was loaded with load_module() is cached by, this is the path the module code
+    /// loader resolves its file name to so the module can also be imported by that path
+    fn get_module_cache_key(&self, module_file_name: &str) -> String {
+        jsapi_utils::modules::get_module_cache_key(
+            &self.clone_esrt_inner(),
+            module_file_name,
+            module_file_name,
+        )
+    }
+
     /// eval a piece of script and return the result as a EsValueFacade
     // todo, this should not return an EsValueFacade, refactor to rval
     pub fn eval(&self, eval_code: &str, file_name: &str) -> Result<EsValueFacade, EsErrorInfo> {
@@ -216,9 +616,9 @@ impl SmRuntime {
                 jsapi_utils::eval(rt, global, eval_code, file_name, rval.handle_mut());
 
             if eval_res.is_ok() {
-                Ok(EsValueFacade::new_v(cx, rval.handle()))
+                EsValueFacade::try_new_v(cx, rval.handle())
             } else {
-                Err(eval_res.err().unwrap())
+                check_terminated(Err(eval_res.err().unwrap()))
             }
         })
     }
@@ -239,7 +639,7 @@ impl SmRuntime {
             if eval_res.is_ok() {
                 Ok(())
             } else {
-                Err(eval_res.err().unwrap())
+                check_terminated(Err(eval_res.err().unwrap()))
             }
         })
     }
@@ -309,7 +709,7 @@ impl SmRuntime {
                 );
 
             if res2.is_ok() {
-                Ok(EsValueFacade::new_v(context, rval.handle()))
+                EsValueFacade::try_new_v(context, rval.handle())
             } else {
                 Err(res2.err().unwrap())
             }
@@ -337,7 +737,7 @@ impl SmRuntime {
             );
 
             if res2.is_ok() {
-                Ok(EsValueFacade::new_v(context, rval.handle()))
+                EsValueFacade::try_new_v(context, rval.handle())
             } else {
                 Err(res2.err().unwrap())
             }
@@ -357,6 +757,8 @@ impl SmRuntime {
 
         rooted!(in (cx) let global_root = global);
 
+        let _running_job_guard = RunningJobGuard::new();
+
         let ret;
         {
             trace!("do_with_jsapi _ac");
@@ -489,7 +891,10 @@ unsafe extern "C" fn enqueue_promise_job(
 
         let cb = PromiseJobCallback::new(cx, job.get());
 
+        PENDING_PROMISE_JOBS.with(|pending| pending.set(pending.get() + 1));
+
         let task = move || {
+            PENDING_PROMISE_JOBS.with(|pending| pending.set(pending.get() - 1));
             SM_RT.with(move |rc| {
                 trace!("running a job");
 
@@ -501,12 +906,11 @@ unsafe extern "C" fn enqueue_promise_job(
                     trace!("checking cb.call res");
                     if call_res.is_err() {
                         debug!("job failed");
-                        if let Some(err) = jsapi_utils::get_pending_exception(cx) {
-                            panic!(
-                                "job failed {}:{}:{} -> {}",
-                                err.filename, err.lineno, err.column, err.message
-                            );
-                        }
+                        // without a pending exception the job was terminated (e.g. by an interrupt)
+                        let err = jsapi_utils::get_pending_exception(cx).unwrap_or_else(|| {
+                            EsErrorInfo::new("promise job failed".to_string(), "".to_string(), 0, 0)
+                        });
+                        report_uncaught_exception(err);
                     }
                 });
                 trace!("job ran ok");
@@ -629,12 +1033,15 @@ impl CallbackFunction {
 #[cfg(test)]
 mod tests {
     use crate::esruntime::tests::init_test_runtime;
+    use crate::esruntimebuilder::EsRuntimeBuilder;
     use crate::esvaluefacade::EsValueFacade;
     use crate::jsapi_utils;
     use crate::jsapi_utils::EsErrorInfo;
     use crate::spidermonkeyruntimewrapper::{do_with_rooted_esvf_vec, SmRuntime};
     use log::trace;
     use mozjs::jsval::UndefinedValue;
+    use std::sync::Mutex;
+    use std::time::Duration;
 
     #[test]
     fn test_call_method_name() {
@@ -677,6 +1084,34 @@ mod tests {
         assert_eq!(res, "abc_true_123".to_string());
     }
 
+    #[test]
+    fn test_uncaught_exception_in_job() {
+        log::info!("test: test_uncaught_exception_in_job");
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = Mutex::new(tx);
+        let rt = EsRuntimeBuilder::new()
+            .on_uncaught_exception(Box::new(move |err| {
+                tx.lock().unwrap().send(err.message).unwrap();
+            }))
+            .build();
+        rt.eval_sync(
+            "setImmediate(() => {throw new Error('poof');}); true;",
+            "test_uncaught_exception_in_job.es",
+        )
+        .expect("script failed");
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5))
+                .expect("no uncaught exception was reported"),
+            "poof"
+        );
+
+        // the runtime should still work
+        let esvf = rt
+            .eval_sync("1 + 2;", "test_uncaught_exception_in_job2.es")
+            .expect("script failed");
+        assert_eq!(esvf.get_i32(), 3);
+    }
+
     fn _test_import() {
         log::info!("test: test_import");
         let rt = init_test_runtime();